@@ -0,0 +1,142 @@
+/*
+ * Copyright (c) 2021, Andrei Yaskovets
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+// compares the union-find behind the environment with the chains of bindings it replaced,
+// run with `cargo run --release --example unification`
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use typesystems::*;
+
+trait Bindings {
+    fn bind(&mut self, id: Id, level: Level, t: Type);
+    fn lookup(&self, id: Id, level: Level) -> Option<&Type>;
+}
+
+// every binding is kept by the variable and the level it had, lookups walk to the end of the chain
+#[derive(Default)]
+struct Chains {
+    bound: HashMap<(Id, Level), Type>,
+}
+
+impl Bindings for Chains {
+    fn bind(&mut self, id: Id, level: Level, t: Type) {
+        self.bound.insert((id, level), t);
+    }
+
+    fn lookup(&self, mut id: Id, mut level: Level) -> Option<&Type> {
+        let mut prev = None;
+        while let Some(next) = self.bound.get(&(id, level)) {
+            prev = Some(next);
+            match next {
+                Type::TypeVar(id2, level2) => {
+                    id = *id2;
+                    level = *level2;
+                }
+                _ => break,
+            }
+        }
+        prev
+    }
+}
+
+impl Bindings for UnionFind<Type> {
+    fn bind(&mut self, id: Id, level: Level, t: Type) {
+        UnionFind::bind(self, id, level, t);
+    }
+
+    fn lookup(&self, id: Id, level: Level) -> Option<&Type> {
+        UnionFind::lookup(self, id, level)
+    }
+}
+
+// each variable is unified with the next one and the first is resolved after every step,
+// as the chain of lets `let x1 = x0 in let x2 = x1 in ...` does
+fn chains(bindings: &mut impl Bindings, n: usize) -> usize {
+    let mut resolved = 0;
+    for id in 1..n {
+        bindings.bind(id, 1, Type::TypeVar(id + 1, 1));
+        resolved += usize::from(bindings.lookup(1, 1).is_some());
+    }
+    bindings.bind(n, 1, Type::Const(String::from("int")));
+    resolved
+        + (1..=n)
+            .filter(|id| bindings.lookup(*id, 1).is_some())
+            .count()
+}
+
+// each variable escapes one let at a time down to the top level,
+// and is then resolved from the level it was created at
+fn levels(bindings: &mut impl Bindings, n: usize, depth: Level) -> usize {
+    for id in 1..=n {
+        for level in (1..=depth).rev() {
+            bindings.bind(id, level, Type::TypeVar(id, level - 1));
+        }
+    }
+    (1..=n)
+        .filter(|id| bindings.lookup(*id, depth).is_some())
+        .count()
+}
+
+fn time<B: Bindings>(new: impl Fn() -> B, run: impl Fn(&mut B) -> usize) -> (usize, Duration) {
+    let mut bindings = new();
+    let start = Instant::now();
+    let resolved = run(&mut bindings);
+    (resolved, start.elapsed())
+}
+
+fn compare(
+    name: &str,
+    run_chains: impl Fn(&mut Chains) -> usize,
+    run: impl Fn(&mut UnionFind<Type>) -> usize,
+) {
+    let (resolved1, chains) = time(Chains::default, run_chains);
+    let (resolved2, union_find) = time(UnionFind::new, run);
+    assert_eq!(resolved1, resolved2);
+    println!(
+        "{:<16} chains {:>10.3?}  union-find {:>10.3?}",
+        name, chains, union_find
+    );
+}
+
+// the inference of a term with the given number of nested lets, each applying the previous one
+fn nested_lets(n: usize) -> Duration {
+    let mut code = String::from(r"let x0 = \y -> y in ");
+    for i in 1..n {
+        code.push_str(&format!(r"let x{} = \y -> x{}(y) in ", i, i - 1));
+    }
+    code.push_str(&format!("x{}", n - 1));
+
+    let term = Term::parse(&code).unwrap();
+    let start = Instant::now();
+    infer(&term, &mut Env::new(), &mut Gen::new()).unwrap();
+    start.elapsed()
+}
+
+fn main() {
+    for n in [1000, 2000, 4000] {
+        compare(
+            &format!("chains {}", n),
+            |bindings| chains(bindings, n),
+            |bindings| chains(bindings, n),
+        );
+    }
+    for depth in [10, 100, 1000] {
+        compare(
+            &format!("levels {}", depth),
+            |bindings| levels(bindings, 1000, depth),
+            |bindings| levels(bindings, 1000, depth),
+        );
+    }
+    for n in [250, 500, 1000] {
+        println!(
+            "{:<16} inference {:>10.3?}",
+            format!("lets {}", n),
+            nested_lets(n)
+        );
+    }
+}
@@ -5,11 +5,23 @@
  * LICENSE file in the root directory of this source tree.
  */
 
+mod operator;
+pub use operator::*;
+
 mod term;
 pub use term::*;
 
+mod pattern;
+pub use pattern::*;
+
+mod exhaustive;
+pub use exhaustive::*;
+
 mod ty;
 pub use ty::*;
 
 mod infer;
 pub use infer::*;
+
+mod decl;
+pub use decl::*;
@@ -0,0 +1,374 @@
+/*
+ * Copyright (c) 2021, Andrei Yaskovets
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+use std::rc::Rc;
+
+use tokenstream::{bind, fmap, spanned, Parser, Spanned};
+
+use crate::{
+    braces, brackets, comma_list1, context, eof, ident, let_binding, many_space, parens, parse,
+    predicate, rec_bindings, spaced, term, token, ty, Operators, ParseError, Term, Token, Ty,
+};
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum Decl {
+    Let(String, Spanned<Term>),
+    LetRec(Vec<(String, Spanned<Term>)>),
+    Type(String, Vec<String>, Vec<(String, Vec<Spanned<Ty>>)>),
+    Class(String, String, Vec<(String, Spanned<Ty>)>),
+    // the methods are left to the backend if there are none
+    Instance(
+        Vec<(String, Spanned<Ty>)>,
+        String,
+        Spanned<Ty>,
+        Vec<(String, Spanned<Term>)>,
+    ),
+}
+
+impl Decl {
+    pub fn parse(s: &str) -> Result<Spanned<Decl>, ParseError> {
+        Decl::parse_with(s, &Operators::default())
+    }
+
+    pub fn parse_with(s: &str, ops: &Operators) -> Result<Spanned<Decl>, ParseError> {
+        parse(decl(Rc::new(ops.clone())) << eof(), s)
+    }
+}
+
+impl std::str::FromStr for Decl {
+    type Err = ParseError;
+    fn from_str(s: &str) -> std::result::Result<Self, <Self as std::str::FromStr>::Err> {
+        Decl::parse(s).map(|decl| decl.node)
+    }
+}
+
+impl std::fmt::Display for Decl {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            Decl::Let(name, assign) => {
+                write!(fmt, "let {} = {}", name, assign)
+            }
+            Decl::LetRec(bindings) => {
+                write!(fmt, "let rec ")?;
+                for (i, (name, assign)) in bindings.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, " and ")?;
+                    }
+                    write!(fmt, "{} = {}", name, assign)?;
+                }
+                Ok(())
+            }
+            Decl::Type(name, params, constructors) => {
+                write!(fmt, "type {}", name)?;
+                if !params.is_empty() {
+                    write!(fmt, "[{}]", params.join(", "))?;
+                }
+                write!(fmt, " =")?;
+                for (i, (constructor, args)) in constructors.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, " |")?;
+                    }
+                    write!(fmt, " {}", constructor)?;
+                    if !args.is_empty() {
+                        write!(fmt, "({}", args[0])?;
+                        for arg in args.iter().skip(1) {
+                            write!(fmt, ", {}", arg)?;
+                        }
+                        write!(fmt, ")")?;
+                    }
+                }
+                Ok(())
+            }
+            Decl::Class(name, param, methods) => {
+                write!(fmt, "class {}[{}] {{ ", name, param)?;
+                for (i, (method, ty)) in methods.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, ", ")?;
+                    }
+                    write!(fmt, "{} : {}", method, ty)?;
+                }
+                write!(fmt, " }}")
+            }
+            Decl::Instance(context, class, head, methods) => {
+                write!(fmt, "instance ")?;
+                let context: Vec<String> = context
+                    .iter()
+                    .map(|(class, ty)| format!("{}[{}]", class, ty))
+                    .collect();
+                match context.len() {
+                    0 => {}
+                    1 => write!(fmt, "{} => ", context[0])?,
+                    _ => write!(fmt, "({}) => ", context.join(", "))?,
+                }
+                write!(fmt, "{}[{}]", class, head)?;
+                if !methods.is_empty() {
+                    write!(fmt, " {{ ")?;
+                    for (i, (method, assign)) in methods.iter().enumerate() {
+                        if i > 0 {
+                            write!(fmt, ", ")?;
+                        }
+                        write!(fmt, "{} = {}", method, assign)?;
+                    }
+                    write!(fmt, " }}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn decl(ops: Rc<Operators>) -> Parser<'static, Spanned<Token>, Spanned<Decl>> {
+    let p_let = fmap(
+        |(name, assign)| Decl::Let(name, assign),
+        let_binding(ops.clone()),
+    );
+    let p_let_rec = fmap(Decl::LetRec, rec_bindings(ops.clone()));
+
+    let p_params = brackets(spaced(comma_list1(spaced(ident())))) << many_space();
+    let p_constructor = bind(ident(), |name| {
+        let _name = name.clone();
+        fmap(
+            move |args| (name.clone(), args),
+            parens(spaced(comma_list1(spaced(ty())))),
+        ) | Parser::pure((_name, Vec::new()))
+    });
+    let p_type = bind(token(Token::Type) >> spaced(ident()), move |name| {
+        let p_constructor = p_constructor.clone();
+        bind(
+            (p_params.clone() | Parser::pure(Vec::new())) << token(Token::Equals),
+            move |params| {
+                let name = name.clone();
+                fmap(
+                    move |constructors| Decl::Type(name.clone(), params.clone(), constructors),
+                    spaced(p_constructor.clone().sep_by1(spaced(token(Token::Pipe)))),
+                )
+            },
+        )
+    });
+
+    let p_method = bind(spaced(ident()) << token(Token::Colon), |method| {
+        fmap(move |ty| (method.clone(), ty), spaced(ty()))
+    });
+    let p_class = bind(token(Token::Class) >> spaced(ident()), move |name| {
+        let p_method = p_method.clone();
+        bind(brackets(spaced(ident())) << many_space(), move |param| {
+            let name = name.clone();
+            fmap(
+                move |methods| Decl::Class(name.clone(), param.clone(), methods),
+                braces(comma_list1(p_method.clone())),
+            )
+        })
+    });
+    let p_binding = bind(spaced(ident()) << token(Token::Equals), move |method| {
+        fmap(
+            move |assign| (method.clone(), assign),
+            spaced(term(ops.clone())),
+        )
+    });
+    let p_methods = braces(comma_list1(p_binding)) | Parser::pure(Vec::new());
+    let p_instance = token(Token::Instance)
+        >> spaced(bind(context() | Parser::pure(Vec::new()), move |context| {
+            let p_methods = p_methods.clone();
+            bind(predicate() << many_space(), move |(class, head)| {
+                let context = context.clone();
+                fmap(
+                    move |methods| {
+                        Decl::Instance(context.clone(), class.clone(), head.clone(), methods)
+                    },
+                    p_methods.clone(),
+                )
+            })
+        }));
+
+    spanned(p_let | p_let_rec | p_type | p_class | p_instance).label("declaration")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Decl, Decl::*};
+    use crate::Term::{Abs, App, Var};
+    use crate::Ty;
+
+    use std::str::FromStr;
+
+    fn collect(s: &str) -> Option<Decl> {
+        Decl::from_str(s).ok()
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(collect(""), None);
+        assert_eq!(collect("x"), None);
+        assert_eq!(collect("let x ="), None);
+        assert_eq!(collect("let x = y in x"), None);
+    }
+
+    #[test]
+    fn let_decl() {
+        assert_eq!(
+            collect(r"let id = \x -> x"),
+            Some(Let(
+                String::from("id"),
+                Abs(
+                    vec![String::from("x")],
+                    Box::new(Var(String::from("x")).into())
+                )
+                .into()
+            ))
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                collect(r"let id : forall[a] a -> a = \x -> x").unwrap()
+            ),
+            String::from(r"let id = (\x -> x : forall[a] a -> a)")
+        );
+        assert_eq!(
+            format!("{}", collect(r"let id = \x -> x").unwrap()),
+            String::from(r"let id = \x -> x")
+        );
+    }
+
+    #[test]
+    fn type_decl() {
+        assert_eq!(
+            collect("type list[a] = nil | cons(a, list[a])"),
+            Some(Type(
+                String::from("list"),
+                vec![String::from("a")],
+                vec![
+                    (String::from("nil"), vec![]),
+                    (
+                        String::from("cons"),
+                        vec![
+                            Ty::Const(String::from("a")).into(),
+                            Ty::App(
+                                Box::new(Ty::Const(String::from("list")).into()),
+                                vec![Ty::Const(String::from("a")).into()]
+                            )
+                            .into()
+                        ]
+                    )
+                ]
+            ))
+        );
+        assert_eq!(
+            format!("{}", collect("type  color =red|green | blue").unwrap()),
+            String::from("type color = red | green | blue")
+        );
+        assert_eq!(
+            format!("{}", collect("type f [a , b]= f(a -> b)").unwrap()),
+            String::from("type f[a, b] = f(a -> b)")
+        );
+        assert_eq!(collect("type t ="), None);
+        assert_eq!(collect("type t[] = a"), None);
+        assert_eq!(collect("type t = a |"), None);
+        assert_eq!(collect("type t = a()"), None);
+    }
+
+    #[test]
+    fn let_rec_decl() {
+        assert_eq!(
+            collect("let rec f = g and g = f(x)"),
+            Some(LetRec(vec![
+                (String::from("f"), Var(String::from("g")).into()),
+                (
+                    String::from("g"),
+                    App(
+                        Box::new(Var(String::from("f")).into()),
+                        vec![Var(String::from("x")).into()]
+                    )
+                    .into()
+                )
+            ]))
+        );
+        assert_eq!(
+            format!("{}", collect("let rec f = g and g = f").unwrap()),
+            String::from("let rec f = g and g = f")
+        );
+        assert_eq!(collect("let rec f = g in f"), None);
+    }
+
+    #[test]
+    fn class_decl() {
+        assert_eq!(
+            collect("class Eq[a] { eq : (a, a) -> bool }"),
+            Some(Class(
+                String::from("Eq"),
+                String::from("a"),
+                vec![(
+                    String::from("eq"),
+                    Ty::Arrow(
+                        vec![
+                            Ty::Const(String::from("a")).into(),
+                            Ty::Const(String::from("a")).into()
+                        ],
+                        Box::new(Ty::Const(String::from("bool")).into())
+                    )
+                    .into()
+                )]
+            ))
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                collect("class Show [a]{show:a -> string ,  shows : list[a] -> string}").unwrap()
+            ),
+            String::from("class Show[a] { show : a -> string, shows : list[a] -> string }")
+        );
+        assert_eq!(collect("class Eq[a] {}"), None);
+        assert_eq!(collect("class Eq[a, b] { eq : (a, b) -> bool }"), None);
+    }
+
+    #[test]
+    fn instance_decl() {
+        assert_eq!(
+            collect("instance Eq[a] => Eq[list[a]]"),
+            Some(Instance(
+                vec![(String::from("Eq"), Ty::Const(String::from("a")).into())],
+                String::from("Eq"),
+                Ty::App(
+                    Box::new(Ty::Const(String::from("list")).into()),
+                    vec![Ty::Const(String::from("a")).into()]
+                )
+                .into(),
+                vec![]
+            ))
+        );
+        assert_eq!(
+            format!("{}", collect("instance  Eq[ int ]").unwrap()),
+            String::from("instance Eq[int]")
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                collect("instance (Eq[a],Eq[b])=>Eq[pair[a, b]]").unwrap()
+            ),
+            String::from("instance (Eq[a], Eq[b]) => Eq[pair[a, b]]")
+        );
+        assert_eq!(
+            collect("instance Eq[bool] { eq = f }"),
+            Some(Instance(
+                vec![],
+                String::from("Eq"),
+                Ty::Const(String::from("bool")).into(),
+                vec![(String::from("eq"), Var(String::from("f")).into())]
+            ))
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                collect("instance Show[a]=>Show[list[a]]{show=f,shows = \\xs -> g(xs)}").unwrap()
+            ),
+            String::from("instance Show[a] => Show[list[a]] { show = f, shows = \\xs -> g(xs) }")
+        );
+        assert_eq!(collect("instance Eq[a] =>"), None);
+        assert_eq!(collect("instance Eq"), None);
+        assert_eq!(collect("instance Eq[int] {}"), None);
+        assert_eq!(collect("instance Eq[int] { eq }"), None);
+    }
+}
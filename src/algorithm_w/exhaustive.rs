@@ -0,0 +1,238 @@
+/*
+ * Copyright (c) 2021, Andrei Yaskovets
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+use tokenstream::Spanned;
+
+use crate::{Literal, Pattern};
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum Constructor {
+    Named(String),
+    Lit(Literal),
+    Tuple(usize),
+    Variant(String),
+}
+
+// patterns with the variables erased and the constructors resolved
+#[derive(PartialEq, Clone, Debug)]
+pub enum Pat {
+    Any,
+    Con(Constructor, Vec<Pat>),
+}
+
+impl From<Pat> for Pattern {
+    fn from(pat: Pat) -> Self {
+        match pat {
+            Pat::Any => Pattern::Wildcard,
+            Pat::Con(Constructor::Lit(literal), _) => Pattern::Lit(literal),
+            Pat::Con(Constructor::Named(name), args) => Pattern::Constructor(
+                name,
+                args.into_iter()
+                    .map(|arg| Spanned::from(Pattern::from(arg)))
+                    .collect(),
+            ),
+            // the payload of a variant is left out unless it narrows the pattern
+            Pat::Con(Constructor::Variant(label), mut payload) => match payload.pop() {
+                None | Some(Pat::Any) => Pattern::Variant(label, None),
+                Some(payload) => {
+                    Pattern::Variant(label, Some(Box::new(Pattern::from(payload).into())))
+                }
+            },
+            Pat::Con(Constructor::Tuple(_), items) => Pattern::Tuple(
+                items
+                    .into_iter()
+                    .map(|item| Spanned::from(Pattern::from(item)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+// every constructor of the type the given one belongs to with its arity, None if there are infinitely many
+pub type Signature<'a> = dyn Fn(&Constructor) -> Option<Vec<(Constructor, usize)>> + 'a;
+
+fn specialize(row: &[Pat], constructor: &Constructor, arity: usize) -> Option<Vec<Pat>> {
+    let mut specialized = match &row[0] {
+        Pat::Any => vec![Pat::Any; arity],
+        Pat::Con(head, args) if head == constructor => args.clone(),
+        Pat::Con(_, _) => return None,
+    };
+    specialized.extend_from_slice(&row[1..]);
+    Some(specialized)
+}
+
+fn specialize_all(rows: &[Vec<Pat>], constructor: &Constructor, arity: usize) -> Vec<Vec<Pat>> {
+    rows.iter()
+        .filter_map(|row| specialize(row, constructor, arity))
+        .collect()
+}
+
+fn default(rows: &[Vec<Pat>]) -> Vec<Vec<Pat>> {
+    rows.iter()
+        .filter(|row| row[0] == Pat::Any)
+        .map(|row| row[1..].to_vec())
+        .collect()
+}
+
+fn heads(rows: &[Vec<Pat>]) -> Vec<&Constructor> {
+    let mut heads = Vec::new();
+    for row in rows {
+        if let Pat::Con(head, _) = &row[0] {
+            if !heads.contains(&head) {
+                heads.push(head);
+            }
+        }
+    }
+    heads
+}
+
+// the whole signature if the first column mentions every constructor of it
+fn complete(heads: &[&Constructor], signature: &Signature) -> Option<Vec<(Constructor, usize)>> {
+    let all = signature(heads.first()?)?;
+    all.iter()
+        .all(|(constructor, _)| heads.contains(&constructor))
+        .then_some(all)
+}
+
+// whether some value matched by the row is not matched by any of the rows before it
+pub fn useful(rows: &[Vec<Pat>], row: &[Pat], signature: &Signature) -> bool {
+    let Some((head, tail)) = row.split_first() else {
+        return rows.is_empty();
+    };
+
+    match head {
+        Pat::Con(constructor, args) => useful(
+            &specialize_all(rows, constructor, args.len()),
+            &specialize(row, constructor, args.len()).unwrap_or_default(),
+            signature,
+        ),
+        Pat::Any => match complete(&heads(rows), signature) {
+            Some(all) => all.iter().any(|(constructor, arity)| {
+                useful(
+                    &specialize_all(rows, constructor, *arity),
+                    &specialize(row, constructor, *arity).unwrap_or_default(),
+                    signature,
+                )
+            }),
+            None => useful(&default(rows), tail, signature),
+        },
+    }
+}
+
+// a row of n patterns matching values that none of the rows match
+pub fn missing(rows: &[Vec<Pat>], n: usize, signature: &Signature) -> Option<Vec<Pat>> {
+    if n == 0 {
+        return rows.is_empty().then(Vec::new);
+    }
+
+    let heads = heads(rows);
+    if let Some(all) = complete(&heads, signature) {
+        return all.into_iter().find_map(|(constructor, arity)| {
+            let specialized = specialize_all(rows, &constructor, arity);
+            let mut args = missing(&specialized, arity + n - 1, signature)?;
+            let tail = args.split_off(arity);
+            let mut witness = vec![Pat::Con(constructor, args)];
+            witness.extend(tail);
+            Some(witness)
+        });
+    }
+
+    let mut witness = missing(&default(rows), n - 1, signature)?;
+    let head = heads
+        .first()
+        .and_then(|head| signature(head))
+        .and_then(|all| {
+            all.into_iter()
+                .find(|(constructor, _)| !heads.contains(&constructor))
+        })
+        .map_or(Pat::Any, |(constructor, arity)| {
+            Pat::Con(constructor, vec![Pat::Any; arity])
+        });
+    witness.insert(0, head);
+    Some(witness)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{missing, useful, Constructor, Pat, Pat::*};
+    use crate::{Literal, Pattern};
+
+    fn named(name: &str, args: Vec<Pat>) -> Pat {
+        Con(Constructor::Named(String::from(name)), args)
+    }
+
+    fn int(int: i64) -> Pat {
+        Con(Constructor::Lit(Literal::Int(int)), Vec::new())
+    }
+
+    fn signature(constructor: &Constructor) -> Option<Vec<(Constructor, usize)>> {
+        let named = |name: &str| Constructor::Named(String::from(name));
+        match constructor {
+            Constructor::Named(name) if name == "nil" || name == "cons" => {
+                Some(vec![(named("nil"), 0), (named("cons"), 2)])
+            }
+            Constructor::Named(name) if name == "none" || name == "some" => {
+                Some(vec![(named("none"), 0), (named("some"), 1)])
+            }
+            Constructor::Tuple(n) => Some(vec![(Constructor::Tuple(*n), *n)]),
+            _ => None,
+        }
+    }
+
+    fn witness(rows: &[Pat]) -> Option<String> {
+        let rows: Vec<Vec<Pat>> = rows.iter().map(|row| vec![row.clone()]).collect();
+        missing(&rows, 1, &signature)
+            .map(|mut witness| Pattern::from(witness.remove(0)).to_string())
+    }
+
+    #[test]
+    fn exhaustiveness() {
+        let nil = || named("nil", vec![]);
+        let cons = |x, xs| named("cons", vec![x, xs]);
+        let some = |x| named("some", vec![x]);
+
+        assert_eq!(witness(&[nil(), cons(Any, Any)]), None);
+        assert_eq!(witness(&[Any]), None);
+        assert_eq!(witness(&[]), Some(String::from("_")));
+        assert_eq!(witness(&[nil()]), Some(String::from("cons(_, _)")));
+        assert_eq!(witness(&[cons(Any, Any)]), Some(String::from("nil")));
+        assert_eq!(
+            witness(&[nil(), cons(Any, nil())]),
+            Some(String::from("cons(_, cons(_, _))"))
+        );
+        assert_eq!(
+            witness(&[nil(), cons(some(Any), Any)]),
+            Some(String::from("cons(none, _)"))
+        );
+        assert_eq!(witness(&[int(0), int(1)]), Some(String::from("_")));
+        assert_eq!(witness(&[int(0), Any]), None);
+
+        let tuple = |items: Vec<Pat>| Con(Constructor::Tuple(items.len()), items);
+        assert_eq!(
+            witness(&[tuple(vec![nil(), Any]), tuple(vec![Any, nil()])]),
+            Some(String::from("(cons(_, _), cons(_, _))"))
+        );
+        assert_eq!(
+            witness(&[tuple(vec![Any, int(1)]), tuple(vec![nil(), Any])]),
+            Some(String::from("(cons(_, _), _)"))
+        );
+    }
+
+    #[test]
+    fn redundancy() {
+        let nil = || vec![named("nil", vec![])];
+        let cons = |x, xs| vec![named("cons", vec![x, xs])];
+
+        assert!(useful(&[], &[Any], &signature));
+        assert!(useful(&[nil()], &cons(Any, Any), &signature));
+        assert!(!useful(&[nil(), cons(Any, Any)], &[Any], &signature));
+        assert!(!useful(&[vec![Any]], &nil(), &signature));
+        assert!(!useful(&[cons(Any, Any)], &cons(int(1), Any), &signature));
+        assert!(useful(&[vec![int(1)]], &[int(2)], &signature));
+        assert!(!useful(&[vec![int(1)]], &[int(1)], &signature));
+    }
+}
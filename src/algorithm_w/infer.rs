@@ -7,57 +7,172 @@
 
 use std::collections::{HashMap, HashSet};
 
-use crate::{Env, Gen, Id, Level, Term, Type};
+use tokenstream::{Span, Spanned};
 
-pub fn unify(t1: &Type, t2: &Type, env: &mut Env<Type>) -> Result<Type, String> {
-    struct Unify<'a> {
+use crate::{
+    arity, first_order, kinds, missing, useful, Class, Constructor, Context, DataType, Decl, Env,
+    Error, Gen, Id, Instance, Level, Literal, Pat, Pattern, SystemF, Term, Ty, Type, TypeError,
+};
+
+pub fn unify(
+    t1: &Type,
+    t2: &Type,
+    env: &mut Env<Type>,
+    gen: &mut Gen<Type>,
+) -> Result<Type, TypeError> {
+    struct Unify<'a, 'b> {
         env: &'a mut Env<Type>,
+        gen: &'b mut Gen<Type>,
     }
 
-    impl<'a> Unify<'a> {
-        pub fn new(env: &'a mut Env<Type>) -> Self {
-            Unify { env }
+    impl<'a, 'b> Unify<'a, 'b> {
+        pub fn new(env: &'a mut Env<Type>, gen: &'b mut Gen<Type>) -> Self {
+            Unify { env, gen }
         }
 
-        fn update_bound_levels(&mut self, id: Id, level: Level, t: &Type) -> Result<(), String> {
+        // the free variables of the type are lowered to the level of the variable bound to it
+        fn update_levels(&mut self, level: Level, t: &Type) {
             match t {
-                Type::TypeVar(id2, level2) => {
-                    if id == *id2 {
-                        Err(format!("Infinite type"))
+                Type::TypeVar(id, level2) => {
+                    if let Some(binding) = self.env.lookup_binding(*id, *level2) {
+                        let binding = binding.clone();
+                        self.update_levels(level, &binding);
                     } else if *level2 > level {
-                        self.env.bind(*id2, *level2, Type::TypeVar(*id2, level));
-                        Ok(())
-                    } else {
-                        Ok(())
+                        self.env.bind(*id, *level2, Type::TypeVar(*id, level));
                     }
                 }
-                Type::Const(_) => Ok(()),
-                Type::Generic(_) => unreachable!(),
+                Type::Const(_) | Type::Skolem(_, _) | Type::RowEmpty | Type::Error => {}
+                // schemes are instantiated before they are unified
+                Type::Generic(_) | Type::Qualified(_, _) => unreachable!(),
                 Type::App(t, params) => {
-                    self.update_bound_levels(id, level, t)?;
-                    for param in params {
-                        self.update_bound_levels(id, level, param)?;
-                    }
-                    Ok(())
+                    self.update_levels(level, t);
+                    params
+                        .iter()
+                        .for_each(|param| self.update_levels(level, param));
+                }
+                Type::Arrow(init, tail) => {
+                    init.iter()
+                        .for_each(|param| self.update_levels(level, param));
+                    self.update_levels(level, tail);
+                }
+                Type::Tuple(items) => items
+                    .iter()
+                    .for_each(|item| self.update_levels(level, item)),
+                Type::Record(row) | Type::Variant(row) => self.update_levels(level, row),
+                Type::RowExtend(_, field, rest) => {
+                    self.update_levels(level, field);
+                    self.update_levels(level, rest);
+                }
+            }
+        }
+
+        // the unbound variable a row ends with, unless the label is found before it
+        fn row_tail(&self, row: &Type, label: &str) -> Option<Id> {
+            match row {
+                Type::RowExtend(label2, _, _) if label2 == label => None,
+                Type::RowExtend(_, _, rest) => self.row_tail(rest, label),
+                Type::TypeVar(id, level) => match self.env.lookup_binding(*id, *level) {
+                    Some(binding) => self.row_tail(binding, label),
+                    None => Some(*id),
+                },
+                _ => None,
+            }
+        }
+
+        // whether the variable occurs in the type through the bound variables
+        fn occurs_in(&self, id: Id, t: &Type) -> bool {
+            match t {
+                Type::TypeVar(id2, level2) => {
+                    id == *id2
+                        || self
+                            .env
+                            .lookup_binding(*id2, *level2)
+                            .is_some_and(|binding| self.occurs_in(id, binding))
+                }
+                Type::Const(_)
+                | Type::Skolem(_, _)
+                | Type::Generic(_)
+                | Type::RowEmpty
+                | Type::Error => false,
+                Type::App(t, params) => {
+                    self.occurs_in(id, t) || params.iter().any(|t| self.occurs_in(id, t))
                 }
                 Type::Arrow(init, tail) => {
-                    for param in init {
-                        self.update_bound_levels(id, level, param)?;
+                    init.iter().any(|t| self.occurs_in(id, t)) || self.occurs_in(id, tail)
+                }
+                Type::Tuple(items) => items.iter().any(|t| self.occurs_in(id, t)),
+                Type::Record(row) | Type::Variant(row) => self.occurs_in(id, row),
+                Type::RowExtend(_, field, rest) => {
+                    self.occurs_in(id, field) || self.occurs_in(id, rest)
+                }
+                Type::Qualified(context, t) => {
+                    context.iter().any(|(_, t)| self.occurs_in(id, t)) || self.occurs_in(id, t)
+                }
+            }
+        }
+
+        // the rest of the row after the label, whose field is unified with the given one
+        fn rewrite_row(
+            &mut self,
+            row: &Type,
+            label: &str,
+            field: &Type,
+        ) -> Result<Type, TypeError> {
+            match row {
+                Type::RowEmpty => Err(TypeError::MissingLabel(label.to_owned())),
+                Type::RowExtend(label2, field2, rest) if label2 == label => {
+                    self.unify(field, field2)?;
+                    Ok(*rest.clone())
+                }
+                Type::RowExtend(label2, field2, rest) => Ok(Type::RowExtend(
+                    label2.clone(),
+                    field2.clone(),
+                    Box::new(self.rewrite_row(rest, label, field)?),
+                )),
+                Type::TypeVar(id, level) => {
+                    if let Some(binding) = self.env.lookup_binding(*id, *level) {
+                        let binding = binding.clone();
+                        return self.rewrite_row(&binding, label, field);
+                    }
+
+                    let rest = self.gen.newvar(Some(*level));
+                    let row = Type::RowExtend(
+                        label.to_owned(),
+                        Box::new(field.clone()),
+                        Box::new(rest.clone()),
+                    );
+                    if self.occurs_in(*id, field) {
+                        return Err(TypeError::InfiniteType { var: *id, ty: row });
                     }
-                    self.update_bound_levels(id, level, tail)
+                    self.env.bind(*id, *level, row);
+                    self.update_levels(*level, field);
+                    Ok(rest)
                 }
+                // a rigid or ill-kinded row cannot grow the label
+                _ => Err(TypeError::MissingLabel(label.to_owned())),
             }
         }
 
-        pub fn unify(&mut self, t1: &Type, t2: &Type) -> Result<Type, String> {
+        pub fn unify(&mut self, t1: &Type, t2: &Type) -> Result<Type, TypeError> {
             if *t1 == *t2 {
                 return Ok(t1.clone());
             }
 
             match (t1, t2) {
+                (Type::Error, t) | (t, Type::Error) => Ok(t.clone()),
                 (Type::Const(name1), Type::Const(name2)) if name1 == name2 => {
                     Ok(Type::Const(name1.to_string()))
                 }
+                (Type::App(_, params1), Type::App(_, params2))
+                | (Type::Arrow(params1, _), Type::Arrow(params2, _))
+                    if params1.len() != params2.len() =>
+                {
+                    Err(TypeError::UnequalArity {
+                        expected: t1.clone(),
+                        found: t2.clone(),
+                        arities: (params1.len(), params2.len()),
+                    })
+                }
                 (Type::App(t1, params1), Type::App(t2, params2)) => {
                     let t = self.unify(t1, t2)?;
                     let mut params = Vec::new();
@@ -73,27 +188,94 @@ pub fn unify(t1: &Type, t2: &Type, env: &mut Env<Type>) -> Result<Type, String>
                     }
                     Ok(Type::Arrow(init, Box::new(self.unify(tail1, tail2)?)))
                 }
+                (Type::Tuple(items1), Type::Tuple(items2)) if items1.len() == items2.len() => {
+                    let mut items = Vec::new();
+                    for (item1, item2) in items1.iter().zip(items2) {
+                        items.push(self.unify(item1, item2)?);
+                    }
+                    Ok(Type::Tuple(items))
+                }
+                (Type::Record(row1), Type::Record(row2)) => {
+                    Ok(Type::Record(Box::new(self.unify(row1, row2)?)))
+                }
+                (Type::Variant(row1), Type::Variant(row2)) => {
+                    Ok(Type::Variant(Box::new(self.unify(row1, row2)?)))
+                }
+                (Type::RowExtend(label, field, rest1), row2 @ Type::RowExtend(_, _, _)) => {
+                    // rewriting the second row would bind the tail of the first one to itself
+                    let tail = match **rest1 {
+                        Type::TypeVar(id, level)
+                            if self.env.lookup_binding(id, level).is_none() =>
+                        {
+                            Some(id)
+                        }
+                        _ => None,
+                    };
+                    if let Some(id) = tail.filter(|id| self.row_tail(row2, label) == Some(*id)) {
+                        return Err(TypeError::InfiniteType {
+                            var: id,
+                            ty: row2.clone(),
+                        });
+                    }
+                    let rest2 = self.rewrite_row(row2, label, field)?;
+                    let rest = self.unify(rest1, &rest2)?;
+                    Ok(Type::RowExtend(
+                        label.clone(),
+                        field.clone(),
+                        Box::new(rest),
+                    ))
+                }
+                (Type::RowExtend(label, _, _), Type::RowEmpty)
+                | (Type::RowEmpty, Type::RowExtend(label, _, _)) => {
+                    Err(TypeError::MissingLabel(label.clone()))
+                }
                 (Type::TypeVar(id1, _), Type::TypeVar(id2, _)) if id1 == id2 => {
-                    Err(format!("Multiple instances of variable"))
+                    Err(TypeError::MultipleInstances { var: *id1 })
                 }
                 (Type::TypeVar(id, level), t) | (t, Type::TypeVar(id, level)) => {
                     if let Some(binding) = self.env.lookup_binding(*id, *level) {
                         let binding = binding.clone();
                         self.unify(&binding, t)
+                    } else if let Some(binding) = match t {
+                        Type::TypeVar(id2, level2) => self.env.lookup_binding(*id2, *level2),
+                        _ => None,
+                    } {
+                        // never bind a variable to a chain leading back to itself
+                        let binding = binding.clone();
+                        self.unify(&Type::TypeVar(*id, *level), &binding)
+                    } else if self.occurs_in(*id, t) {
+                        // a recursive binding is never made, the substitution stays acyclic
+                        Err(TypeError::InfiniteType {
+                            var: *id,
+                            ty: t.clone(),
+                        })
                     } else {
                         self.env.bind(*id, *level, t.clone());
-                        self.update_bound_levels(*id, *level, t)?;
+                        self.update_levels(*level, t);
                         Ok(t.clone())
                     }
                 }
-                (t1, t2) => Err(format!("Cannot unify {} with {}", t1, t2)),
+                (t1, t2) => Err(TypeError::Mismatch {
+                    expected: t1.clone(),
+                    found: t2.clone(),
+                }),
             }
         }
     }
-    Unify::new(env).unify(t1, t2)
+    Unify::new(env, gen).unify(t1, t2)
 }
 
 pub fn instantiate(t: &Type, level: Level, gen: &mut Gen<Type>, env: &Env<Type>) -> Type {
+    instantiation(t, level, gen, env).0
+}
+
+// the instance of the scheme and the fresh variables its generic ones became
+fn instantiation(
+    t: &Type,
+    level: Level,
+    gen: &mut Gen<Type>,
+    env: &Env<Type>,
+) -> (Type, HashMap<Id, Type>) {
     struct Instantiate<'a, 'b> {
         instantiated: HashMap<Id, Type>,
         gen: &'a mut Gen<Type>,
@@ -118,7 +300,7 @@ pub fn instantiate(t: &Type, level: Level, gen: &mut Gen<Type>, env: &Env<Type>)
                         Type::TypeVar(*id, *level)
                     }
                 }
-                Type::Const(_) => t.clone(),
+                Type::Const(_) | Type::Skolem(_, _) | Type::Error => t.clone(),
                 Type::App(t, params) => Type::App(
                     Box::new(self.instantiate(t, level)),
                     params
@@ -132,8 +314,29 @@ pub fn instantiate(t: &Type, level: Level, gen: &mut Gen<Type>, env: &Env<Type>)
                         .collect(),
                     Box::new(self.instantiate(tail, level)),
                 ),
+                Type::Tuple(items) => Type::Tuple(
+                    items
+                        .iter()
+                        .map(|item| self.instantiate(item, level))
+                        .collect(),
+                ),
+                Type::Record(row) => Type::Record(Box::new(self.instantiate(row, level))),
+                Type::Variant(row) => Type::Variant(Box::new(self.instantiate(row, level))),
+                Type::Qualified(context, t) => Type::Qualified(
+                    context
+                        .iter()
+                        .map(|(class, t)| (class.clone(), self.instantiate(t, level)))
+                        .collect(),
+                    Box::new(self.instantiate(t, level)),
+                ),
+                Type::RowEmpty => Type::RowEmpty,
+                Type::RowExtend(label, field, rest) => Type::RowExtend(
+                    label.clone(),
+                    Box::new(self.instantiate(field, level)),
+                    Box::new(self.instantiate(rest, level)),
+                ),
                 Type::Generic(id) => {
-                    if let Some(t) = self.instantiated.get(&id) {
+                    if let Some(t) = self.instantiated.get(id) {
                         t.clone()
                     } else {
                         let var = self.gen.newvar(Some(level));
@@ -145,7 +348,9 @@ pub fn instantiate(t: &Type, level: Level, gen: &mut Gen<Type>, env: &Env<Type>)
         }
     }
 
-    Instantiate::new(gen, env).instantiate(t, level)
+    let mut instantiate = Instantiate::new(gen, env);
+    let t = instantiate.instantiate(t, level);
+    (t, instantiate.instantiated)
 }
 
 pub fn generalize(t: &Type, level: Level, env: &Env<Type>) -> Type {
@@ -169,7 +374,7 @@ pub fn generalize(t: &Type, level: Level, env: &Env<Type>) -> Type {
                         Type::TypeVar(*id, *level2)
                     }
                 }
-                Type::Const(_) | Type::Generic(_) => t.clone(),
+                Type::Const(_) | Type::Generic(_) | Type::Skolem(_, _) | Type::Error => t.clone(),
                 Type::App(t, params) => Type::App(
                     Box::new(self.generalize(t, level)),
                     params
@@ -183,6 +388,27 @@ pub fn generalize(t: &Type, level: Level, env: &Env<Type>) -> Type {
                         .collect(),
                     Box::new(self.generalize(tail, level)),
                 ),
+                Type::Tuple(items) => Type::Tuple(
+                    items
+                        .iter()
+                        .map(|item| self.generalize(item, level))
+                        .collect(),
+                ),
+                Type::Record(row) => Type::Record(Box::new(self.generalize(row, level))),
+                Type::Variant(row) => Type::Variant(Box::new(self.generalize(row, level))),
+                Type::Qualified(context, t) => Type::Qualified(
+                    context
+                        .iter()
+                        .map(|(class, t)| (class.clone(), self.generalize(t, level)))
+                        .collect(),
+                    Box::new(self.generalize(t, level)),
+                ),
+                Type::RowEmpty => Type::RowEmpty,
+                Type::RowExtend(label, field, rest) => Type::RowExtend(
+                    label.clone(),
+                    Box::new(self.generalize(field, level)),
+                    Box::new(self.generalize(rest, level)),
+                ),
             }
         }
     }
@@ -190,151 +416,2438 @@ pub fn generalize(t: &Type, level: Level, env: &Env<Type>) -> Type {
     Generalize::new(env).generalize(t, level)
 }
 
-pub fn infer(term: &Term, env: &Env<Type>, gen: &Gen<Type>) -> Result<Type, String> {
-    struct Infer {
-        env: Env<Type>,
-        gen: Gen<Type>,
+// whether the variable occurs in the type, whose bound variables are already substituted
+fn occurs(id: Id, t: &Type) -> bool {
+    match t {
+        Type::TypeVar(id2, _) | Type::Generic(id2) => id == *id2,
+        Type::Const(_) | Type::Skolem(_, _) | Type::RowEmpty | Type::Error => false,
+        Type::App(t, params) => occurs(id, t) || params.iter().any(|t| occurs(id, t)),
+        Type::Arrow(init, tail) => init.iter().any(|t| occurs(id, t)) || occurs(id, tail),
+        Type::Tuple(items) => items.iter().any(|t| occurs(id, t)),
+        Type::Record(row) | Type::Variant(row) => occurs(id, row),
+        Type::RowExtend(_, field, rest) => occurs(id, field) || occurs(id, rest),
+        Type::Qualified(context, t) => context.iter().any(|(_, t)| occurs(id, t)) || occurs(id, t),
     }
+}
 
-    impl Infer {
-        pub fn new(env: Env<Type>, gen: Gen<Type>) -> Self {
-            Infer { env, gen }
+// the generic variables of the scheme in the order they first occur, the context first
+fn generics(t: &Type) -> Vec<Id> {
+    fn collect(t: &Type, ids: &mut Vec<Id>) {
+        match t {
+            Type::Generic(id) => {
+                if !ids.contains(id) {
+                    ids.push(*id);
+                }
+            }
+            Type::Const(_)
+            | Type::TypeVar(_, _)
+            | Type::Skolem(_, _)
+            | Type::RowEmpty
+            | Type::Error => {}
+            Type::App(t, params) => {
+                collect(t, ids);
+                params.iter().for_each(|t| collect(t, ids));
+            }
+            Type::Arrow(init, tail) => {
+                init.iter().for_each(|t| collect(t, ids));
+                collect(tail, ids);
+            }
+            Type::Tuple(items) => items.iter().for_each(|t| collect(t, ids)),
+            Type::Record(row) | Type::Variant(row) => collect(row, ids),
+            Type::RowExtend(_, field, rest) => {
+                collect(field, ids);
+                collect(rest, ids);
+            }
+            Type::Qualified(context, t) => {
+                context.iter().for_each(|(_, t)| collect(t, ids));
+                collect(t, ids);
+            }
         }
+    }
 
-        fn genarrow(&mut self, t: Type, args_len: usize) -> Result<(Vec<Type>, Type), String> {
-            match t {
-                Type::Arrow(init, tail) => {
-                    if init.len() == args_len {
-                        Ok((init.clone(), *tail.clone()))
-                    } else {
-                        Err(format!(
-                            "Incorrect number of arguments. Must be {}",
-                            args_len
-                        ))
+    let mut ids = Vec::new();
+    collect(t, &mut ids);
+    ids
+}
+
+// binds the generic variables of the instance head so that it becomes the type
+fn matches(head: &Type, t: &Type, subst: &mut HashMap<Id, Type>) -> bool {
+    let all = |ts1: &[Type], ts2: &[Type], subst: &mut HashMap<Id, Type>| {
+        ts1.len() == ts2.len() && ts1.iter().zip(ts2).all(|(t1, t2)| matches(t1, t2, subst))
+    };
+    match (head, t) {
+        (Type::Generic(id), t) => subst.entry(*id).or_insert_with(|| t.clone()) == t,
+        (Type::App(head, params1), Type::App(t, params2)) => {
+            params1.len() == params2.len()
+                && matches(head, t, subst)
+                && all(params1, params2, subst)
+        }
+        (Type::Tuple(items1), Type::Tuple(items2)) => all(items1, items2, subst),
+        (Type::Arrow(init1, tail1), Type::Arrow(init2, tail2)) => {
+            all(init1, init2, subst) && matches(tail1, tail2, subst)
+        }
+        (Type::Record(row1), Type::Record(row2)) | (Type::Variant(row1), Type::Variant(row2)) => {
+            matches(row1, row2, subst)
+        }
+        (Type::RowExtend(label1, field1, rest1), Type::RowExtend(label2, field2, rest2)) => {
+            label1 == label2 && matches(field1, field2, subst) && matches(rest1, rest2, subst)
+        }
+        (Type::Qualified(context1, t1), Type::Qualified(context2, t2)) => {
+            context1.len() == context2.len()
+                && context1
+                    .iter()
+                    .zip(context2)
+                    .all(|((class1, t1), (class2, t2))| class1 == class2 && matches(t1, t2, subst))
+                && matches(t1, t2, subst)
+        }
+        (head, t) => head == t,
+    }
+}
+
+// the generic variables and the context of a recursive group, which its names are used at
+type Group = (Vec<Id>, Vec<(String, Type)>);
+
+// the scheme of a term with the context of its dictionaries
+type Generalized = (Type, Vec<(String, Type)>);
+
+// a term in the core language and in System F
+type Translation = (Spanned<Term>, Spanned<SystemF>);
+
+// a node of the inferred term, the terms built in code all have the same span so the nodes
+// are told apart by the ids they are given when they are built
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct Node(usize);
+
+impl Node {
+    fn of(term: &Spanned<Term>) -> Self {
+        Node(term.id())
+    }
+}
+
+struct Infer<'a> {
+    env: &'a mut Env<Type>,
+    gen: &'a mut Gen<Type>,
+    // the class constraints met so far, reduced when their variables are generalized
+    predicates: Vec<Spanned<(String, Type)>>,
+    // the constraints met at every overloaded use
+    evidence: HashMap<Node, Vec<(String, Type)>>,
+    // the context every let binding is generalized with, keyed by the bound term
+    generalized: HashMap<Node, Vec<(String, Type)>>,
+    // the context given by every annotation
+    given: HashMap<Node, Vec<(String, Type)>>,
+    // the types the generic variables of every use are instantiated at
+    instantiated: HashMap<Node, Vec<Type>>,
+    // the scheme of every let binding, keyed by the bound term
+    schemes: HashMap<Node, Type>,
+    // the types of the arguments of every abstraction
+    parameters: HashMap<Node, Vec<Type>>,
+    // the skolems of every polymorphic annotation in the order of its generic variables
+    rigid: HashMap<Node, Vec<Id>>,
+    // the variant type of every injection
+    injected: HashMap<Node, Type>,
+    // whether the failed terms are given the error type, with their errors kept in the diagnostics
+    recovering: bool,
+    diagnostics: Vec<Spanned<TypeError>>,
+}
+
+impl<'a> Infer<'a> {
+    pub fn new(env: &'a mut Env<Type>, gen: &'a mut Gen<Type>) -> Self {
+        Infer {
+            env,
+            gen,
+            predicates: Vec::new(),
+            evidence: HashMap::new(),
+            generalized: HashMap::new(),
+            given: HashMap::new(),
+            instantiated: HashMap::new(),
+            schemes: HashMap::new(),
+            parameters: HashMap::new(),
+            rigid: HashMap::new(),
+            injected: HashMap::new(),
+            recovering: false,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    // the type with the bound variables substituted and the free ones kept
+    fn resolve(&self, t: &Type) -> Type {
+        generalize(t, Level::MAX, self.env)
+    }
+
+    // the context of the scheme is collected as constraints met at the term,
+    // and the types its generic variables are instantiated at are kept in their order
+    fn instantiate_scheme(&mut self, t: &Type, level: Level, term: &Spanned<Term>) -> Type {
+        let span = term.span;
+        let (t_instance, instantiated) = instantiation(t, level, self.gen, self.env);
+        let types = generics(t)
+            .iter()
+            .map(|id| instantiated[id].clone())
+            .collect();
+        self.instantiated.insert(Node::of(term), types);
+        match t_instance {
+            Type::Qualified(context, t) => {
+                self.predicates.extend(
+                    context
+                        .iter()
+                        .map(|predicate| Spanned::new(predicate.clone(), span)),
+                );
+                self.evidence.insert(Node::of(term), context);
+                *t
+            }
+            t => t,
+        }
+    }
+
+    // the constraints the given one holds under by the instances, down to those on variables
+    fn reduce(&self, class: &str, t: &Type) -> Result<Vec<(String, Type)>, TypeError> {
+        let t = self.resolve(t);
+        if let Type::TypeVar(_, _) | Type::Skolem(_, _) = t {
+            return Ok(vec![(class.to_owned(), t)]);
+        }
+        // the error type is an instance of every class, its term was reported already
+        if t == Type::Error {
+            return Ok(Vec::new());
+        }
+
+        let mut subst = HashMap::new();
+        let Some(instance) = self.env.instances(class).iter().find(|instance| {
+            subst.clear();
+            matches(&instance.head, &t, &mut subst)
+        }) else {
+            return Err(TypeError::MissingInstance {
+                class: class.to_owned(),
+                ty: generalize(&t, -1, self.env),
+            });
+        };
+        let mut reduced = Vec::new();
+        for (class, param) in &instance.context {
+            let t_param = match param {
+                Type::Generic(id) => subst.get(id).unwrap_or(param),
+                param => param,
+            };
+            reduced.extend(self.reduce(class, t_param)?);
+        }
+        Ok(reduced)
+    }
+
+    // reduces the constraints met since the mark, those on the variables above the level
+    // are returned to qualify the generalized types and the others are left to the outer levels
+    fn retain(
+        &mut self,
+        since: usize,
+        level: Level,
+        ts: &[Type],
+    ) -> Result<Vec<(String, Type)>, Box<Spanned<TypeError>>> {
+        let ts: Vec<Type> = ts.iter().map(|t| self.resolve(t)).collect();
+        let mut retained = Vec::new();
+        let mut deferred: Vec<Spanned<(String, Type)>> = Vec::new();
+        for predicate in self.predicates.split_off(since) {
+            let (class, t_predicate) = &predicate.node;
+            let reduced = self
+                .reduce(class, t_predicate)
+                .map_err(|error| Box::new(Spanned::new(error, predicate.span)))?;
+            for reduced in reduced {
+                match reduced.1 {
+                    // a generalized variable that is not in every type can never be resolved
+                    Type::TypeVar(id, level2)
+                        if level2 > level && ts.iter().any(|t| !occurs(id, t)) =>
+                    {
+                        return Err(Box::new(Spanned::new(
+                            TypeError::Ambiguous { class: reduced.0 },
+                            predicate.span,
+                        )));
                     }
-                }
-                Type::TypeVar(id, level) => {
-                    if let Some(binding) = self.env.lookup_binding(id, level) {
-                        let binding = binding.clone();
-                        return self.genarrow(binding, args_len);
+                    Type::TypeVar(_, level2) if level2 > level => {
+                        if !retained.contains(&reduced) {
+                            retained.push(reduced);
+                        }
+                    }
+                    _ => {
+                        if !deferred.iter().any(|other| other.node == reduced) {
+                            deferred.push(Spanned::new(reduced, predicate.span));
+                        }
                     }
+                }
+            }
+        }
+        self.predicates.extend(deferred);
+        Ok(retained)
+    }
 
-                    let init: Vec<Type> = std::iter::repeat_with(|| self.gen.newvar(Some(level)))
-                        .take(args_len)
-                        .collect();
-                    let tail = self.gen.newvar(Some(level));
-                    self.env
-                        .bind(id, level, Type::Arrow(init.clone(), Box::new(tail.clone())));
-                    Ok((init, tail))
+    // the generalized type qualified with the retained constraints
+    fn scheme(&self, context: &[(String, Type)], t: &Type, level: Level) -> Type {
+        if context.is_empty() {
+            generalize(t, level, self.env)
+        } else {
+            let t = Type::Qualified(context.to_vec(), Box::new(t.clone()));
+            generalize(&t, level, self.env)
+        }
+    }
+
+    // the names are bound while the closure runs, the outer ones come back even if it fails
+    fn scoped<R>(
+        &mut self,
+        bound: &[(String, Type)],
+        f: impl FnOnce(&mut Self) -> Result<R, Box<Spanned<TypeError>>>,
+    ) -> Result<R, Box<Spanned<TypeError>>> {
+        self.env.push_scope();
+        for (name, t) in bound {
+            self.env.insert(name, t.clone());
+        }
+        let result = f(self);
+        self.env.pop_scope();
+        result
+    }
+
+    fn genarrow(&mut self, t: Type, args_len: usize) -> Result<(Vec<Type>, Type), TypeError> {
+        match t {
+            Type::Arrow(init, tail) => {
+                if init.len() == args_len {
+                    Ok((init.clone(), *tail.clone()))
+                } else {
+                    Err(TypeError::ArityMismatch {
+                        expected: init.len(),
+                        found: args_len,
+                    })
                 }
-                t_f @ _ => Err(format!("Invalid type of function: {}", t_f)),
             }
+            Type::Error => Ok((vec![Type::Error; args_len], Type::Error)),
+            Type::TypeVar(id, level) => {
+                if let Some(binding) = self.env.lookup_binding(id, level) {
+                    let binding = binding.clone();
+                    return self.genarrow(binding, args_len);
+                }
+
+                let init: Vec<Type> = std::iter::repeat_with(|| self.gen.newvar(Some(level)))
+                    .take(args_len)
+                    .collect();
+                let tail = self.gen.newvar(Some(level));
+                self.env
+                    .bind(id, level, Type::Arrow(init.clone(), Box::new(tail.clone())));
+                Ok((init, tail))
+            }
+            t_f => Err(TypeError::NotAFunction(t_f)),
+        }
+    }
+
+    // checks the term against the rigid type, where the constraints on the skolems must follow
+    // from the given context and the other ones are left to the outer levels
+    fn subsume(
+        &mut self,
+        term: &Spanned<Term>,
+        t_declared: &Type,
+        t_rigid: &Type,
+        given: &[(String, Type)],
+        skolems: &HashSet<Id>,
+        level: Level,
+    ) -> Result<(), Box<Spanned<TypeError>>> {
+        let since = self.predicates.len();
+        let t_term = self.infer(term, level)?;
+        let inferred = generalize(&t_term, -1, self.env);
+        if unify(t_rigid, &t_term, self.env, self.gen).is_err()
+            || self.env.iter().any(|(_, t)| self.mentions(t, skolems))
+        {
+            return Err(Box::new(Spanned::new(
+                TypeError::LessGeneral {
+                    declared: t_declared.clone(),
+                    inferred,
+                },
+                term.span,
+            )));
         }
 
-        pub fn infer(&mut self, term: &Term, level: Level) -> Result<Type, String> {
-            match term {
-                Term::Var(name) => {
-                    if let Some(t) = self.env.lookup(name) {
-                        Ok(instantiate(t, level, &mut self.gen, &self.env))
-                    } else {
-                        Err(format!("Undefined variable '{}'", name))
+        let mut wanted = Vec::new();
+        for predicate in self.predicates.split_off(since) {
+            let (class, t_predicate) = &predicate.node;
+            let reduced = self
+                .reduce(class, t_predicate)
+                .map_err(|error| Box::new(Spanned::new(error, predicate.span)))?;
+            for reduced in reduced {
+                match reduced.1 {
+                    Type::Skolem(id, _) if skolems.contains(&id) && !given.contains(&reduced) => {
+                        return Err(Box::new(Spanned::new(
+                            TypeError::MissingInstance {
+                                class: reduced.0,
+                                ty: reduced.1,
+                            },
+                            predicate.span,
+                        )));
                     }
+                    Type::Skolem(id, _) if skolems.contains(&id) => {}
+                    _ => wanted.push(Spanned::new(reduced, predicate.span)),
                 }
-                Term::Let(name, assign, body) => {
-                    let t_assign = generalize(&self.infer(assign, level + 1)?, level, &self.env);
+            }
+        }
+        self.predicates.extend(wanted);
+        Ok(())
+    }
 
-                    let shadowing = self.env.insert(name, t_assign);
-                    let t_body = self.infer(body, level)?;
-                    self.env.remove(name);
+    // whether the type refers to one of the skolems through the bound variables
+    fn mentions(&self, t: &Type, skolems: &HashSet<Id>) -> bool {
+        match t {
+            Type::Skolem(id, _) => skolems.contains(id),
+            Type::TypeVar(id, level) => self
+                .env
+                .lookup_binding(*id, *level)
+                .is_some_and(|binding| self.mentions(binding, skolems)),
+            Type::Const(_) | Type::Generic(_) | Type::Error => false,
+            Type::App(t, params) => {
+                self.mentions(t, skolems) || params.iter().any(|t| self.mentions(t, skolems))
+            }
+            Type::Arrow(init, tail) => {
+                init.iter().any(|t| self.mentions(t, skolems)) || self.mentions(tail, skolems)
+            }
+            Type::Tuple(items) => items.iter().any(|t| self.mentions(t, skolems)),
+            Type::Record(row) | Type::Variant(row) => self.mentions(row, skolems),
+            Type::Qualified(context, t) => {
+                context.iter().any(|(_, t)| self.mentions(t, skolems)) || self.mentions(t, skolems)
+            }
+            Type::RowEmpty => false,
+            Type::RowExtend(_, field, rest) => {
+                self.mentions(field, skolems) || self.mentions(rest, skolems)
+            }
+        }
+    }
 
-                    if let Some(t_old) = shadowing {
-                        self.env.insert(name, t_old);
-                    }
-                    Ok(t_body)
-                }
-                Term::Abs(args, body) => {
-                    let mut unique = HashSet::new();
-                    for arg in args {
-                        if !unique.insert(arg) {
-                            return Err(format!(
-                                "Conflicting definitions of {} in \\{} -> {}",
-                                arg,
-                                args.join(" "),
-                                body
-                            ));
-                        }
+    // the labels of a row and what follows them, through the bound variables
+    fn row_labels(&self, mut row: Type) -> (Vec<String>, Type) {
+        let mut labels = Vec::new();
+        loop {
+            match row {
+                Type::RowExtend(label, _, rest) => {
+                    if !labels.contains(&label) {
+                        labels.push(label);
                     }
+                    row = *rest;
+                }
+                Type::TypeVar(id, level) => match self.env.lookup_binding(id, level) {
+                    Some(binding) => row = binding.clone(),
+                    None => return (labels, row),
+                },
+                row => return (labels, row),
+            }
+        }
+    }
 
-                    let mut shadowing = HashMap::new();
-                    let t_args: Vec<Type> = args
+    // the constructors of the type of the given one, as the exhaustiveness checker sees them,
+    // variants take the labels from the rows of the matched variant types
+    fn signature(
+        &self,
+        constructor: &Constructor,
+        variants: &[Type],
+    ) -> Option<Vec<(Constructor, usize)>> {
+        match constructor {
+            Constructor::Variant(label) => {
+                let (labels, rest) = variants
+                    .iter()
+                    .map(|row| self.row_labels(row.clone()))
+                    .find(|(labels, _)| labels.contains(label))?;
+                (rest == Type::RowEmpty).then(|| {
+                    labels
+                        .into_iter()
+                        .map(|label| (Constructor::Variant(label), 1))
+                        .collect()
+                })
+            }
+            Constructor::Lit(Literal::Bool(_)) => Some(vec![
+                (Constructor::Lit(Literal::Bool(true)), 0),
+                (Constructor::Lit(Literal::Bool(false)), 0),
+            ]),
+            Constructor::Lit(_) => None,
+            Constructor::Tuple(n) => Some(vec![(Constructor::Tuple(*n), *n)]),
+            Constructor::Named(name) => {
+                let data = self.env.lookup_type(self.env.lookup_constructor(name)?)?;
+                Some(
+                    data.constructors
                         .iter()
-                        .map(|arg| {
-                            let t_arg = self.gen.newvar(Some(level));
-                            if let Some(t_old) = self.env.insert(arg, t_arg.clone()) {
-                                shadowing.insert(arg, t_old);
-                            }
-                            t_arg
+                        .map(|name| {
+                            let arity = match self.env.lookup_constructor_scheme(name) {
+                                Some(Type::Arrow(init, _)) => init.len(),
+                                _ => 0,
+                            };
+                            (Constructor::Named(name.clone()), arity)
                         })
-                        .collect();
-                    let t_body = self.infer(body, level)?;
-                    for arg in args {
-                        if let Some(t_old) = shadowing.remove(arg) {
-                            self.env.insert(arg, t_old);
-                        }
+                        .collect(),
+                )
+            }
+        }
+    }
+
+    // the variables of the pattern are collected monomorphically into bound,
+    // and the rows of the variant types it matches into variants
+    fn infer_pattern(
+        &mut self,
+        pattern: &Spanned<Pattern>,
+        level: Level,
+        bound: &mut Vec<(String, Type)>,
+        variants: &mut Vec<Type>,
+    ) -> Result<(Type, Pat), Box<Spanned<TypeError>>> {
+        let at = |error| Box::new(Spanned::new(error, pattern.span));
+        let (name, args) = match &pattern.node {
+            Pattern::Wildcard => return Ok((self.gen.newvar(Some(level)), Pat::Any)),
+            Pattern::Lit(literal) => {
+                let t = self.infer(
+                    &Spanned::new(Term::Lit(literal.clone()), pattern.span),
+                    level,
+                )?;
+                return Ok((t, Pat::Con(Constructor::Lit(literal.clone()), Vec::new())));
+            }
+            Pattern::Var(name) if self.env.lookup_constructor(name).is_none() => {
+                if bound.iter().any(|(bound, _)| bound == name) {
+                    return Err(at(TypeError::DuplicateDefinition(name.clone())));
+                }
+                let t = self.gen.newvar(Some(level));
+                bound.push((name.clone(), t.clone()));
+                return Ok((t, Pat::Any));
+            }
+            Pattern::Tuple(items) => {
+                let mut t_items = Vec::new();
+                let mut pats = Vec::new();
+                for item in items {
+                    let (t_item, pat) = self.infer_pattern(item, level, bound, variants)?;
+                    t_items.push(t_item);
+                    pats.push(pat);
+                }
+                return Ok((
+                    Type::Tuple(t_items),
+                    Pat::Con(Constructor::Tuple(items.len()), pats),
+                ));
+            }
+            Pattern::Variant(label, payload) => {
+                let (t_payload, pat) = match payload {
+                    Some(payload) => self.infer_pattern(payload, level, bound, variants)?,
+                    None => (Type::Record(Box::new(Type::RowEmpty)), Pat::Any),
+                };
+                let row = Type::RowExtend(
+                    label.clone(),
+                    Box::new(t_payload),
+                    Box::new(self.gen.newvar(Some(level))),
+                );
+                variants.push(row.clone());
+                return Ok((
+                    Type::Variant(Box::new(row)),
+                    Pat::Con(Constructor::Variant(label.clone()), vec![pat]),
+                ));
+            }
+            Pattern::Var(name) => (name, &[][..]),
+            Pattern::Constructor(name, args) => (name, &args[..]),
+        };
+
+        // a binding of the same name does not shadow the constructor of a pattern
+        let t_constructor = match self.env.lookup_constructor_scheme(name) {
+            Some(t) => instantiate(t, level, self.gen, self.env),
+            None => return Err(at(TypeError::UndefinedConstructor(name.clone()))),
+        };
+        let (t_args, t_data) = match t_constructor {
+            Type::Arrow(init, tail) => (init, *tail),
+            t => (Vec::new(), t),
+        };
+        if t_args.len() != args.len() {
+            return Err(at(TypeError::ArityMismatch {
+                expected: t_args.len(),
+                found: args.len(),
+            }));
+        }
+
+        let mut pats = Vec::new();
+        for (arg, t_arg) in args.iter().zip(t_args.iter()) {
+            let (t_pattern, pat) = self.infer_pattern(arg, level, bound, variants)?;
+            unify(t_arg, &t_pattern, self.env, self.gen)
+                .map_err(|error| Box::new(Spanned::new(error, arg.span)))?;
+            pats.push(pat);
+        }
+        Ok((t_data, Pat::Con(Constructor::Named(name.clone()), pats)))
+    }
+
+    // while recovering, a term that fails gets the error type and the error is kept
+    pub fn infer(
+        &mut self,
+        term: &Spanned<Term>,
+        level: Level,
+    ) -> Result<Type, Box<Spanned<TypeError>>> {
+        match self.infer_term(term, level) {
+            Err(error) if self.recovering => {
+                self.diagnostics.push(*error);
+                Ok(Type::Error)
+            }
+            t => t,
+        }
+    }
+
+    // while recovering, an error that leaves the term its type is only kept
+    fn recover(
+        &mut self,
+        result: Result<Type, Box<Spanned<TypeError>>>,
+    ) -> Result<(), Box<Spanned<TypeError>>> {
+        match result {
+            Err(error) if self.recovering => {
+                self.diagnostics.push(*error);
+                Ok(())
+            }
+            result => result.map(|_| ()),
+        }
+    }
+
+    fn infer_term(
+        &mut self,
+        term: &Spanned<Term>,
+        level: Level,
+    ) -> Result<Type, Box<Spanned<TypeError>>> {
+        let at = |error| Box::new(Spanned::new(error, term.span));
+        match &term.node {
+            Term::Lit(literal) => Ok(Type::Const(String::from(match literal {
+                Literal::Int(_) => "int",
+                Literal::Float(_) => "float",
+                Literal::Bool(_) => "bool",
+                Literal::Str(_) => "string",
+            }))),
+            Term::Var(name) => {
+                if let Some(t) = self.env.lookup(name).cloned() {
+                    Ok(self.instantiate_scheme(&t, level, term))
+                } else {
+                    Err(at(TypeError::UndefinedVariable(name.clone())))
+                }
+            }
+            Term::Let(name, assign, body) => {
+                // only a value is generalized, the variables of any other term stay weak
+                let since = self.predicates.len();
+                let t_assign = if nonexpansive(assign, self.env) {
+                    self.infer(assign, level + 1)?
+                } else {
+                    self.infer(assign, level)?
+                };
+                let context = self.retain(since, level, std::slice::from_ref(&t_assign))?;
+                let t_assign = self.scheme(&context, &t_assign, level);
+                self.generalized.insert(Node::of(assign), context);
+                self.schemes.insert(Node::of(assign), t_assign.clone());
+
+                self.scoped(&[(name.clone(), t_assign)], |infer| {
+                    infer.infer(body, level)
+                })
+            }
+            Term::LetRec(bindings, body) => {
+                let mut unique = HashSet::new();
+                for (name, _) in bindings {
+                    if !unique.insert(name) {
+                        return Err(at(TypeError::DuplicateBinder {
+                            name: name.clone(),
+                            term: Box::new(term.node.clone()),
+                        }));
+                    }
+                }
+
+                // the group is monomorphic while its own bindings are inferred
+                let level_group = if bindings
+                    .iter()
+                    .all(|(_, assign)| nonexpansive(assign, self.env))
+                {
+                    level + 1
+                } else {
+                    level
+                };
+                let since = self.predicates.len();
+                let vars: Vec<(String, Type)> = bindings
+                    .iter()
+                    .map(|(name, _)| (name.clone(), self.gen.newvar(Some(level_group))))
+                    .collect();
+                self.scoped(&vars, |infer| {
+                    for ((_, assign), (_, t_var)) in bindings.iter().zip(vars.iter()) {
+                        let t_assign = infer.infer(assign, level_group)?;
+                        unify(t_var, &t_assign, infer.env, infer.gen)
+                            .map_err(|error| Box::new(Spanned::new(error, assign.span)))?;
+                    }
+
+                    // every binding of the group takes the constraints of the whole group
+                    let t_vars: Vec<Type> = vars.iter().map(|(_, t_var)| t_var.clone()).collect();
+                    let context = infer.retain(since, level, &t_vars)?;
+                    for ((name, assign), t_var) in bindings.iter().zip(t_vars.iter()) {
+                        let t_name = infer.scheme(&context, t_var, level);
+                        infer.env.insert(name, t_name.clone());
+                        infer.generalized.insert(Node::of(assign), context.clone());
+                        infer.schemes.insert(Node::of(assign), t_name);
                     }
+                    infer.infer(body, level)
+                })
+            }
+            Term::Abs(args, body) => {
+                let mut unique = HashSet::new();
+                for arg in args {
+                    if !unique.insert(arg) {
+                        return Err(at(TypeError::DuplicateBinder {
+                            name: arg.clone(),
+                            term: Box::new(term.node.clone()),
+                        }));
+                    }
+                }
+
+                let bound: Vec<(String, Type)> = args
+                    .iter()
+                    .map(|arg| (arg.clone(), self.gen.newvar(Some(level))))
+                    .collect();
+                let t_body = self.scoped(&bound, |infer| infer.infer(body, level))?;
+                let t_args: Vec<Type> = bound.into_iter().map(|(_, t_arg)| t_arg).collect();
+
+                self.parameters.insert(Node::of(term), t_args.clone());
+                Ok(Type::Arrow(t_args, Box::new(t_body)))
+            }
+            Term::App(f, args) => {
+                let t_f = self.infer(f, level)?;
+                let (t_args, t_return) = self.genarrow(t_f, args.len()).map_err(at)?;
+
+                for (arg, t_arg) in args.iter().zip(t_args.iter()) {
+                    let t_param = self.infer(arg, level)?;
+                    let unified = unify(t_arg, &t_param, self.env, self.gen)
+                        .map_err(|error| Box::new(Spanned::new(error, arg.span)));
+                    self.recover(unified)?;
+                }
+
+                Ok(t_return)
+            }
+            Term::Annot(annotated, ty) => {
+                // the names are those of the environment and the quantified variables
+                match &ty.node {
+                    Ty::Forall(params, t) => {
+                        first_order(t)?;
+                        let known = |name: &str| {
+                            params.iter().any(|param| param == name)
+                                || arity(name, self.env).is_some()
+                        };
+                        check(t, &known)?;
+                    }
+                    _ => {
+                        first_order(ty)?;
+                        check(ty, &|name| arity(name, self.env).is_some())?;
+                    }
+                }
+                kinds(ty, &|name| arity(name, self.env), &|_| false)?;
+                let t_declared = Type::from(ty.node.clone(), self.gen);
+                let Ty::Forall(params, t_body) = &ty.node else {
+                    let t_annotated = self.infer(annotated, level)?;
+                    unify(&t_declared, &t_annotated, self.env, self.gen)
+                        .map_err(|error| Box::new(Spanned::new(error, annotated.span)))?;
+                    return Ok(t_declared);
+                };
+
+                // the quantified variables become rigid, so the term must not specialize them
+                let mut names = Env::new();
+                let mut skolems = HashSet::new();
+                for param in params {
+                    let id = self.gen.newid();
+                    names.insert(param, Type::Skolem(id, param.clone()));
+                    skolems.insert(id);
+                }
+                let (given, t_rigid) = match Type::lookup(t_body.node.clone(), &names) {
+                    Type::Qualified(context, t) => (context, *t),
+                    t => (Vec::new(), t),
+                };
+
+                self.subsume(annotated, &t_declared, &t_rigid, &given, &skolems, level)?;
 
-                    Ok(Type::Arrow(t_args, Box::new(t_body)))
+                // the skolems are abstracted in the order the generic variables are instantiated
+                let mut subst = HashMap::new();
+                let t_skolems = match given.is_empty() {
+                    true => t_rigid,
+                    false => Type::Qualified(given.clone(), Box::new(t_rigid)),
+                };
+                matches(&t_declared, &t_skolems, &mut subst);
+                let ids = generics(&t_declared)
+                    .iter()
+                    .filter_map(|id| match subst.get(id) {
+                        Some(Type::Skolem(id, _)) => Some(*id),
+                        _ => None,
+                    })
+                    .collect();
+                self.rigid.insert(Node::of(term), ids);
+                if !given.is_empty() {
+                    self.given.insert(Node::of(term), given);
                 }
-                Term::App(f, args) => {
-                    let t_f = self.infer(f, level)?;
-                    let (t_args, t_return) = self.genarrow(t_f, args.len())?;
+                Ok(self.instantiate_scheme(&t_declared, level, term))
+            }
+            Term::If(cond, then, otherwise) => {
+                let t_cond = self.infer(cond, level)?;
+                let unified = unify(
+                    &Type::Const(String::from("bool")),
+                    &t_cond,
+                    self.env,
+                    self.gen,
+                )
+                .map_err(|error| Box::new(Spanned::new(error, cond.span)));
+                self.recover(unified)?;
+
+                let t_then = self.infer(then, level)?;
+                let t_otherwise = self.infer(otherwise, level)?;
+                unify(&t_then, &t_otherwise, self.env, self.gen).map_err(|_| {
+                    Box::new(Spanned::new(
+                        TypeError::BranchMismatch {
+                            then: generalize(&t_then, -1, self.env),
+                            otherwise: generalize(&t_otherwise, -1, self.env),
+                        },
+                        otherwise.span,
+                    ))
+                })
+            }
+            Term::Tuple(items) => {
+                let mut t_items = Vec::new();
+                for item in items {
+                    t_items.push(self.infer(item, level)?);
+                }
+                Ok(Type::Tuple(t_items))
+            }
+            Term::Record(fields, rest) => {
+                let mut t_fields = Vec::new();
+                for (label, field) in fields {
+                    t_fields.push((label.clone(), self.infer(field, level)?));
+                }
+                let t_rest = match rest {
+                    Some(rest) => {
+                        let t_rest = self.gen.newvar(Some(level));
+                        let t_record = self.infer(rest, level)?;
+                        unify(
+                            &Type::Record(Box::new(t_rest.clone())),
+                            &t_record,
+                            self.env,
+                            self.gen,
+                        )
+                        .map_err(|error| Box::new(Spanned::new(error, rest.span)))?;
+                        t_rest
+                    }
+                    None => Type::RowEmpty,
+                };
+                let row = t_fields.into_iter().rev().fold(t_rest, |row, (label, t)| {
+                    Type::RowExtend(label, Box::new(t), Box::new(row))
+                });
+                Ok(Type::Record(Box::new(row)))
+            }
+            Term::Select(record, label) | Term::Restrict(record, label) => {
+                let t_field = self.gen.newvar(Some(level));
+                let t_rest = self.gen.newvar(Some(level));
+                let t_param = Type::Record(Box::new(Type::RowExtend(
+                    label.clone(),
+                    Box::new(t_field.clone()),
+                    Box::new(t_rest.clone()),
+                )));
+                let t_record = self.infer(record, level)?;
+                unify(&t_param, &t_record, self.env, self.gen).map_err(at)?;
+                match &term.node {
+                    Term::Select(_, _) => Ok(t_field),
+                    _ => Ok(Type::Record(Box::new(t_rest))),
+                }
+            }
+            Term::Variant(label, payload) => {
+                let t_payload = match payload {
+                    Some(payload) => self.infer(payload, level)?,
+                    None => Type::Record(Box::new(Type::RowEmpty)),
+                };
+                let t_variant = Type::Variant(Box::new(Type::RowExtend(
+                    label.clone(),
+                    Box::new(t_payload),
+                    Box::new(self.gen.newvar(Some(level))),
+                )));
+                self.injected.insert(Node::of(term), t_variant.clone());
+                Ok(t_variant)
+            }
+            Term::Match(scrutinee, arms) => {
+                let t_scrutinee = self.infer(scrutinee, level)?;
+                let t_match = self.gen.newvar(Some(level));
+
+                let mut rows = Vec::new();
+                let mut variants = Vec::new();
+                for (pattern, body) in arms {
+                    let mut bound = Vec::new();
+                    let (t_pattern, pat) =
+                        self.infer_pattern(pattern, level, &mut bound, &mut variants)?;
+                    unify(&t_scrutinee, &t_pattern, self.env, self.gen)
+                        .map_err(|error| Box::new(Spanned::new(error, pattern.span)))?;
 
-                    for (arg, t_arg) in args.iter().zip(t_args.iter()) {
-                        let t_param = self.infer(arg, level)?;
-                        unify(&t_arg, &t_param, &mut self.env)?;
+                    let t_body = self.scoped(&bound, |infer| infer.infer(body, level))?;
+                    unify(&t_match, &t_body, self.env, self.gen)
+                        .map_err(|error| Box::new(Spanned::new(error, body.span)))?;
+
+                    let signature =
+                        |constructor: &Constructor| self.signature(constructor, &variants);
+                    if !useful(&rows, std::slice::from_ref(&pat), &signature) {
+                        return Err(Box::new(Spanned::new(
+                            TypeError::UnreachablePattern(pattern.node.clone()),
+                            pattern.span,
+                        )));
                     }
+                    rows.push(vec![pat]);
+                }
 
-                    Ok(t_return)
+                // the variants still open are closed if that is what makes the match exhaustive
+                let signature = |constructor: &Constructor| self.signature(constructor, &variants);
+                if missing(&rows, 1, &signature).is_some() {
+                    for row in &variants {
+                        if let (_, Type::TypeVar(id, level)) = self.row_labels(row.clone()) {
+                            self.env.bind(id, level, Type::RowEmpty);
+                        }
+                    }
                 }
+                let signature = |constructor: &Constructor| self.signature(constructor, &variants);
+                if let Some(mut witness) = missing(&rows, 1, &signature) {
+                    return Err(at(TypeError::NonExhaustive(Pattern::from(
+                        witness.remove(0),
+                    ))));
+                }
+                Ok(t_match)
             }
         }
     }
 
-    let mut infer = Infer::new(env.clone(), gen.clone());
-    let ty = infer.infer(term, 0)?;
-    Ok(generalize(&ty, -1, &infer.env))
-}
+    // the scheme of the term at the top level, whose variables stay weak unless it is a value,
+    // with the context it is generalized with
+    fn top_level(
+        &mut self,
+        term: &Spanned<Term>,
+        value: bool,
+    ) -> Result<Generalized, Box<Spanned<TypeError>>> {
+        let ty = self.infer(term, if value { 0 } else { -1 })?;
+        let context = self.retain(0, -1, std::slice::from_ref(&ty))?;
+        // the constraints on the weak variables can never be resolved
+        if let Some(predicate) = self.predicates.first() {
+            return Err(Box::new(Spanned::new(
+                TypeError::Ambiguous {
+                    class: predicate.node.0.clone(),
+                },
+                predicate.span,
+            )));
+        }
+        Ok((self.scheme(&context, &ty, -1), context))
+    }
 
-#[cfg(test)]
-fn testing() -> Result<(), String> {
-    use std::str::FromStr;
+    // the term inferred at the top level with the dictionaries of its context as parameters
+    fn translated(&self, term: &Spanned<Term>, context: &[(String, Type)]) -> Spanned<Term> {
+        self.abstracted(context, self.core(term, &HashMap::new()))
+    }
 
-    struct Testing {
-        env: Env<Type>,
-        gen: Gen<Type>,
+    // the term inferred at the top level abstracted over the variables and the context
+    // of its scheme
+    fn elaborated(
+        &self,
+        term: &Spanned<Term>,
+        scheme: &Type,
+        context: &[(String, Type)],
+    ) -> Result<Spanned<SystemF>, Box<Spanned<TypeError>>> {
+        let ids = generics(scheme);
+        let scope = ids.iter().copied().collect();
+        Ok(abstraction(
+            ids.iter().map(|id| variable(*id)).collect(),
+            self.dictionary_params(context, &scope),
+            self.elaborate(term, &scope, &HashMap::new())?,
+        ))
     }
 
-    impl Testing {
-        pub fn new() -> Self {
-            Testing {
-                env: Env::new(),
-                gen: Gen::new(),
+    fn translate(
+        &mut self,
+        term: &Spanned<Term>,
+        value: bool,
+    ) -> Result<(Type, Translation), Box<Spanned<TypeError>>> {
+        let (scheme, context) = self.top_level(term, value)?;
+        let core = self.translated(term, &context);
+        let elaborated = self.elaborated(term, &scheme, &context)?;
+        Ok((scheme, (core, elaborated)))
+    }
+
+    // the group is inferred once with the tuple of its names as the body, every name is
+    // translated as the group with its own name as the body, and qualified with the part
+    // of the context its type mentions
+    fn translate_group(
+        &mut self,
+        bindings: &[(String, Spanned<Term>)],
+        span: Span,
+        value: bool,
+    ) -> Result<Declaration, Box<Spanned<TypeError>>> {
+        let names = bindings
+            .iter()
+            .map(|(name, _)| Spanned::new(Term::Var(name.clone()), span))
+            .collect();
+        let body = Spanned::new(Term::Tuple(names), span);
+        let group = Spanned::new(Term::LetRec(bindings.to_vec(), Box::new(body)), span);
+        let (scheme, context) = self.top_level(&group, value)?;
+        let scope = generics(&scheme).into_iter().collect();
+        let core = self.core(&group, &HashMap::new());
+        let elaborated = self.elaborate(&group, &scope, &HashMap::new())?;
+
+        let (t_context, t_names) = match scheme {
+            Type::Qualified(t_context, t) => (t_context, *t),
+            t => (Vec::new(), t),
+        };
+        let (t_names, core_bindings, core_names, bindings, names) =
+            match (t_names, core.node, elaborated.node) {
+                (
+                    Type::Tuple(t_names),
+                    Term::LetRec(core_bindings, core_body),
+                    SystemF::LetRec(bindings, body),
+                ) => match (core_body.node, body.node) {
+                    (Term::Tuple(core_names), SystemF::Tuple(names)) => {
+                        (t_names, core_bindings, core_names, bindings, names)
+                    }
+                    _ => unreachable!("The body of the group is translated as a tuple"),
+                },
+                _ => unreachable!("The group is translated as a group"),
+            };
+
+        let mut declared = Declaration {
+            types: Vec::new(),
+            cores: Vec::new(),
+            elaborated: Vec::new(),
+        };
+        let names = t_names.into_iter().zip(core_names).zip(names);
+        for ((name, _), ((t_name, core_name), elaborated_name)) in core_bindings.iter().zip(names) {
+            let ids = generics(&t_name);
+            let (t_context, context): (Vec<_>, Vec<_>) = t_context
+                .iter()
+                .cloned()
+                .zip(context.iter().cloned())
+                .filter(|((_, t), _)| generics(t).iter().all(|id| ids.contains(id)))
+                .unzip();
+            let ty = match t_context.is_empty() {
+                true => t_name,
+                false => Type::Qualified(t_context, Box::new(t_name)),
+            };
+
+            let core = Term::LetRec(core_bindings.clone(), Box::new(core_name));
+            let elaborated = SystemF::LetRec(bindings.clone(), Box::new(elaborated_name));
+            let ids = generics(&ty);
+            let scope = ids.iter().copied().collect();
+            declared.cores.push((
+                name.clone(),
+                self.abstracted(&context, Spanned::new(core, span)),
+            ));
+            declared.elaborated.push((
+                name.clone(),
+                abstraction(
+                    ids.iter().map(|id| variable(*id)).collect(),
+                    self.dictionary_params(&context, &scope),
+                    Spanned::new(elaborated, span),
+                ),
+            ));
+            declared.types.push((name.clone(), ty));
+        }
+        Ok(declared)
+    }
+
+    // the scheme of the term with its failed parts given the error type, and all the errors
+    fn diagnose(&mut self, term: &Spanned<Term>, value: bool) -> (Type, Vec<Spanned<TypeError>>) {
+        self.recovering = true;
+        let ty = self
+            .infer(term, if value { 0 } else { -1 })
+            .unwrap_or(Type::Error);
+        let context = match self.retain(0, -1, std::slice::from_ref(&ty)) {
+            Ok(context) => context,
+            Err(error) => {
+                self.diagnostics.push(*error);
+                Vec::new()
             }
+        };
+        if let Some(predicate) = self.predicates.first() {
+            let error = TypeError::Ambiguous {
+                class: predicate.node.0.clone(),
+            };
+            self.diagnostics.push(Spanned::new(error, predicate.span));
         }
+        let scheme = self.scheme(&context, &ty, -1);
+        (scheme, std::mem::take(&mut self.diagnostics))
+    }
 
-        pub fn define(&mut self, name: &str, ty: &str) -> Result<(), String> {
-            self.env.insert(name, Type::from_str(ty, &mut self.gen)?);
-            Ok(())
+    // the dictionary of the class at the type, built of the instances and the parameters in scope
+    fn dictionary(&self, class: &str, t: &Type, span: Span) -> Spanned<Term> {
+        let t = self.resolve(t);
+        let name = |t| Spanned::new(Term::Var(dictionary_name(class, t)), span);
+        if let Type::TypeVar(_, _) | Type::Skolem(_, _) = t {
+            return name(&t);
         }
 
-        pub fn deduce(&mut self, code: &str) -> Result<Type, String> {
-            Ok(infer(&Term::from_str(code)?, &mut self.env, &mut self.gen)?)
+        let mut subst = HashMap::new();
+        let Some(instance) = self.env.instances(class).iter().find(|instance| {
+            subst.clear();
+            matches(&instance.head, &t, &mut subst)
+        }) else {
+            return name(&t);
+        };
+        if instance.context.is_empty() {
+            return name(&instance.head);
         }
+        let dictionaries = instance
+            .context
+            .iter()
+            .map(|(class, param)| {
+                let t_param = match param {
+                    Type::Generic(id) => subst.get(id).unwrap_or(param),
+                    param => param,
+                };
+                self.dictionary(class, t_param, span)
+            })
+            .collect();
+        Spanned::new(
+            Term::App(Box::new(name(&instance.head)), dictionaries),
+            span,
+        )
     }
 
-    let mut testing = Testing::new();
+    // the term abstracted over the dictionaries of the context
+    fn abstracted(&self, context: &[(String, Type)], term: Spanned<Term>) -> Spanned<Term> {
+        if context.is_empty() {
+            return term;
+        }
+        let params = context
+            .iter()
+            .map(|(class, t)| dictionary_name(class, &self.resolve(t)))
+            .collect();
+        let span = term.span;
+        Spanned::new(Term::Abs(params, Box::new(term)), span)
+    }
 
-    testing.define("head", "forall[a] list[a] -> a")?;
+    // the inferred term with the dictionaries passed explicitly,
+    // where the names of the recursive groups in scope take those of their group
+    fn core(
+        &self,
+        term: &Spanned<Term>,
+        recursive: &HashMap<String, Vec<(String, Type)>>,
+    ) -> Spanned<Term> {
+        let core = |term| Box::new(self.core(term, recursive));
+        let shadow = |names: &[String]| {
+            let mut recursive = recursive.clone();
+            for name in names {
+                recursive.remove(name);
+            }
+            recursive
+        };
+        let binding = |assign: &Spanned<Term>, recursive| {
+            let context = self
+                .generalized
+                .get(&Node::of(assign))
+                .map_or(&[][..], |c| c);
+            self.abstracted(context, self.core(assign, recursive))
+        };
+        let dictionaries = |context: &[(String, Type)]| {
+            context
+                .iter()
+                .map(|(class, t)| self.dictionary(class, t, term.span))
+                .collect()
+        };
+
+        let node = match &term.node {
+            Term::Lit(_) => term.node.clone(),
+            Term::Var(name) => match (self.evidence.get(&Node::of(term)), recursive.get(name)) {
+                (Some(context), _) | (None, Some(context)) => {
+                    Term::App(Box::new(term.clone()), dictionaries(context))
+                }
+                (None, None) => term.node.clone(),
+            },
+            Term::Let(name, assign, body) => Term::Let(
+                name.clone(),
+                Box::new(binding(assign, recursive)),
+                Box::new(self.core(body, &shadow(std::slice::from_ref(name)))),
+            ),
+            Term::LetRec(bindings, body) => {
+                let names: Vec<String> = bindings.iter().map(|(name, _)| name.clone()).collect();
+                let mut group = shadow(&names);
+                for (name, assign) in bindings {
+                    match self.generalized.get(&Node::of(assign)) {
+                        Some(context) if !context.is_empty() => {
+                            group.insert(name.clone(), context.clone());
+                        }
+                        _ => {}
+                    }
+                }
+                Term::LetRec(
+                    bindings
+                        .iter()
+                        .map(|(name, assign)| (name.clone(), binding(assign, &group)))
+                        .collect(),
+                    Box::new(self.core(body, &shadow(&names))),
+                )
+            }
+            Term::Abs(args, body) => {
+                Term::Abs(args.clone(), Box::new(self.core(body, &shadow(args))))
+            }
+            Term::App(f, args) => Term::App(core(f), args.iter().map(|arg| *core(arg)).collect()),
+            Term::If(cond, then, otherwise) => Term::If(core(cond), core(then), core(otherwise)),
+            Term::Tuple(items) => Term::Tuple(items.iter().map(|item| *core(item)).collect()),
+            Term::Record(fields, rest) => Term::Record(
+                fields
+                    .iter()
+                    .map(|(label, field)| (label.clone(), *core(field)))
+                    .collect(),
+                rest.as_ref().map(|rest| core(rest)),
+            ),
+            Term::Select(record, label) => Term::Select(core(record), label.clone()),
+            Term::Restrict(record, label) => Term::Restrict(core(record), label.clone()),
+            Term::Variant(label, payload) => {
+                Term::Variant(label.clone(), payload.as_ref().map(|payload| core(payload)))
+            }
+            // the given dictionaries are abstracted and those of the use are applied right away
+            Term::Annot(annotated, ty) => match self.given.get(&Node::of(term)) {
+                Some(given) => {
+                    let context = self.evidence.get(&Node::of(term)).map_or(&[][..], |c| c);
+                    Term::App(
+                        Box::new(self.abstracted(given, *core(annotated))),
+                        dictionaries(context),
+                    )
+                }
+                None => Term::Annot(core(annotated), ty.clone()),
+            },
+            Term::Match(scrutinee, arms) => Term::Match(
+                core(scrutinee),
+                arms.iter()
+                    .map(|(pattern, body)| {
+                        let recursive = shadow(&binders(&pattern.node));
+                        (pattern.clone(), self.core(body, &recursive))
+                    })
+                    .collect(),
+            ),
+        };
+        Spanned::new(node, term.span)
+    }
+
+    // the type in System F with the bound variables substituted
+    fn ty(&self, t: &Type, scope: &HashSet<Id>, span: Span) -> Spanned<Ty> {
+        Spanned::new(to_ty(&self.resolve(t), scope), span)
+    }
+
+    // the parameters the dictionaries of the context are passed in
+    fn dictionary_params(
+        &self,
+        context: &[(String, Type)],
+        scope: &HashSet<Id>,
+    ) -> Vec<(String, Spanned<Ty>)> {
+        context
+            .iter()
+            .map(|(class, t)| {
+                let t = self.resolve(t);
+                let ty = dictionary_ty(class, to_ty(&t, scope));
+                (dictionary_name(class, &t), Spanned::from(ty))
+            })
+            .collect()
+    }
+
+    // the dictionary in System F, where the instance is applied to the types of its head
+    fn elaborate_dictionary(
+        &self,
+        class: &str,
+        t: &Type,
+        span: Span,
+        scope: &HashSet<Id>,
+    ) -> Spanned<SystemF> {
+        let t = self.resolve(t);
+        let name = |t| Spanned::new(SystemF::Var(dictionary_name(class, t)), span);
+        if let Type::TypeVar(_, _) | Type::Skolem(_, _) = t {
+            return name(&t);
+        }
+
+        let mut subst = HashMap::new();
+        let Some(instance) = self.env.instances(class).iter().find(|instance| {
+            subst.clear();
+            matches(&instance.head, &t, &mut subst)
+        }) else {
+            return name(&t);
+        };
+        let types = generics(&instance.head)
+            .iter()
+            .map(|id| Spanned::new(to_ty(&subst[id], scope), span))
+            .collect();
+        let dictionaries = instance
+            .context
+            .iter()
+            .map(|(class, param)| {
+                let t_param = match param {
+                    Type::Generic(id) => subst.get(id).unwrap_or(param),
+                    param => param,
+                };
+                self.elaborate_dictionary(class, t_param, span, scope)
+            })
+            .collect();
+        applied(name(&instance.head), types, dictionaries)
+    }
+
+    // the pattern with the bare names of constructors written as constructors
+    fn elaborate_pattern(&self, pattern: &Spanned<Pattern>) -> Spanned<Pattern> {
+        let node = match &pattern.node {
+            Pattern::Var(name) if self.env.lookup_constructor(name).is_some() => {
+                Pattern::Constructor(name.clone(), Vec::new())
+            }
+            Pattern::Constructor(name, args) => Pattern::Constructor(
+                name.clone(),
+                args.iter().map(|arg| self.elaborate_pattern(arg)).collect(),
+            ),
+            Pattern::Tuple(items) => Pattern::Tuple(
+                items
+                    .iter()
+                    .map(|item| self.elaborate_pattern(item))
+                    .collect(),
+            ),
+            Pattern::Variant(label, Some(payload)) => Pattern::Variant(
+                label.clone(),
+                Some(Box::new(self.elaborate_pattern(payload))),
+            ),
+            pattern => pattern.clone(),
+        };
+        Spanned::new(node, pattern.span)
+    }
+
+    // the inferred term in System F, where the variables in scope are those of the enclosing
+    // type abstractions and the names of the recursive groups are used at the variables
+    // of their group with its dictionaries
+    fn elaborate(
+        &self,
+        term: &Spanned<Term>,
+        scope: &HashSet<Id>,
+        recursive: &HashMap<String, Group>,
+    ) -> Result<Spanned<SystemF>, Box<Spanned<TypeError>>> {
+        let elaborate = |term| self.elaborate(term, scope, recursive).map(Box::new);
+        let shadow = |names: &[String]| {
+            let mut recursive = recursive.clone();
+            for name in names {
+                recursive.remove(name);
+            }
+            recursive
+        };
+        // every node the inference went through has its entries
+        let missing = |what: &str, term: &Spanned<Term>| {
+            let error = TypeError::Internal(format!("no {} was inferred for {}", what, term.node));
+            Box::new(Spanned::new(error, term.span))
+        };
+        // the bound term is abstracted over the variables and the context of its scheme
+        let binding = |assign: &Spanned<Term>, recursive| -> Result<_, Box<Spanned<TypeError>>> {
+            let ids = self
+                .schemes
+                .get(&Node::of(assign))
+                .map_or(Vec::new(), generics);
+            let mut scope = scope.clone();
+            scope.extend(ids.iter().copied());
+            let context = self
+                .generalized
+                .get(&Node::of(assign))
+                .map_or(&[][..], |c| c);
+            Ok(abstraction(
+                ids.iter().map(|id| variable(*id)).collect(),
+                self.dictionary_params(context, &scope),
+                self.elaborate(assign, &scope, recursive)?,
+            ))
+        };
+        let instance = |context: Option<&Vec<(String, Type)>>| {
+            let types = self
+                .instantiated
+                .get(&Node::of(term))
+                .map_or(Vec::new(), |types| {
+                    types.iter().map(|t| self.ty(t, scope, term.span)).collect()
+                });
+            let dictionaries = context.map_or(Vec::new(), |context| {
+                context
+                    .iter()
+                    .map(|(class, t)| self.elaborate_dictionary(class, t, term.span, scope))
+                    .collect()
+            });
+            (types, dictionaries)
+        };
+
+        let node = match &term.node {
+            Term::Lit(literal) => SystemF::Lit(literal.clone()),
+            Term::Var(name) => {
+                let var = Spanned::new(SystemF::Var(name.clone()), term.span);
+                let (types, dictionaries) = match recursive.get(name) {
+                    Some((ids, context)) => (
+                        ids.iter()
+                            .map(|id| Spanned::new(scoped_ty(*id, scope), term.span))
+                            .collect(),
+                        instance(Some(context)).1,
+                    ),
+                    None => instance(self.evidence.get(&Node::of(term))),
+                };
+                return Ok(applied(var, types, dictionaries));
+            }
+            Term::Let(name, assign, body) => SystemF::Let(
+                name.clone(),
+                Box::new(binding(assign, recursive)?),
+                Box::new(self.elaborate(body, scope, &shadow(std::slice::from_ref(name)))?),
+            ),
+            Term::LetRec(bindings, body) => {
+                let names: Vec<String> = bindings.iter().map(|(name, _)| name.clone()).collect();
+                let mut group = shadow(&names);
+                for (name, assign) in bindings {
+                    let ids = self
+                        .schemes
+                        .get(&Node::of(assign))
+                        .map_or(Vec::new(), generics);
+                    let context = self.generalized.get(&Node::of(assign)).cloned();
+                    group.insert(name.clone(), (ids, context.unwrap_or_default()));
+                }
+                SystemF::LetRec(
+                    bindings
+                        .iter()
+                        .map(|(name, assign)| {
+                            let scheme = self
+                                .schemes
+                                .get(&Node::of(assign))
+                                .ok_or_else(|| missing("scheme", assign))?;
+                            let ty = scheme_ty(&self.resolve(scheme), scope);
+                            let ty = Spanned::new(ty, assign.span);
+                            Ok((name.clone(), ty, binding(assign, &group)?))
+                        })
+                        .collect::<Result<_, Box<Spanned<TypeError>>>>()?,
+                    Box::new(self.elaborate(body, scope, &shadow(&names))?),
+                )
+            }
+            Term::Abs(args, body) => {
+                let t_args = self.parameters.get(&Node::of(term)).map_or(&[][..], |t| t);
+                SystemF::Abs(
+                    args.iter()
+                        .zip(t_args)
+                        .map(|(arg, t_arg)| (arg.clone(), self.ty(t_arg, scope, term.span)))
+                        .collect(),
+                    Box::new(self.elaborate(body, scope, &shadow(args))?),
+                )
+            }
+            Term::App(f, args) => SystemF::App(
+                elaborate(f)?,
+                args.iter()
+                    .map(|arg| self.elaborate(arg, scope, recursive))
+                    .collect::<Result<_, Box<Spanned<TypeError>>>>()?,
+            ),
+            Term::If(cond, then, otherwise) => {
+                SystemF::If(elaborate(cond)?, elaborate(then)?, elaborate(otherwise)?)
+            }
+            Term::Tuple(items) => SystemF::Tuple(
+                items
+                    .iter()
+                    .map(|item| self.elaborate(item, scope, recursive))
+                    .collect::<Result<_, Box<Spanned<TypeError>>>>()?,
+            ),
+            Term::Record(fields, rest) => SystemF::Record(
+                fields
+                    .iter()
+                    .map(|(label, field)| Ok((label.clone(), *elaborate(field)?)))
+                    .collect::<Result<_, Box<Spanned<TypeError>>>>()?,
+                rest.as_ref().map(|rest| elaborate(rest)).transpose()?,
+            ),
+            Term::Select(record, label) => SystemF::Select(elaborate(record)?, label.clone()),
+            Term::Restrict(record, label) => SystemF::Restrict(elaborate(record)?, label.clone()),
+            Term::Variant(label, payload) => {
+                let t_variant = self
+                    .injected
+                    .get(&Node::of(term))
+                    .ok_or_else(|| missing("variant type", term))?;
+                SystemF::Variant(
+                    label.clone(),
+                    payload
+                        .as_ref()
+                        .map(|payload| elaborate(payload))
+                        .transpose()?,
+                    self.ty(t_variant, scope, term.span),
+                )
+            }
+            // the skolems are abstracted with the given dictionaries and applied right away
+            Term::Annot(annotated, _) => match self.rigid.get(&Node::of(term)) {
+                Some(ids) => {
+                    let given = self.given.get(&Node::of(term)).map_or(&[][..], |c| c);
+                    let abstracted = abstraction(
+                        ids.iter().map(|id| variable(*id)).collect(),
+                        self.dictionary_params(given, scope),
+                        *elaborate(annotated)?,
+                    );
+                    let (types, dictionaries) = instance(self.evidence.get(&Node::of(term)));
+                    return Ok(applied(abstracted, types, dictionaries));
+                }
+                None => return elaborate(annotated).map(|elaborated| *elaborated),
+            },
+            Term::Match(scrutinee, arms) => SystemF::Match(
+                elaborate(scrutinee)?,
+                arms.iter()
+                    .map(|(pattern, body)| {
+                        let recursive = shadow(&binders(&pattern.node));
+                        Ok((
+                            self.elaborate_pattern(pattern),
+                            self.elaborate(body, scope, &recursive)?,
+                        ))
+                    })
+                    .collect::<Result<_, Box<Spanned<TypeError>>>>()?,
+            ),
+        };
+        Ok(Spanned::new(node, term.span))
+    }
+}
+
+// the name of the dictionary of the class at a variable or at the head of an instance
+fn dictionary_name(class: &str, t: &Type) -> String {
+    match t {
+        Type::TypeVar(id, _) | Type::Generic(id) | Type::Skolem(id, _) => {
+            format!("dict_{}_{}", class, id)
+        }
+        Type::App(t, _) => dictionary_name(class, t),
+        Type::Tuple(items) => format!("dict_{}_tuple{}", class, items.len()),
+        t => format!("dict_{}_{}", class, t),
+    }
+}
+
+// the name of the type variable in System F
+fn variable(id: Id) -> String {
+    format!("t{}", id)
+}
+
+// a variable that no enclosing type abstraction binds is never constrained,
+// so it defaults to the empty record, or the empty row in a row
+fn scoped_ty(id: Id, scope: &HashSet<Id>) -> Ty {
+    match scope.contains(&id) {
+        true => Ty::Const(variable(id)),
+        false => Ty::Record(Vec::new(), None),
+    }
+}
+
+// the resolved type in System F, where the weak variables are left free
+// and a row on its own is written as a record
+fn to_ty(t: &Type, scope: &HashSet<Id>) -> Ty {
+    let convert = |t: &Type| Spanned::from(to_ty(t, scope));
+    match t {
+        Type::Const(name) => Ty::Const(name.clone()),
+        Type::App(t, params) => Ty::App(Box::new(convert(t)), params.iter().map(convert).collect()),
+        Type::Arrow(init, tail) => {
+            Ty::Arrow(init.iter().map(convert).collect(), Box::new(convert(tail)))
+        }
+        Type::Tuple(items) => Ty::Tuple(items.iter().map(convert).collect()),
+        Type::Record(row) => to_ty(row, scope),
+        Type::Variant(row) => match to_ty(row, scope) {
+            Ty::Record(fields, rest) => Ty::Variant(fields, rest),
+            rest => Ty::Variant(Vec::new(), Some(Box::new(Spanned::from(rest)))),
+        },
+        Type::RowEmpty => Ty::Record(Vec::new(), None),
+        Type::RowExtend(label, field, rest) => {
+            let field = (label.clone(), convert(field));
+            match to_ty(rest, scope) {
+                Ty::Record(mut fields, rest) => {
+                    fields.insert(0, field);
+                    Ty::Record(fields, rest)
+                }
+                rest => Ty::Record(vec![field], Some(Box::new(Spanned::from(rest)))),
+            }
+        }
+        Type::Qualified(context, t) => Ty::Arrow(
+            context
+                .iter()
+                .map(|(class, t)| Spanned::from(dictionary_ty(class, to_ty(t, scope))))
+                .collect(),
+            Box::new(convert(t)),
+        ),
+        Type::TypeVar(id, level) if *level < 0 => Ty::Const(format!("_weak{}", id)),
+        Type::TypeVar(id, _) => scoped_ty(*id, scope),
+        Type::Generic(id) | Type::Skolem(id, _) => Ty::Const(variable(*id)),
+        Type::Error => unreachable!("Terms that failed to infer are never elaborated"),
+    }
+}
+
+// the type quantified over the variables, unless there are none
+fn quantified(ids: &[Id], ty: Ty) -> Ty {
+    match ids.is_empty() {
+        true => ty,
+        false => Ty::Forall(
+            ids.iter().map(|id| variable(*id)).collect(),
+            Box::new(Spanned::from(ty)),
+        ),
+    }
+}
+
+// the scheme in System F, whose context becomes the parameters of its dictionaries
+fn scheme_ty(t: &Type, scope: &HashSet<Id>) -> Ty {
+    quantified(&generics(t), to_ty(t, scope))
+}
+
+// the type of the dictionary of the class at the type, the record of its methods
+fn dictionary_ty(class: &str, ty: Ty) -> Ty {
+    Ty::App(
+        Box::new(Spanned::from(Ty::Const(class.to_owned()))),
+        vec![Spanned::from(ty)],
+    )
+}
+
+// the term abstracted over the dictionaries and then over the type variables
+fn abstraction(
+    params: Vec<String>,
+    dictionaries: Vec<(String, Spanned<Ty>)>,
+    term: Spanned<SystemF>,
+) -> Spanned<SystemF> {
+    let span = term.span;
+    let term = match dictionaries.is_empty() {
+        true => term,
+        false => Spanned::new(SystemF::Abs(dictionaries, Box::new(term)), span),
+    };
+    match params.is_empty() {
+        true => term,
+        false => Spanned::new(SystemF::TyAbs(params, Box::new(term)), span),
+    }
+}
+
+// the term applied to the types and then to the dictionaries
+fn applied(
+    term: Spanned<SystemF>,
+    types: Vec<Spanned<Ty>>,
+    dictionaries: Vec<Spanned<SystemF>>,
+) -> Spanned<SystemF> {
+    let span = term.span;
+    let term = match types.is_empty() {
+        true => term,
+        false => Spanned::new(SystemF::TyApp(Box::new(term), types), span),
+    };
+    match dictionaries.is_empty() {
+        true => term,
+        false => Spanned::new(SystemF::App(Box::new(term), dictionaries), span),
+    }
+}
+
+// the names bound by the pattern
+fn binders(pattern: &Pattern) -> Vec<String> {
+    match pattern {
+        Pattern::Wildcard | Pattern::Lit(_) | Pattern::Variant(_, None) => Vec::new(),
+        Pattern::Var(name) => vec![name.clone()],
+        Pattern::Constructor(_, args) | Pattern::Tuple(args) => {
+            args.iter().flat_map(|arg| binders(&arg.node)).collect()
+        }
+        Pattern::Variant(_, Some(payload)) => binders(&payload.node),
+    }
+}
+
+// the term inferred at the top level and handed to the translation with its scheme and context,
+// a query that fails leaves the environment as it was, one that succeeds keeps what it learnt
+// of the weak variables, its own names are already out of scope
+fn query<R>(
+    term: &Spanned<Term>,
+    env: &mut Env<Type>,
+    gen: &mut Gen<Type>,
+    translation: impl FnOnce(&Infer, Type, &[(String, Type)]) -> Result<R, Box<Spanned<TypeError>>>,
+) -> Result<R, Box<Spanned<TypeError>>> {
+    let value = nonexpansive(term, env);
+    let snapshot = env.snapshot();
+    let mut infer = Infer::new(env, gen);
+    let queried = infer
+        .top_level(term, value)
+        .and_then(|(scheme, context)| translation(&infer, scheme, &context));
+    match queried {
+        Ok(_) => env.commit(snapshot),
+        Err(_) => env.rollback(snapshot),
+    }
+    queried
+}
+
+pub fn infer(
+    term: &Spanned<Term>,
+    env: &mut Env<Type>,
+    gen: &mut Gen<Type>,
+) -> Result<Type, Box<Spanned<TypeError>>> {
+    query(term, env, gen, |_, scheme, _| Ok(scheme))
+}
+
+// the type of the term and the term itself with every overloaded name taking its dictionaries
+pub fn translate(
+    term: &Spanned<Term>,
+    env: &mut Env<Type>,
+    gen: &mut Gen<Type>,
+) -> Result<(Type, Spanned<Term>), Box<Spanned<TypeError>>> {
+    query(term, env, gen, |infer, scheme, context| {
+        Ok((scheme, infer.translated(term, context)))
+    })
+}
+
+// the type of the term and the term itself in System F
+pub fn elaborate(
+    term: &Spanned<Term>,
+    env: &mut Env<Type>,
+    gen: &mut Gen<Type>,
+) -> Result<(Type, Spanned<SystemF>), Box<Spanned<TypeError>>> {
+    query(term, env, gen, |infer, scheme, context| {
+        let elaborated = infer.elaborated(term, &scheme, context)?;
+        Ok((scheme, elaborated))
+    })
+}
+
+// the best type of the term however many errors it has, with the errors in the order they are met,
+// only a term without errors keeps what it learnt of the weak variables
+pub fn diagnose(
+    term: &Spanned<Term>,
+    env: &mut Env<Type>,
+    gen: &mut Gen<Type>,
+) -> (Type, Vec<Spanned<TypeError>>) {
+    let value = nonexpansive(term, env);
+    let snapshot = env.snapshot();
+    let diagnosed = Infer::new(env, gen).diagnose(term, value);
+    match diagnosed.1.is_empty() {
+        true => env.commit(snapshot),
+        false => env.rollback(snapshot),
+    }
+    diagnosed
+}
+
+// the environment shared by the queries, which never copy it, a query only keeps the weak
+// variables it resolves and a declaration what it declares, the fresh variables keep counting
+// across them all
+pub struct Session {
+    env: Env<Type>,
+    gen: Gen<Type>,
+}
+
+impl Session {
+    pub fn new(prelude: Env<Type>, gen: Gen<Type>) -> Self {
+        Session { env: prelude, gen }
+    }
+
+    pub fn env(&self) -> &Env<Type> {
+        &self.env
+    }
+
+    // the names in order with what the queries bound their weak variables to
+    pub fn names(&self) -> Vec<(&String, Type)> {
+        let mut names: Vec<(&String, Type)> = self
+            .env
+            .iter()
+            .map(|(name, t)| (name, generalize(t, Level::MAX, &self.env)))
+            .collect();
+        names.sort_by_key(|(name, _)| *name);
+        names
+    }
+
+    // the classes of the context are checked as those of a declaration
+    pub fn define(&mut self, name: &str, ty: &str) -> Result<Type, Error> {
+        let ty = Ty::parse(ty)?;
+        let span = ty.span;
+        let at = |error| Box::new(Spanned::new(error, span));
+        let t = Type::checked(ty, &mut self.gen, &self.env)?;
+        if let Type::Qualified(context, t_body) = &t {
+            for (class, t_class) in context {
+                if self.env.lookup_class(class).is_none() {
+                    return Err(at(TypeError::UndefinedClass(class.clone())).into());
+                }
+                if generics(t_class).into_iter().any(|id| !occurs(id, t_body)) {
+                    return Err(at(TypeError::Ambiguous {
+                        class: class.clone(),
+                    })
+                    .into());
+                }
+            }
+        }
+        self.env.insert(name, t.clone());
+        Ok(t)
+    }
+
+    pub fn infer(&mut self, term: &Spanned<Term>) -> Result<Type, Box<Spanned<TypeError>>> {
+        infer(term, &mut self.env, &mut self.gen)
+    }
+
+    pub fn translate(
+        &mut self,
+        term: &Spanned<Term>,
+    ) -> Result<(Type, Spanned<Term>), Box<Spanned<TypeError>>> {
+        translate(term, &mut self.env, &mut self.gen)
+    }
+
+    pub fn elaborate(
+        &mut self,
+        term: &Spanned<Term>,
+    ) -> Result<(Type, Spanned<SystemF>), Box<Spanned<TypeError>>> {
+        elaborate(term, &mut self.env, &mut self.gen)
+    }
+
+    pub fn diagnose(&mut self, term: &Spanned<Term>) -> (Type, Vec<Spanned<TypeError>>) {
+        diagnose(term, &mut self.env, &mut self.gen)
+    }
+
+    pub fn declare(
+        &mut self,
+        decl: &Spanned<Decl>,
+    ) -> Result<Vec<(String, Type)>, Box<Spanned<TypeError>>> {
+        declare(decl, &mut self.env, &mut self.gen)
+    }
+
+    pub fn translate_decl(
+        &mut self,
+        decl: &Spanned<Decl>,
+    ) -> Result<Translated, Box<Spanned<TypeError>>> {
+        translate_decl(decl, &mut self.env, &mut self.gen)
+    }
+
+    pub fn elaborate_decl(
+        &mut self,
+        decl: &Spanned<Decl>,
+    ) -> Result<Elaborated, Box<Spanned<TypeError>>> {
+        elaborate_decl(decl, &mut self.env, &mut self.gen)
+    }
+
+    // whatever the closure declares is forgotten after it returns
+    pub fn tentatively<R>(&mut self, f: impl FnOnce(&mut Session) -> R) -> R {
+        let snapshot = self.env.snapshot();
+        let result = f(self);
+        self.env.rollback(snapshot);
+        result
+    }
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new(Env::new(), Gen::new())
+    }
+}
+
+// the names of the environment with their schemes in System F, the classes with the types
+// of their methods and the dictionaries of the instances
+impl From<&Env<Type>> for Context {
+    fn from(env: &Env<Type>) -> Self {
+        let scope = HashSet::new();
+        let mut context = Context::new();
+        for (name, data) in env.types() {
+            context.insert_type(name);
+            for constructor in &data.constructors {
+                if let Some(t) = env.lookup_constructor_scheme(constructor) {
+                    let ty = scheme_ty(&generalize(t, Level::MAX, env), &scope);
+                    context.insert_constructor(constructor, ty);
+                }
+            }
+        }
+        // the names shadowing a constructor only replace it as a term
+        for (name, t) in env.iter() {
+            let ty = scheme_ty(&generalize(t, Level::MAX, env), &scope);
+            context.insert(name, ty);
+        }
+        for (class, t_class) in env.classes() {
+            let Type::Generic(param) = t_class.param else {
+                continue;
+            };
+            let methods = t_class
+                .methods
+                .iter()
+                .map(|(method, t_method)| {
+                    let mut ids = generics(t_method);
+                    ids.retain(|id| *id != param);
+                    (method.clone(), quantified(&ids, to_ty(t_method, &scope)))
+                })
+                .collect();
+            context.insert_class(class, &variable(param), methods);
+
+            for instance in env.instances(class) {
+                let ty = dictionary_ty(class, to_ty(&instance.head, &scope));
+                let ty = match instance.context.is_empty() {
+                    true => ty,
+                    false => Ty::Arrow(
+                        instance
+                            .context
+                            .iter()
+                            .map(|(class, t)| Spanned::from(dictionary_ty(class, to_ty(t, &scope))))
+                            .collect(),
+                        Box::new(Spanned::from(ty)),
+                    ),
+                };
+                let name = dictionary_name(class, &instance.head);
+                context.insert(&name, quantified(&generics(&instance.head), ty));
+            }
+        }
+        context
+    }
+}
+
+// whether the term is a syntactic value, whose evaluation cannot allocate a reference
+fn nonexpansive(term: &Spanned<Term>, env: &Env<Type>) -> bool {
+    value(term, env, &HashSet::new())
+}
+
+// a name is only applied as a constructor while no binding shadows its scheme
+fn value(term: &Spanned<Term>, env: &Env<Type>, shadowed: &HashSet<&str>) -> bool {
+    match &term.node {
+        Term::Lit(_) | Term::Var(_) | Term::Abs(_, _) => true,
+        Term::App(f, args) => {
+            let constructor = |name: &String| {
+                !shadowed.contains(name.as_str())
+                    && env
+                        .lookup_constructor_scheme(name)
+                        .is_some_and(|scheme| env.lookup(name) == Some(scheme))
+            };
+            matches!(&f.node, Term::Var(name) if constructor(name))
+                && args.iter().all(|arg| value(arg, env, shadowed))
+        }
+        Term::Tuple(items) => items.iter().all(|item| value(item, env, shadowed)),
+        Term::Record(fields, rest) => {
+            fields.iter().all(|(_, field)| value(field, env, shadowed))
+                && rest.iter().all(|rest| value(rest, env, shadowed))
+        }
+        Term::Select(record, _) | Term::Restrict(record, _) => value(record, env, shadowed),
+        Term::Variant(_, payload) => payload.iter().all(|payload| value(payload, env, shadowed)),
+        Term::Annot(annotated, _) => value(annotated, env, shadowed),
+        Term::Let(name, assign, body) => {
+            let mut inner = shadowed.clone();
+            inner.insert(name.as_str());
+            value(assign, env, shadowed) && value(body, env, &inner)
+        }
+        Term::LetRec(bindings, body) => {
+            let mut inner = shadowed.clone();
+            inner.extend(bindings.iter().map(|(name, _)| name.as_str()));
+            bindings
+                .iter()
+                .all(|(_, assign)| value(assign, env, &inner))
+                && value(body, env, &inner)
+        }
+        Term::If(_, _, _) | Term::Match(_, _) => false,
+    }
+}
+
+// every name in the type is a known one
+fn check(ty: &Spanned<Ty>, known: &dyn Fn(&str) -> bool) -> Result<(), Box<Spanned<TypeError>>> {
+    match &ty.node {
+        Ty::Const(name) if known(name) => Ok(()),
+        Ty::Const(name) => Err(Box::new(Spanned::new(
+            TypeError::UndefinedType(name.clone()),
+            ty.span,
+        ))),
+        Ty::App(t, params) => {
+            check(t, known)?;
+            params.iter().try_for_each(|param| check(param, known))
+        }
+        Ty::Arrow(init, tail) => {
+            init.iter().try_for_each(|param| check(param, known))?;
+            check(tail, known)
+        }
+        Ty::Tuple(items) => items.iter().try_for_each(|item| check(item, known)),
+        Ty::Record(fields, rest) | Ty::Variant(fields, rest) => {
+            fields
+                .iter()
+                .try_for_each(|(_, field)| check(field, known))?;
+            rest.iter().try_for_each(|rest| check(rest, known))
+        }
+        Ty::Qualified(context, t) => {
+            context.iter().try_for_each(|(_, t)| check(t, known))?;
+            check(t, known)
+        }
+        Ty::Forall(_, _) => first_order(ty),
+    }
+}
+
+pub fn declare(
+    decl: &Spanned<Decl>,
+    env: &mut Env<Type>,
+    gen: &mut Gen<Type>,
+) -> Result<Vec<(String, Type)>, Box<Spanned<TypeError>>> {
+    declaration(decl, env, gen).map(|declared| declared.types)
+}
+
+// the declared names with their types, and the core terms the declaration defines
+pub type Translated = (Vec<(String, Type)>, Vec<(String, Spanned<Term>)>);
+
+pub fn translate_decl(
+    decl: &Spanned<Decl>,
+    env: &mut Env<Type>,
+    gen: &mut Gen<Type>,
+) -> Result<Translated, Box<Spanned<TypeError>>> {
+    declaration(decl, env, gen).map(|declared| (declared.types, declared.cores))
+}
+
+// the declared names with their types, and the terms the declaration defines in System F
+pub type Elaborated = (Vec<(String, Type)>, Vec<(String, Spanned<SystemF>)>);
+
+pub fn elaborate_decl(
+    decl: &Spanned<Decl>,
+    env: &mut Env<Type>,
+    gen: &mut Gen<Type>,
+) -> Result<Elaborated, Box<Spanned<TypeError>>> {
+    declaration(decl, env, gen).map(|declared| (declared.types, declared.elaborated))
+}
+
+// the terms are defined both with the dictionaries passed explicitly and in System F
+struct Declaration {
+    types: Vec<(String, Type)>,
+    cores: Vec<(String, Spanned<Term>)>,
+    elaborated: Vec<(String, Spanned<SystemF>)>,
+}
+
+// a declaration that fails leaves the environment as it was
+fn declaration(
+    decl: &Spanned<Decl>,
+    env: &mut Env<Type>,
+    gen: &mut Gen<Type>,
+) -> Result<Declaration, Box<Spanned<TypeError>>> {
+    let snapshot = env.snapshot();
+    let declared = declared(decl, env, gen);
+    match declared {
+        Ok(_) => env.commit(snapshot),
+        Err(_) => env.rollback(snapshot),
+    }
+    declared
+}
+
+fn declared(
+    decl: &Spanned<Decl>,
+    env: &mut Env<Type>,
+    gen: &mut Gen<Type>,
+) -> Result<Declaration, Box<Spanned<TypeError>>> {
+    let at = |error| Box::new(Spanned::new(error, decl.span));
+    match &decl.node {
+        // the weak variables keep what the declarations learn of them
+        Decl::Let(name, assign) => {
+            let value = nonexpansive(assign, env);
+            let (ty, (core, elaborated)) = Infer::new(env, gen).translate(assign, value)?;
+            env.insert(name, ty.clone());
+            Ok(Declaration {
+                types: vec![(name.clone(), ty)],
+                cores: vec![(name.clone(), core)],
+                elaborated: vec![(name.clone(), elaborated)],
+            })
+        }
+        Decl::LetRec(bindings) => {
+            // every name of the group is translated as the body of the whole group
+            let value = bindings.iter().all(|(_, assign)| nonexpansive(assign, env));
+            let declared = Infer::new(env, gen).translate_group(bindings, decl.span, value)?;
+            for (name, ty) in &declared.types {
+                env.insert(name, ty.clone());
+            }
+            Ok(declared)
+        }
+        Decl::Type(name, params, constructors) => {
+            // a type or constructor is declared once, the old values would be left with the new type
+            if arity(name, env).is_some() {
+                return Err(at(TypeError::DuplicateDefinition(name.clone())));
+            }
+            let mut unique = HashSet::new();
+            for param in params {
+                if !unique.insert(param) {
+                    return Err(at(TypeError::DuplicateDefinition(param.clone())));
+                }
+            }
+            let mut unique = HashSet::new();
+            for (constructor, _) in constructors {
+                if !unique.insert(constructor) || env.lookup_constructor(constructor).is_some() {
+                    return Err(at(TypeError::DuplicateDefinition(constructor.clone())));
+                }
+            }
+
+            let arity = |ty: &str| match ty == name {
+                true => Some(params.len()),
+                false if params.iter().any(|param| param == ty) => Some(0),
+                false => arity(ty, env),
+            };
+            for (_, args) in constructors {
+                for arg in args {
+                    check(arg, &|ty| arity(ty).is_some())?;
+                    kinds(arg, &arity, &|ty| params.iter().any(|param| param == ty))?;
+                }
+            }
+
+            let mut names = Env::new();
+            let mut t_params = Vec::new();
+            for param in params {
+                let t_param = gen.newvar(None);
+                names.insert(param, t_param.clone());
+                t_params.push(t_param);
+            }
+            let t_data = if t_params.is_empty() {
+                Type::Const(name.clone())
+            } else {
+                Type::App(Box::new(Type::Const(name.clone())), t_params)
+            };
+
+            let types: Vec<(String, Type)> = constructors
+                .iter()
+                .map(|(constructor, args)| {
+                    let ty = if args.is_empty() {
+                        t_data.clone()
+                    } else {
+                        Type::Arrow(
+                            args.iter()
+                                .map(|arg| Type::lookup(arg.node.clone(), &names))
+                                .collect(),
+                            Box::new(t_data.clone()),
+                        )
+                    };
+                    (constructor.clone(), ty)
+                })
+                .collect();
+
+            env.insert_type(
+                name,
+                DataType {
+                    params: params.clone(),
+                    constructors: constructors.iter().map(|(c, _)| c.clone()).collect(),
+                },
+            );
+            for (constructor, ty) in &types {
+                env.insert_constructor(constructor, ty.clone());
+            }
+            Ok(Declaration {
+                types,
+                cores: Vec::new(),
+                elaborated: Vec::new(),
+            })
+        }
+        Decl::Class(name, param, methods) => {
+            // the instances of a class are only valid for the methods they were declared with
+            if env.lookup_class(name).is_some() {
+                return Err(at(TypeError::DuplicateDefinition(name.clone())));
+            }
+            let mut unique = HashSet::new();
+            for (method, _) in methods {
+                if !unique.insert(method) {
+                    return Err(at(TypeError::DuplicateDefinition(method.clone())));
+                }
+            }
+            let arity = |ty: &str| match ty == param {
+                true => Some(0),
+                false => arity(ty, env),
+            };
+            for (_, ty) in methods {
+                check(ty, &|ty| arity(ty).is_some())?;
+                kinds(ty, &arity, &|ty| ty == param)?;
+            }
+
+            // every method is qualified with the class, so it has to mention the parameter
+            let mut names = Env::new();
+            let t_param = gen.newvar(None);
+            names.insert(param, t_param.clone());
+            let context = vec![(name.clone(), t_param.clone())];
+            let mut t_methods = Vec::new();
+            for (method, ty) in methods {
+                let t_method = Type::lookup(ty.node.clone(), &names);
+                if !matches!(t_param, Type::Generic(id) if occurs(id, &t_method)) {
+                    return Err(Box::new(Spanned::new(
+                        TypeError::Ambiguous {
+                            class: name.clone(),
+                        },
+                        ty.span,
+                    )));
+                }
+                t_methods.push((method.clone(), t_method));
+            }
+            let types: Vec<(String, Type)> = t_methods
+                .iter()
+                .map(|(method, t_method)| {
+                    let t_method = Type::Qualified(context.clone(), Box::new(t_method.clone()));
+                    (method.clone(), t_method)
+                })
+                .collect();
+
+            // every method selects itself from the dictionary
+            let cores = methods
+                .iter()
+                .map(|(method, _)| {
+                    let dictionary = Spanned::new(Term::Var(String::from("dict")), decl.span);
+                    let select = Term::Select(Box::new(dictionary), method.clone());
+                    let core = Term::Abs(
+                        vec![String::from("dict")],
+                        Box::new(Spanned::new(select, decl.span)),
+                    );
+                    (method.clone(), Spanned::new(core, decl.span))
+                })
+                .collect();
+            // and takes the other variables of its type after the parameter
+            let elaborated = t_methods
+                .iter()
+                .map(|(method, t_method)| {
+                    let mut ids = generics(t_method);
+                    ids.retain(|id| Type::Generic(*id) != t_param);
+                    let dictionary = Spanned::new(SystemF::Var(String::from("dict")), decl.span);
+                    let select = Spanned::new(
+                        SystemF::Select(Box::new(dictionary), method.clone()),
+                        decl.span,
+                    );
+                    let types = ids
+                        .iter()
+                        .map(|id| Spanned::new(Ty::Const(variable(*id)), decl.span))
+                        .collect();
+                    let t_dictionary = dictionary_ty(name, to_ty(&t_param, &HashSet::new()));
+                    let elaborated = abstraction(
+                        generics(&t_param)
+                            .into_iter()
+                            .chain(ids)
+                            .map(variable)
+                            .collect(),
+                        vec![(String::from("dict"), Spanned::new(t_dictionary, decl.span))],
+                        applied(select, types, Vec::new()),
+                    );
+                    (method.clone(), elaborated)
+                })
+                .collect();
+
+            env.insert_class(
+                name,
+                Class {
+                    param: t_param,
+                    methods: t_methods,
+                },
+            );
+            for (method, ty) in &types {
+                env.insert(method, ty.clone());
+            }
+            Ok(Declaration {
+                types,
+                cores,
+                elaborated,
+            })
+        }
+        Decl::Instance(context, class, head, methods) => {
+            let invalid = || {
+                at(TypeError::InvalidInstance {
+                    class: class.clone(),
+                    head: head.node.clone(),
+                })
+            };
+            let classes = context.iter().map(|(class, _)| class);
+            for class in std::iter::once(class).chain(classes) {
+                if env.lookup_class(class).is_none() {
+                    return Err(at(TypeError::UndefinedClass(class.clone())));
+                }
+            }
+
+            // the head is a known type constructor applied to distinct variables
+            let known = |ty: &str| arity(ty, env).is_some();
+            kinds(head, &|ty| arity(ty, env), &|ty| !known(ty))?;
+            let params = match &head.node {
+                Ty::Const(name) if known(name) => Vec::new(),
+                Ty::App(t, params) => match &t.node {
+                    Ty::Const(name) if known(name) => params.iter().collect(),
+                    _ => return Err(invalid()),
+                },
+                Ty::Tuple(items) => items.iter().collect(),
+                _ => return Err(invalid()),
+            };
+            let mut names = Env::new();
+            for param in params {
+                match &param.node {
+                    Ty::Const(name) if !known(name) && names.lookup(name).is_none() => {
+                        names.insert(name, gen.newvar(None));
+                    }
+                    _ => return Err(invalid()),
+                }
+            }
+
+            let mut t_context = Vec::new();
+            for (class, ty) in context {
+                match &ty.node {
+                    Ty::Const(name) if names.lookup(name).is_some() => {
+                        t_context.push((class.clone(), Type::lookup(ty.node.clone(), &names)));
+                    }
+                    _ => return Err(invalid()),
+                }
+            }
+            let t_head = Type::lookup(head.node.clone(), &names);
+            if env
+                .instances(class)
+                .iter()
+                .any(|instance| matches(&instance.head, &t_head, &mut HashMap::new()))
+            {
+                return Err(at(TypeError::DuplicateDefinition(format!(
+                    "{}[{}]",
+                    class, head
+                ))));
+            }
+
+            let instance = Instance {
+                context: t_context,
+                head: t_head,
+            };
+            if methods.is_empty() {
+                env.insert_instance(class, instance);
+                return Ok(Declaration {
+                    types: Vec::new(),
+                    cores: Vec::new(),
+                    elaborated: Vec::new(),
+                });
+            }
+
+            // the variables of the head are rigid in the methods, which may rely on the context
+            let mut rigid = Env::new();
+            for (name, _) in names.iter() {
+                rigid.insert(name, Type::Skolem(gen.newid(), name.clone()));
+            }
+            let given: Vec<(String, Type)> = context
+                .iter()
+                .map(|(class, ty)| (class.clone(), Type::lookup(ty.node.clone(), &rigid)))
+                .collect();
+            let t_head = Type::lookup(head.node.clone(), &rigid);
+            // the dictionary abstracts over the skolems in the order of the variables of the head
+            let mut subst = HashMap::new();
+            matches(&instance.head, &t_head, &mut subst);
+            let skolems = generics(&instance.head)
+                .iter()
+                .filter_map(|id| match subst.get(id) {
+                    Some(Type::Skolem(id, _)) => Some(variable(*id)),
+                    _ => None,
+                })
+                .collect();
+
+            // the methods may use the instance itself
+            let name = dictionary_name(class, &instance.head);
+            env.insert_instance(class, instance);
+            let (core, elaborated) = instance_methods(
+                class,
+                &given,
+                &t_head,
+                methods,
+                decl.span,
+                &mut Infer::new(env, gen),
+            )?;
+            Ok(Declaration {
+                types: Vec::new(),
+                cores: vec![(name.clone(), core)],
+                elaborated: vec![(name, abstraction(skolems, Vec::new(), elaborated))],
+            })
+        }
+    }
+}
+
+// the generic variables substituted, the ones not given become fresh skolems
+fn skolemize(t: &Type, rigid: &mut HashMap<Id, Type>, gen: &mut Gen<Type>) -> Type {
+    match t {
+        Type::Generic(id) => {
+            let name = format!("t{}", rigid.len());
+            rigid
+                .entry(*id)
+                .or_insert_with(|| Type::Skolem(gen.newid(), name))
+                .clone()
+        }
+        Type::Const(_)
+        | Type::TypeVar(_, _)
+        | Type::Skolem(_, _)
+        | Type::RowEmpty
+        | Type::Error => t.clone(),
+        Type::App(t, params) => Type::App(
+            Box::new(skolemize(t, rigid, gen)),
+            params.iter().map(|t| skolemize(t, rigid, gen)).collect(),
+        ),
+        Type::Arrow(init, tail) => Type::Arrow(
+            init.iter().map(|t| skolemize(t, rigid, gen)).collect(),
+            Box::new(skolemize(tail, rigid, gen)),
+        ),
+        Type::Tuple(items) => Type::Tuple(items.iter().map(|t| skolemize(t, rigid, gen)).collect()),
+        Type::Record(row) => Type::Record(Box::new(skolemize(row, rigid, gen))),
+        Type::Variant(row) => Type::Variant(Box::new(skolemize(row, rigid, gen))),
+        Type::RowExtend(label, field, rest) => Type::RowExtend(
+            label.clone(),
+            Box::new(skolemize(field, rigid, gen)),
+            Box::new(skolemize(rest, rigid, gen)),
+        ),
+        Type::Qualified(context, t) => Type::Qualified(
+            context
+                .iter()
+                .map(|(class, t)| (class.clone(), skolemize(t, rigid, gen)))
+                .collect(),
+            Box::new(skolemize(t, rigid, gen)),
+        ),
+    }
+}
+
+// the methods of an instance checked at its rigid head, as the record of its dictionary
+// that takes the dictionaries of the context
+fn instance_methods(
+    class: &str,
+    given: &[(String, Type)],
+    t_head: &Type,
+    methods: &[(String, Spanned<Term>)],
+    span: Span,
+    infer: &mut Infer,
+) -> Result<Translation, Box<Spanned<TypeError>>> {
+    let Some(t_class) = infer.env.lookup_class(class).cloned() else {
+        return Err(Box::new(Spanned::new(
+            TypeError::UndefinedClass(class.to_owned()),
+            span,
+        )));
+    };
+    let mut unique = HashSet::new();
+    for (method, assign) in methods {
+        if !unique.insert(method) {
+            return Err(Box::new(Spanned::new(
+                TypeError::DuplicateDefinition(method.clone()),
+                assign.span,
+            )));
+        }
+        if !t_class.methods.iter().any(|(name, _)| name == method) {
+            return Err(Box::new(Spanned::new(
+                TypeError::UndefinedMethod {
+                    class: class.to_owned(),
+                    method: method.clone(),
+                },
+                assign.span,
+            )));
+        }
+    }
+
+    let mut fields = Vec::new();
+    let mut elaborated = Vec::new();
+    for (method, t_method) in &t_class.methods {
+        let Some((_, assign)) = methods.iter().find(|(name, _)| name == method) else {
+            return Err(Box::new(Spanned::new(
+                TypeError::MissingMethod {
+                    class: class.to_owned(),
+                    method: method.clone(),
+                },
+                span,
+            )));
+        };
+
+        let mut rigid = HashMap::new();
+        if let Type::Generic(id) = t_class.param {
+            rigid.insert(id, t_head.clone());
+        }
+        let t_rigid = skolemize(t_method, &mut rigid, infer.gen);
+        let params = match t_head {
+            Type::App(_, params) | Type::Tuple(params) => &params[..],
+            _ => &[],
+        };
+        let skolems: HashSet<Id> = params
+            .iter()
+            .chain(rigid.values())
+            .filter_map(|t| match t {
+                Type::Skolem(id, _) => Some(*id),
+                _ => None,
+            })
+            .collect();
+
+        infer.subsume(assign, &t_rigid, &t_rigid, given, &skolems, 0)?;
+        infer.retain(0, -1, std::slice::from_ref(&t_rigid))?;
+        fields.push((method.clone(), infer.core(assign, &HashMap::new())));
+
+        // the other variables of the method are abstracted as the skolems they became
+        let ids: Vec<String> = generics(t_method)
+            .iter()
+            .filter(|id| Type::Generic(**id) != t_class.param)
+            .filter_map(|id| match rigid.get(id) {
+                Some(Type::Skolem(id, _)) => Some(variable(*id)),
+                _ => None,
+            })
+            .collect();
+        let field = infer.elaborate(assign, &HashSet::new(), &HashMap::new())?;
+        elaborated.push((method.clone(), abstraction(ids, Vec::new(), field)));
+    }
+
+    let record = Spanned::new(Term::Record(fields, None), span);
+    let elaborated = Spanned::new(SystemF::Record(elaborated, None), span);
+    let params = infer.dictionary_params(given, &HashSet::new());
+    Ok((
+        infer.abstracted(given, record),
+        abstraction(Vec::new(), params, elaborated),
+    ))
+}
+
+#[cfg(test)]
+fn testing() -> Result<(), crate::Error> {
+    use crate::Error;
+
+    struct Testing {
+        env: Env<Type>,
+        gen: Gen<Type>,
+    }
+
+    impl Testing {
+        pub fn new() -> Self {
+            Testing {
+                env: Env::new(),
+                gen: Gen::new(),
+            }
+        }
+
+        pub fn define(&mut self, name: &str, ty: &str) -> Result<(), Error> {
+            self.env.insert(name, Type::from_str(ty, &mut self.gen)?);
+            Ok(())
+        }
+
+        pub fn deduce(&mut self, code: &str) -> Result<Type, Error> {
+            Ok(infer(&Term::parse(code)?, &mut self.env, &mut self.gen)?)
+        }
+    }
+
+    let mut testing = Testing::new();
+
+    testing.define("head", "forall[a] list[a] -> a")?;
     testing.define("tail", "forall[a] list[a] -> list[a]")?;
     testing.define("nil", "forall[a] list[a]")?;
     testing.define("cons", "forall[a] (a, list[a]) -> list[a]")?;
@@ -348,8 +2861,6 @@ fn testing() -> Result<(), String> {
     testing.define("eq", "forall[a] (a, a) -> bool")?;
     testing.define("eq_curry", "forall[a] a -> a -> bool")?;
     testing.define("not", "bool -> bool")?;
-    testing.define("true", "bool")?;
-    testing.define("false", "bool")?;
     testing.define("pair", "forall[a, b] (a, b) -> pair[a, b]")?;
     testing.define("pair_curry", "forall[a, b] a -> b -> pair[a, b]")?;
     testing.define("first", "forall[a, b] pair[a, b] -> a")?;
@@ -360,15 +2871,23 @@ fn testing() -> Result<(), String> {
     testing.define("apply_curry", "forall[a, b] (a -> b) -> a -> b")?;
     testing.define("choose", "forall[a] (a, a) -> a")?;
     testing.define("choose_curry", "forall[a] a -> a -> a")?;
+    testing.define("+", "(int, int) -> int")?;
+    testing.define("*", "(int, int) -> int")?;
+    testing.define("<", "(int, int) -> bool")?;
+    testing.define("==", "forall[a] (a, a) -> bool")?;
+    testing.define("and", "(bool, bool) -> bool")?;
 
-    let mut testcase = |code: &str, result: &str| -> Result<(), String> {
+    let mut testcase = |code: &str, result: &str| -> Result<(), Error> {
         match testing.deduce(code) {
             Ok(ty) => {
                 let t_signature = Type::from_str(result, &mut testing.gen)?;
-                assert_eq!(ty.to_string(), t_signature.to_string());
+                assert_eq!(
+                    normalized(&ty.to_string()),
+                    normalized(&t_signature.to_string())
+                );
             }
             Err(error) => {
-                assert_eq!(error, result)
+                assert_eq!(error.to_string(), result)
             }
         }
         Ok(())
@@ -410,18 +2929,21 @@ fn testing() -> Result<(), String> {
         r"let f = \x y -> let a = eq_curry(x)(y) in eq_curry(x)(y) in f",
         r"forall[a] (a, a) -> bool",
     )?;
-    testcase(r"id(id)", r"forall[a] a -> a")?;
-    testcase(r"choose(\x y -> x, \x y -> y)", r"forall[a] (a, a) -> a")?;
+    testcase(r"id(id)", r"_weak1 -> _weak1")?;
+    testcase(
+        r"choose(\x y -> x, \x y -> y)",
+        r"(_weak1, _weak1) -> _weak1",
+    )?;
     testcase(
         r"choose_curry(\x y -> x)(\x y -> y)",
-        r"forall[a] (a, a) -> a",
+        r"(_weak1, _weak1) -> _weak1",
     )?;
     testcase(
         r"let x = id in let y = let z = x(id) in z in y",
-        r"forall[a] a -> a",
+        r"_weak1 -> _weak1",
     )?;
-    testcase(r"cons(id, nil)", r"forall[a] list[a -> a]")?;
-    testcase(r"cons_curry(id)(nil)", r"forall[a] list[a -> a]")?;
+    testcase(r"cons(id, nil)", r"list[_weak1 -> _weak1]")?;
+    testcase(r"cons_curry(id)(nil)", r"list[_weak1 -> _weak1]")?;
     testcase(
         r"let lst1 = cons(id, nil) in let lst2 = cons(succ, lst1) in lst2",
         r"list[int -> int]",
@@ -431,7 +2953,10 @@ fn testing() -> Result<(), String> {
         r"list[int -> int]",
     )?;
     testcase(r"plus(one, true)", "Cannot unify int with bool")?;
-    testcase(r"plus(one)", "Incorrect number of arguments. Must be 1")?;
+    testcase(
+        r"plus(one)",
+        "Incorrect number of arguments: expected 2, found 1",
+    )?;
     testcase(r"\x -> let y = x in y", r"forall[a] a -> a")?;
     testcase(
         r"\x -> let y = let z = x(\x -> x) in z in y",
@@ -473,14 +2998,1370 @@ fn testing() -> Result<(), String> {
     )?;
     testcase(r"\x -> let y = x in y(y)", "Infinite type")?;
     testcase(r"\x -> x(x)", "Infinite type")?;
+    testcase(r"42", r"int")?;
+    testcase(r"-4.2e1", r"float")?;
+    testcase(r#"pair("a\"b", true)"#, r"pair[string, bool]")?;
+    testcase(r"plus(1, 2.0)", "Cannot unify int with float")?;
+    testcase(r"\x -> choose(x, false)", r"bool -> bool")?;
+    testcase(r"\x y -> if x then y else one", r"(bool, int) -> int")?;
+    testcase(
+        r"\f -> if f(one) then f else succ",
+        r"Branches of if have different types: then is int -> bool, else is int -> int",
+    )?;
+    testcase(r"if one then one else zero", r"Cannot unify bool with int")?;
+    testcase(r"{x = one, y = true}", r"{x : int, y : bool}")?;
+    testcase(r"{x = one, y = true}.y", r"bool")?;
+    testcase(r"\r -> r.x", r"forall[a, r] {x : a | r} -> a")?;
+    testcase(r"\r -> {z = 0 | r}", r"forall[r] {| r} -> {z : int | r}")?;
+    testcase(r"{{x = one, y = true} - x}", r"{y : bool}")?;
+    testcase(
+        r"\r -> if r.b then r.x else 0",
+        r"forall[r] {b : bool, x : int | r} -> int",
+    )?;
+    testcase(
+        r"\f -> choose(f({x = 1, y = true}), f({y = false, x = 2}))",
+        r"forall[a] ({x : int, y : bool} -> a) -> a",
+    )?;
+    testcase(r"{x = true | {x = one}}.x", r"bool")?;
+    testcase(r"{{x = true | {x = one}} - x}.x", r"int")?;
+    testcase(r"{x = one}.y", r"Row has no label y")?;
+    testcase(
+        r"choose({x = one}, {x = one, y = one})",
+        r"Row has no label y",
+    )?;
+    testcase(r"\r -> choose({x = 1 | r}, {y = 1 | r})", r"Infinite type")?;
+    testcase(r"\r -> choose(r, {x = 1 | r})", r"Infinite type")?;
+    testcase(
+        r"(\r -> r.x : forall[r] {x : int | r} -> int)",
+        r"forall[r] {x : int | r} -> int",
+    )?;
+    testcase(
+        r"(\r -> r.y : forall[r] {x : int | r} -> int)",
+        r"Type forall[a, r] {y : a | r} -> a is less general than the annotation forall[r] {x : int | r} -> int",
+    )?;
+    testcase(r"(one, (true, id))", r"forall[a] (int, (bool, a -> a))")?;
+    testcase(r"\x -> (x, x)", r"forall[a] a -> (a, a)")?;
+    testcase(
+        r"\f -> f((one, zero))",
+        r"forall[a] (((int, int)) -> a) -> a",
+    )?;
+    testcase(
+        r"choose((one, zero), (one, zero, one))",
+        r"Cannot unify (int, int) with (int, int, int)",
+    )?;
+    testcase(
+        r"(\p -> p : ((int, int)) -> int)",
+        r"Cannot unify (int, int) with int",
+    )?;
+    testcase(
+        r"let rec fact = \n -> if n < 1 then 1 else n * fact(n + -1) in fact",
+        r"int -> int",
+    )?;
+    testcase(
+        r"let rec len = \l -> if eq(l, nil) then 0 else 1 + len(tail(l)) in len",
+        r"forall[a] list[a] -> int",
+    )?;
+    testcase(
+        r"let rec even = \n -> if n == 0 then true else odd(n + -1) and odd = \n -> if n == 0 then false else even(n + -1) in pair(even, odd)",
+        r"pair[int -> bool, int -> bool]",
+    )?;
+    testcase(
+        r"let rec f = \x -> x and g = \x -> f(x) in pair(g(one), g(true))",
+        r"pair[int, bool]",
+    )?;
+    testcase(r"let rec f = \x -> f in f", r"Infinite type")?;
+    testcase(
+        r"let rec f = one and f = zero in f",
+        r"Conflicting definitions of f in let rec f = one and f = zero in f",
+    )?;
+    testcase(
+        r"let fact = \n -> fact(n) in fact",
+        r"Undefined variable 'fact'",
+    )?;
+    testcase(r"(one : int)", r"int")?;
+    testcase(r"\x -> (x : int)", r"int -> int")?;
+    testcase(r"(one : bool)", r"Cannot unify bool with int")?;
+    testcase(
+        r"let f : forall[a] a -> a = \x -> x in pair(f(one), f(true))",
+        r"pair[int, bool]",
+    )?;
+    testcase(r"let f : int -> int = \x -> x in f", r"int -> int")?;
+    testcase(
+        r"let f : forall[a, b] (a, b) -> a = \x y -> x in f",
+        r"forall[a, b] (a, b) -> a",
+    )?;
+    testcase(
+        r"(\x y -> x : forall[a] (a, a) -> a)",
+        r"forall[a] (a, a) -> a",
+    )?;
+    testcase(
+        r"let f : forall[a] a -> a = succ in f",
+        r"Type int -> int is less general than the annotation forall[a] a -> a",
+    )?;
+    testcase(
+        r"let f : forall[a, b] (a, b) -> a = \x y -> y in f",
+        r"Type forall[a, b] (a, b) -> b is less general than the annotation forall[a, b] (a, b) -> a",
+    )?;
+    testcase(
+        r"\x -> let f : forall[a] a -> a = \y -> x in f",
+        r"Type forall[a, b] a -> b is less general than the annotation forall[a] a -> a",
+    )?;
+    testcase(r"1 + 2 * one", r"int")?;
+    testcase(r"\x y -> x < y + 1 and y == x", r"(int, int) -> bool")?;
+    testcase(r"\x -> x == id", r"forall[a] (a -> a) -> bool")?;
+    testcase(r"map((\x -> x * 2), nil)", r"list[int]")?;
+    testcase(r"(+)(one, 2)", r"int")?;
+    testcase(r"one < 2 and 3", r"Cannot unify bool with int")?;
+    testcase(r"not one", r"Cannot unify bool with int")?;
+    testcase(
+        r"if true then one else false",
+        r"Branches of if have different types: then is int, else is bool",
+    )?;
 
     Ok(())
 }
 
+// the generated names with their ids renumbered in the order they first appear, so that the
+// expectations do not depend on how many fresh variables were made before
+#[cfg(test)]
+fn normalized(s: &str) -> String {
+    let mut ids = HashMap::new();
+    let mut rename = |word: &str| {
+        let prefix = word.trim_end_matches(|c: char| c.is_ascii_digit());
+        let generated = prefix == "t"
+            || prefix == "_weak"
+            || (prefix.starts_with("dict_") && prefix.ends_with('_'));
+        if !generated || prefix.len() == word.len() {
+            return word.to_owned();
+        }
+        let next = ids.len() + 1;
+        let id = *ids.entry(word[prefix.len()..].to_owned()).or_insert(next);
+        format!("{}{}", prefix, id)
+    };
+
+    let mut normalized = String::new();
+    let mut word = String::new();
+    for c in s.chars() {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            word.push(c);
+        } else {
+            normalized.push_str(&rename(&word));
+            normalized.push(c);
+            word.clear();
+        }
+    }
+    normalized.push_str(&rename(&word));
+    normalized
+}
+
 #[test]
 fn inference_tests() {
     match testing() {
         Ok(_) => {}
-        Err(error) => println!("{}", error),
+        Err(error) => panic!("{}", error),
     };
 }
+
+#[test]
+fn declarations() {
+    let mut env = Env::new();
+    let mut gen = Gen::new();
+    let mut declare = |code: &str| {
+        declare(&Decl::parse(code).unwrap(), &mut env, &mut gen).map(|types| {
+            types
+                .into_iter()
+                .map(|(name, ty)| format!("{} : {}", name, ty))
+                .collect::<Vec<String>>()
+        })
+    };
+
+    assert_eq!(
+        declare("type list[a] = nil | cons(a, list[a])"),
+        Ok(vec![
+            String::from("nil : forall[a] list[a]"),
+            String::from("cons : forall[a] (a, list[a]) -> list[a]"),
+        ])
+    );
+    assert_eq!(
+        declare("type option[a] = none | some(a)"),
+        Ok(vec![
+            String::from("none : forall[a] option[a]"),
+            String::from("some : forall[a] a -> option[a]"),
+        ])
+    );
+    assert_eq!(
+        declare("type shape = circle(float) | rect(float, float)"),
+        Ok(vec![
+            String::from("circle : float -> shape"),
+            String::from("rect : (float, float) -> shape"),
+        ])
+    );
+    assert_eq!(
+        declare(r"let single = \x -> cons(some(x), nil)"),
+        Ok(vec![String::from(
+            "single : forall[a] a -> list[option[a]]"
+        )])
+    );
+    assert_eq!(
+        declare(r"let rec count = \l -> count(l) + 1").map_err(|e| e.node),
+        Err(TypeError::UndefinedVariable(String::from("+")))
+    );
+    assert_eq!(
+        declare("type tree[a] = leaf | node(tree[a], b, tree[a])").map_err(|e| e.node),
+        Err(TypeError::UndefinedType(String::from("b")))
+    );
+    assert_eq!(
+        declare("type t[a, a] = t").map_err(|e| e.node),
+        Err(TypeError::DuplicateDefinition(String::from("a")))
+    );
+    assert_eq!(
+        declare("type t = a | b | a").map_err(|e| e.node),
+        Err(TypeError::DuplicateDefinition(String::from("a")))
+    );
+    assert_eq!(
+        declare("type list[a] = empty").map_err(|e| e.node),
+        Err(TypeError::DuplicateDefinition(String::from("list")))
+    );
+    assert_eq!(
+        declare("type int = zero").map_err(|e| e.node),
+        Err(TypeError::DuplicateDefinition(String::from("int")))
+    );
+    assert_eq!(
+        declare("type maybe[a] = nothing | some(a)").map_err(|e| e.node),
+        Err(TypeError::DuplicateDefinition(String::from("some")))
+    );
+    assert_eq!(env.lookup_type("maybe"), None);
+
+    assert_eq!(
+        env.lookup_type("list"),
+        Some(&DataType {
+            params: vec![String::from("a")],
+            constructors: vec![String::from("nil"), String::from("cons")],
+        })
+    );
+    assert_eq!(
+        env.lookup_constructor("some"),
+        Some(&String::from("option"))
+    );
+    assert_eq!(env.lookup_constructor("single"), None);
+}
+
+#[test]
+fn pattern_matching() {
+    let mut env = Env::new();
+    let mut gen = Gen::new();
+    for decl in [
+        "type list[a] = nil | cons(a, list[a])",
+        "type option[a] = none | some(a)",
+    ] {
+        declare(&Decl::parse(decl).unwrap(), &mut env, &mut gen).unwrap();
+    }
+    env.insert("+", Type::from_str("(int, int) -> int", &mut gen).unwrap());
+
+    let mut deduce = |code: &str| match infer(&Term::parse(code).unwrap(), &mut env, &mut gen) {
+        Ok(ty) => ty.to_string(),
+        Err(error) => error.node.to_string(),
+    };
+
+    assert_eq!(
+        deduce(r"\l -> match l { nil -> 0, cons(x, _) -> x }"),
+        "list[int] -> int"
+    );
+    assert_eq!(
+        deduce(r"\l d -> match l { cons(x, nil) -> x, _ -> d }"),
+        "forall[a] (list[a], a) -> a"
+    );
+    assert_eq!(
+        deduce(r"\l -> match l { cons(some(x), xs) -> x + 1, cons(none, _) -> 0, nil -> 0 }"),
+        "list[option[int]] -> int"
+    );
+    assert_eq!(
+        deduce(r#"\n -> match n { 0 -> "zero", _ -> "many" }"#),
+        "int -> string"
+    );
+    assert_eq!(
+        deduce(r"\b -> match b { true -> 1, false -> 0 }"),
+        "bool -> int"
+    );
+    assert_eq!(
+        deduce(r"\x -> match some(1) { some(x) -> x, none -> 0 }"),
+        "forall[a] a -> int"
+    );
+    assert_eq!(
+        deduce(r"\o -> match o { some(f) -> if f(true) then f(1) else 0, none -> 0 }"),
+        "Cannot unify bool with int"
+    );
+    // the constructors of the patterns are not shadowed by the names in scope
+    assert_eq!(
+        deduce(r"\o -> let some = 1 in match o { some(x) -> x + some, none -> some }"),
+        "option[int] -> int"
+    );
+    assert_eq!(
+        deduce(r"\o -> let none = true in match o { none -> 0, some(x) -> if none then x else 0 }"),
+        "option[int] -> int"
+    );
+
+    assert_eq!(
+        deduce(r"match nil { nil -> 0 }"),
+        "Non-exhaustive match, cons(_, _) is not covered"
+    );
+    assert_eq!(
+        deduce(r"\l -> match l { nil -> 0, cons(_, nil) -> 1 }"),
+        "Non-exhaustive match, cons(_, cons(_, _)) is not covered"
+    );
+    assert_eq!(
+        deduce(r"\n -> match n { 0 -> 1, 1 -> 2 }"),
+        "Non-exhaustive match, _ is not covered"
+    );
+    assert_eq!(
+        deduce(r"\b -> match b { true -> 1 }"),
+        "Non-exhaustive match, false is not covered"
+    );
+    assert_eq!(
+        deduce(r"\l -> match l { _ -> 0, nil -> 1 }"),
+        "Unreachable pattern nil"
+    );
+    assert_eq!(
+        deduce(r"match 1 { foo(x) -> x }"),
+        "Undefined constructor 'foo'"
+    );
+    assert_eq!(
+        deduce(r"match nil { cons(x) -> x, nil -> 0 }"),
+        "Incorrect number of arguments: expected 2, found 1"
+    );
+    assert_eq!(
+        deduce(r"match some(1) { some(true) -> 0, _ -> 1 }"),
+        "Cannot unify bool with int"
+    );
+    assert_eq!(
+        deduce(r"\l -> match l { cons(x, x) -> x, nil -> 0 }"),
+        "Conflicting definitions of x"
+    );
+
+    assert_eq!(
+        deduce(r"\p -> match p { (x, y) -> (y, x) }"),
+        "forall[a, b] ((a, b)) -> (b, a)"
+    );
+    assert_eq!(
+        deduce(r"\p -> match p { (nil, _) -> 0, (_, nil) -> 1 }"),
+        "Non-exhaustive match, (cons(_, _), cons(_, _)) is not covered"
+    );
+    assert_eq!(
+        deduce(r"match (1, 2) { (1, 2, 3) -> 0, _ -> 1 }"),
+        "Cannot unify (int, int) with (int, int, int)"
+    );
+}
+
+#[test]
+fn polymorphic_variants() {
+    let mut env = Env::new();
+    let mut gen = Gen::new();
+    env.insert("+", Type::from_str("(int, int) -> int", &mut gen).unwrap());
+
+    let mut deduce = |code: &str| match infer(&Term::parse(code).unwrap(), &mut env, &mut gen) {
+        Ok(ty) => ty.to_string(),
+        Err(error) => error.node.to_string(),
+    };
+
+    assert_eq!(deduce(r"`Ok(1)"), "forall[r] [Ok : int | r]");
+    assert_eq!(deduce(r"`None"), "forall[r] [None : {} | r]");
+    assert_eq!(
+        deduce(r"\b -> if b then `Ok(1) else `Err(true)"),
+        "forall[r] bool -> [Ok : int, Err : bool | r]"
+    );
+    assert_eq!(
+        deduce(r"\x -> match x { `Ok(a) -> a, `Err(e) -> 0 }"),
+        "forall[a] [Ok : int, Err : a] -> int"
+    );
+    assert_eq!(
+        deduce(r"\x -> match x { `Some(a) -> a + 1, _ -> 0 }"),
+        "forall[r] [Some : int | r] -> int"
+    );
+    assert_eq!(deduce(r"match `A { `A -> 1, `B -> 2 }"), "int");
+    assert_eq!(
+        deduce(r"\x -> match x { `Pair(a, b) -> a + b, `Zero -> 0 }"),
+        "[Pair : (int, int), Zero : {}] -> int"
+    );
+    assert_eq!(
+        deduce(r"match `C { `A -> 1, `B -> 2 }"),
+        "Non-exhaustive match, `C is not covered"
+    );
+    assert_eq!(
+        deduce(r"(\x -> match x { `A -> 1, `B -> 2 })(`C)"),
+        "Row has no label C"
+    );
+    assert_eq!(
+        deduce(r"\v -> (match v { `A -> 1 }, match v { `B -> 2 })"),
+        "Row has no label B"
+    );
+    assert_eq!(
+        deduce(r"\x -> match x { `Ok(1) -> 1, `Err(e) -> 0 }"),
+        "Non-exhaustive match, `Ok is not covered"
+    );
+    assert_eq!(
+        deduce(r"\x -> match x { `A -> 1, `A -> 2 }"),
+        "Unreachable pattern `A"
+    );
+    assert_eq!(
+        deduce(r"\x -> match x { `A(y) -> y + 1, `A(true) -> 0 }"),
+        "Cannot unify int with bool"
+    );
+}
+
+#[test]
+fn type_classes() {
+    let mut env = Env::new();
+    let mut gen = Gen::new();
+    let mut declare = |code: &str| {
+        declare(&Decl::parse(code).unwrap(), &mut env, &mut gen)
+            .map(|types| {
+                types
+                    .into_iter()
+                    .map(|(name, ty)| format!("{} : {}", name, ty))
+                    .collect::<Vec<String>>()
+            })
+            .map_err(|error| error.node.to_string())
+    };
+
+    assert_eq!(
+        declare("class Eq[a] { eq : (a, a) -> bool }"),
+        Ok(vec![String::from("eq : forall[a] Eq[a] => (a, a) -> bool")])
+    );
+    assert_eq!(
+        declare("class Show[a] { show : a -> string }"),
+        Ok(vec![String::from(
+            "show : forall[a] Show[a] => a -> string"
+        )])
+    );
+    for decl in [
+        "type list[a] = nil | cons(a, list[a])",
+        "instance Eq[int]",
+        "instance Eq[bool]",
+        "instance Show[int]",
+        "instance Eq[a] => Eq[list[a]]",
+        "instance (Eq[a], Eq[b]) => Eq[(a, b)]",
+    ] {
+        assert!(declare(decl).is_ok(), "{}", decl);
+    }
+    assert_eq!(
+        declare("instance Eq[list[b]]"),
+        Err(String::from("Conflicting definitions of Eq[list[b]]"))
+    );
+    assert_eq!(
+        declare("class Eq[b] { same : (b, b) -> bool }"),
+        Err(String::from("Conflicting definitions of Eq"))
+    );
+    assert_eq!(
+        declare("instance Ord[int]"),
+        Err(String::from("Undefined class 'Ord'"))
+    );
+    assert_eq!(
+        declare("instance Eq[list[int]]"),
+        Err(String::from(
+            "Instance Eq[list[int]] is not a type constructor applied to distinct variables"
+        ))
+    );
+    assert_eq!(
+        declare("instance Eq[b] => Eq[option[a]]"),
+        Err(String::from(
+            "Instance Eq[option[a]] is not a type constructor applied to distinct variables"
+        ))
+    );
+    assert_eq!(
+        declare("class Default[a] { default : int }"),
+        Err(String::from(
+            "Ambiguous type variable in a constraint of class Default"
+        ))
+    );
+
+    env.insert("succ", Type::from_str("int -> int", &mut gen).unwrap());
+    let mut deduce = |code: &str| match infer(&Term::parse(code).unwrap(), &mut env, &mut gen) {
+        Ok(ty) => ty.to_string(),
+        Err(error) => error.node.to_string(),
+    };
+
+    assert_eq!(deduce(r"eq(1, 2)"), "bool");
+    assert_eq!(
+        deduce(r"\x y -> eq(x, y)"),
+        "forall[a] Eq[a] => (a, a) -> bool"
+    );
+    assert_eq!(
+        deduce(r"\x -> eq(cons(x, nil), nil)"),
+        "forall[a] Eq[a] => a -> bool"
+    );
+    assert_eq!(
+        deduce(r"\x y -> (eq(y, y), show(x))"),
+        "forall[a, b] (Eq[b], Show[a]) => (a, b) -> (bool, string)"
+    );
+    assert_eq!(
+        deduce(r"\p -> eq(p, (1, cons(true, nil)))"),
+        "((int, list[bool])) -> bool"
+    );
+    assert_eq!(deduce(r"let f = \x -> eq(x, x) in f(1)"), "bool");
+    assert_eq!(
+        deduce(r"\x -> let f = \y -> eq(x, y) in f"),
+        "forall[a] Eq[a] => a -> a -> bool"
+    );
+    assert_eq!(
+        deduce(r"(\x y -> eq(x, y) : forall[a] Eq[a] => (a, a) -> bool)"),
+        "forall[a] Eq[a] => (a, a) -> bool"
+    );
+
+    assert_eq!(
+        deduce(r"eq(succ, succ)"),
+        "No instance of Eq for int -> int"
+    );
+    assert_eq!(
+        deduce(r"let f = \x -> eq(x, x) in f(cons(succ, nil))"),
+        "No instance of Eq for int -> int"
+    );
+    assert_eq!(deduce(r"show(true)"), "No instance of Show for bool");
+    assert_eq!(
+        deduce(r"eq(nil, nil)"),
+        "Ambiguous type variable in a constraint of class Eq"
+    );
+    assert_eq!(
+        deduce(r"(\x y -> eq(x, y) : forall[a] (a, a) -> bool)"),
+        "No instance of Eq for a"
+    );
+    assert_eq!(
+        deduce(r"let rec f = \x -> eq(x, x) and g = \y -> y in g"),
+        "Ambiguous type variable in a constraint of class Eq"
+    );
+}
+
+#[test]
+fn dictionary_passing() {
+    let mut env = Env::new();
+    let mut gen = Gen::new();
+    let mut translate = |code: &str| {
+        translate_decl(&Decl::parse(code).unwrap(), &mut env, &mut gen)
+            .map(|(_, cores)| {
+                cores
+                    .into_iter()
+                    .map(|(name, core)| normalized(&format!("{} = {}", name, core)))
+                    .collect::<Vec<String>>()
+            })
+            .map_err(|error| error.node.to_string())
+    };
+
+    assert_eq!(
+        translate("type list[a] = nil | cons(a, list[a])"),
+        Ok(vec![])
+    );
+    assert_eq!(
+        translate("class Eq[a] { eq : (a, a) -> bool }"),
+        Ok(vec![String::from(r"eq = \dict -> dict.eq")])
+    );
+    assert_eq!(translate("instance Eq[int]"), Ok(vec![]));
+    assert_eq!(
+        translate("instance Eq[bool] { eq = \\x y -> if x then y else if y then false else true }"),
+        Ok(vec![String::from(
+            r"dict_Eq_bool = {eq = \x y -> if x then y else if y then false else true}"
+        )])
+    );
+    assert_eq!(
+        translate(
+            r"instance Eq[a] => Eq[list[a]] { eq = \xs ys -> match (xs, ys) {
+                (nil, nil) -> true,
+                (cons(x, xs), cons(y, ys)) -> if eq(x, y) then eq(xs, ys) else false,
+                _ -> false
+            } }"
+        ),
+        Ok(vec![String::from(
+            r"dict_Eq_list = \dict_Eq_1 -> {eq = \xs ys -> match (xs, ys) { (nil, nil) -> true, (cons(x, xs), cons(y, ys)) -> if eq(dict_Eq_1)(x, y) then eq(dict_Eq_list(dict_Eq_1))(xs, ys) else false, _ -> false }}"
+        )])
+    );
+    assert_eq!(
+        translate(r"let same = \x -> eq(cons(x, nil), cons(x, nil))"),
+        Ok(vec![String::from(
+            r"same = \dict_Eq_1 -> \x -> eq(dict_Eq_list(dict_Eq_1))(cons(x, nil), cons(x, nil))"
+        )])
+    );
+    assert_eq!(
+        translate(r"let yes = same(1)"),
+        Ok(vec![String::from(r"yes = same(dict_Eq_int)(1)")])
+    );
+    assert_eq!(
+        translate(r"let twice = \x -> let f = \y -> eq(y, y) in (f(x), f(true))"),
+        Ok(vec![String::from(
+            r"twice = \dict_Eq_1 -> \x -> let f = \dict_Eq_2 -> \y -> eq(dict_Eq_2)(y, y) in (f(dict_Eq_1)(x), f(dict_Eq_bool)(true))"
+        )])
+    );
+    assert_eq!(
+        translate(
+            r"let rec elem = \x xs -> match xs { nil -> false, cons(y, ys) -> if eq(x, y) then true else elem(x, ys) }"
+        ),
+        Ok(vec![String::from(
+            r"elem = \dict_Eq_1 -> let rec elem = \dict_Eq_2 -> \x xs -> match xs { nil -> false, cons(y, ys) -> if eq(dict_Eq_2)(x, y) then true else elem(dict_Eq_2)(x, ys) } in elem(dict_Eq_1)"
+        )])
+    );
+    // the group is inferred once and every name is abstracted over its own dictionaries
+    assert_eq!(
+        translate(r"let rec same = \x -> eq(x, x) and other = \y -> same(y)"),
+        Ok(vec![
+            String::from(
+                r"same = \dict_Eq_1 -> let rec same = \dict_Eq_2 -> \x -> eq(dict_Eq_2)(x, x) and other = \dict_Eq_2 -> \y -> same(dict_Eq_2)(y) in same(dict_Eq_1)"
+            ),
+            String::from(
+                r"other = \dict_Eq_1 -> let rec same = \dict_Eq_2 -> \x -> eq(dict_Eq_2)(x, x) and other = \dict_Eq_2 -> \y -> same(dict_Eq_2)(y) in other(dict_Eq_1)"
+            ),
+        ])
+    );
+    assert_eq!(
+        translate(r"let annotated = (\x y -> eq(x, y) : forall[a] Eq[a] => (a, a) -> bool)"),
+        Ok(vec![String::from(
+            r"annotated = \dict_Eq_1 -> (\dict_Eq_2 -> \x y -> eq(dict_Eq_2)(x, y))(dict_Eq_1)"
+        )])
+    );
+
+    assert_eq!(
+        translate(r"instance Eq[a] => Eq[(a, a)]"),
+        Err(String::from(
+            "Instance Eq[(a, a)] is not a type constructor applied to distinct variables"
+        ))
+    );
+    translate("type option[a] = none | some(a)").unwrap();
+    assert_eq!(
+        translate(r"instance Eq[option[a]] { eq = \x y -> true, ne = \x y -> false }"),
+        Err(String::from("Class Eq has no method 'ne'"))
+    );
+    assert_eq!(
+        translate(r"instance Eq[option[a]] { ne = \x y -> false }"),
+        Err(String::from("Class Eq has no method 'ne'"))
+    );
+    translate("class Show[a] { show : a -> string, shows : list[a] -> string }").unwrap();
+    assert_eq!(
+        translate(r#"instance Show[int] { show = \x -> "int" }"#),
+        Err(String::from("Instance of Show does not define 'shows'"))
+    );
+    assert_eq!(
+        translate(
+            r"instance Eq[option[a]] { eq = \x y -> match (x, y) { (some(x), some(y)) -> eq(x, y), _ -> false } }"
+        ),
+        Err(String::from("No instance of Eq for a"))
+    );
+    assert_eq!(
+        translate(r"instance Eq[option[a]] { eq = \x y -> eq(x, some(1)) }"),
+        Err(String::from(
+            "Type forall[a] (option[int], a) -> bool is less general than the annotation (option[a], option[a]) -> bool"
+        ))
+    );
+}
+
+#[test]
+fn value_restriction() {
+    let mut env = Env::new();
+    let mut gen = Gen::new();
+    for (name, ty) in [
+        ("id", "forall[a] a -> a"),
+        ("head", "forall[a] list[a] -> a"),
+        ("ref", "forall[a] a -> ref[a]"),
+        ("!", "forall[a] ref[a] -> a"),
+        (":=", "forall[a] (ref[a], a) -> {}"),
+        ("+", "(int, int) -> int"),
+    ] {
+        env.insert(name, Type::from_str(ty, &mut gen).unwrap());
+    }
+    let mut declare = |code: &str| {
+        declare(&Decl::parse(code).unwrap(), &mut env, &mut gen)
+            .map(|types| {
+                types
+                    .into_iter()
+                    .map(|(name, ty)| normalized(&format!("{} : {}", name, ty)))
+                    .collect::<Vec<String>>()
+            })
+            .map_err(|error| error.node.to_string())
+    };
+
+    assert!(declare("type list[a] = nil | cons(a, list[a])").is_ok());
+    assert!(declare("class Eq[a] { eq : (a, a) -> bool }").is_ok());
+    assert_eq!(
+        declare(r"let incr = \r -> r := !r + 1"),
+        Ok(vec![String::from("incr : ref[int] -> {}")])
+    );
+    assert_eq!(
+        declare(r"let single = cons(\x -> x, nil)"),
+        Ok(vec![String::from("single : forall[a] list[a -> a]")])
+    );
+
+    // the classic unsoundness, a polymorphic reference written at one type and read at another
+    assert_eq!(
+        declare(r"let r = ref(nil)"),
+        Ok(vec![String::from("r : ref[list[_weak1]]")])
+    );
+    assert_eq!(
+        declare(r"let u = r := cons(1, nil)"),
+        Ok(vec![String::from("u : {}")])
+    );
+    assert_eq!(
+        declare(r"let v = r := cons(true, nil)"),
+        Err(String::from("Cannot unify bool with int"))
+    );
+    assert_eq!(
+        declare(r"let w = head(!r)"),
+        Ok(vec![String::from("w : int")])
+    );
+
+    // the variables of the failed declaration are not reused
+    assert_eq!(
+        declare(r"let f = id(id)"),
+        Ok(vec![String::from("f : _weak1 -> _weak1")])
+    );
+    assert_eq!(
+        declare(r"let g = f(true)"),
+        Ok(vec![String::from("g : bool")])
+    );
+    assert_eq!(
+        declare(r"let h = f"),
+        Ok(vec![String::from("h : bool -> bool")])
+    );
+    assert_eq!(
+        declare(r"let e = ref(eq)"),
+        Err(String::from(
+            "Ambiguous type variable in a constraint of class Eq"
+        ))
+    );
+
+    // a name shadowing a constructor is applied as any other function
+    assert!(declare("type box[a] = box(a)").is_ok());
+    assert_eq!(
+        declare(r"let box = ref"),
+        Ok(vec![String::from("box : forall[a] a -> ref[a]")])
+    );
+    assert!(declare(r"let q = box(nil)").is_ok());
+    assert!(declare(r"let u = q := cons(1, nil)").is_ok());
+    assert_eq!(
+        declare(r"let v = q := cons(true, nil)"),
+        Err(String::from("Cannot unify bool with int"))
+    );
+
+    let mut deduce = |code: &str| match infer(&Term::parse(code).unwrap(), &mut env, &mut gen) {
+        Ok(ty) => ty.to_string(),
+        Err(error) => error.node.to_string(),
+    };
+    assert_eq!(
+        deduce(r"let r = ref(nil) in let u = r := cons(1, nil) in if head(!r) then 1 else 2"),
+        "Cannot unify int with bool"
+    );
+    assert_eq!(
+        deduce(r"let r = ref(\x -> x) in let u = r := \x -> x + 1 in (!r)(true)"),
+        "Cannot unify int with bool"
+    );
+    assert_eq!(
+        deduce(
+            r"let box = ref in let r = box(nil) in let u = r := cons(1, nil) in if head(!r) then 1 else 2"
+        ),
+        "Cannot unify int with bool"
+    );
+    assert_eq!(
+        deduce(r"let rec box = ref and r = box(nil) in let u = r := cons(1, nil) in !r"),
+        "list[int]"
+    );
+    assert_eq!(
+        deduce(r"let r = ref(nil) in let s = r in let u = s := cons(1, nil) in !r"),
+        "list[int]"
+    );
+    assert_eq!(
+        deduce(r"let rec r = ref(nil) and u = r := cons(1, nil) in !r"),
+        "list[int]"
+    );
+    assert_eq!(
+        deduce(r"let f = id(id) in (f(1), f(true))"),
+        "Cannot unify int with bool"
+    );
+    assert_eq!(
+        deduce(r"let f = \x -> id(x) in (f(1), f(true))"),
+        "(int, bool)"
+    );
+    assert_eq!(
+        deduce(r"let p = {xs = nil, f = \x -> x} in (cons(1, p.xs), p.f(true))"),
+        "(list[int], bool)"
+    );
+}
+
+#[test]
+fn structured_errors() {
+    let mut env = Env::new();
+    let mut gen = Gen::new();
+    env.insert("one", Type::from_str("int", &mut gen).unwrap());
+    env.insert(
+        "plus",
+        Type::from_str("(int, int) -> int", &mut gen).unwrap(),
+    );
+    for (name, ty) in [("short", "t[int]"), ("long", "t[int, bool]")] {
+        env.insert(name, Type::from_str(ty, &mut gen).unwrap());
+    }
+    let pair = Decl::parse("type pair = pair(int, int)").unwrap();
+    declare(&pair, &mut env, &mut gen).unwrap();
+
+    let mut deduce = |code: &str| {
+        infer(&Term::parse(code).unwrap(), &mut env, &mut gen).map_err(|error| error.node)
+    };
+
+    assert_eq!(
+        deduce("plus(one, plus)"),
+        Err(TypeError::Mismatch {
+            expected: Type::Const(String::from("int")),
+            found: Type::from_str("(int, int) -> int", &mut Gen::new()).unwrap(),
+        })
+    );
+    assert_eq!(
+        deduce("plus(one)"),
+        Err(TypeError::ArityMismatch {
+            expected: 2,
+            found: 1
+        })
+    );
+    // the expected number is always the one the callee or the constructor declares
+    assert_eq!(
+        deduce(r"match pair(1, 2) { pair(x) -> x }"),
+        Err(TypeError::ArityMismatch {
+            expected: 2,
+            found: 1
+        })
+    );
+    assert_eq!(
+        deduce("x"),
+        Err(TypeError::UndefinedVariable(String::from("x")))
+    );
+    assert_eq!(
+        deduce("one(one)"),
+        Err(TypeError::NotAFunction(Type::Const(String::from("int"))))
+    );
+    assert!(matches!(
+        deduce("\\x -> x(x)"),
+        Err(TypeError::InfiniteType { .. })
+    ));
+    for code in [
+        "\\r f -> (f(r), f({x = 1 | r}))",
+        "\\r f -> (f({x = 1 | r}), f({y = true | r}))",
+        "\\r s f -> (f({x = 1 | r}), f({y = r | s}))",
+    ] {
+        assert!(matches!(deduce(code), Err(TypeError::InfiniteType { .. })));
+    }
+    assert!(matches!(
+        deduce("\\x x -> x"),
+        Err(TypeError::DuplicateBinder { name, .. }) if name == "x"
+    ));
+
+    assert_eq!(
+        deduce("if one then one else plus"),
+        Err(TypeError::Mismatch {
+            expected: Type::Const(String::from("bool")),
+            found: Type::Const(String::from("int")),
+        })
+    );
+    assert!(matches!(
+        deduce("if true then one else plus"),
+        Err(TypeError::BranchMismatch { .. })
+    ));
+
+    assert!(matches!(
+        deduce("let f : forall[a] a -> a = plus in f"),
+        Err(TypeError::LessGeneral { declared, inferred })
+            if declared.to_string() == "forall[a] a -> a"
+                && inferred.to_string() == "(int, int) -> int"
+    ));
+
+    assert_eq!(
+        deduce(r"(\x -> x : foo -> foo)"),
+        Err(TypeError::UndefinedType(String::from("foo")))
+    );
+    assert_eq!(
+        deduce(r"(\x -> x : forall[a] a -> b)"),
+        Err(TypeError::UndefinedType(String::from("b")))
+    );
+    assert_eq!(
+        deduce(r"(\f -> f(f) : (forall[a] a -> a) -> {})"),
+        Err(TypeError::NestedForall(
+            Ty::parse("forall[a] a -> a").unwrap().node
+        ))
+    );
+    assert!(Type::from_str("forall[a] a -> forall[b] b", &mut Gen::new()).is_err());
+
+    assert!(matches!(
+        deduce(r"(\f -> f(one))(plus)"),
+        Err(TypeError::UnequalArity {
+            arities: (1, 2),
+            ..
+        })
+    ));
+    assert_eq!(
+        deduce(r"\f -> (f(long), f(short))"),
+        Err(TypeError::UnequalArity {
+            expected: Type::from_str("t[int, bool]", &mut Gen::new()).unwrap(),
+            found: Type::from_str("t[int]", &mut Gen::new()).unwrap(),
+            arities: (2, 1),
+        })
+    );
+
+    let error = infer(
+        &Term::parse("plus(one,\n  plus)").unwrap(),
+        &mut env,
+        &mut gen,
+    )
+    .unwrap_err();
+    assert_eq!(
+        (error.span.start.to_string(), error.span.end.to_string()),
+        (String::from("2:3"), String::from("2:7"))
+    );
+}
+
+#[test]
+fn kind_checks() {
+    let mut env = Env::new();
+    let mut gen = Gen::new();
+    let mut declare = |code: &str| {
+        declare(&Decl::parse(code).unwrap(), &mut env, &mut gen).map_err(|error| error.node)
+    };
+    declare("type list[a] = nil | cons(a, list[a])").unwrap();
+    declare("class Eq[a] { eq : (a, a) -> bool }").unwrap();
+
+    let mismatch = |name: &str, expected, found| TypeError::KindMismatch {
+        name: String::from(name),
+        expected,
+        found,
+    };
+    assert_eq!(declare("type t = c(list)"), Err(mismatch("list", 1, 0)));
+    assert_eq!(
+        mismatch("list", 1, 0).to_string(),
+        "Type list takes 1 parameter, found 0"
+    );
+    assert_eq!(
+        mismatch("int", 0, 1).to_string(),
+        "Type int takes 0 parameters, found 1"
+    );
+    assert_eq!(declare("type t[a] = c(t[a, a])"), Err(mismatch("t", 1, 2)));
+    assert_eq!(
+        declare("class Show[a] { show : ref[a, a] -> string }"),
+        Err(mismatch("ref", 1, 2))
+    );
+    assert_eq!(
+        declare("instance Eq[list[a, b]]"),
+        Err(mismatch("list", 1, 2))
+    );
+    assert_eq!(
+        declare("type t = c({x : int | int})"),
+        Err(TypeError::NotARow(Ty::Const(String::from("int"))))
+    );
+    assert!(declare("type obj[r] = obj({x : int | r})").is_ok());
+    assert_eq!(
+        infer(
+            &Term::parse("(nil : int[list])").unwrap(),
+            &mut env,
+            &mut gen
+        )
+        .map_err(|error| error.node),
+        Err(mismatch("int", 0, 1))
+    );
+    // a quantified variable shadows the declared type and takes no parameters
+    assert!(Type::from_str_with("forall[list] list -> list", &mut gen, &env).is_ok());
+    assert_eq!(
+        Type::from_str_with("forall[a, b] list[a, b] -> a", &mut gen, &env),
+        Err(crate::Error::Type(Box::new(mismatch("list", 1, 2).into())))
+    );
+    // only the builtin types are known without the environment
+    assert!(Type::from_str("list[a, b]", &mut gen).is_ok());
+    assert!(Type::from_str("ref[a, b]", &mut gen).is_err());
+
+    // a row is extended by a variable only
+    assert!(Type::from_str_with("forall[a, r] [A : a | r] -> a", &mut gen, &env).is_ok());
+    assert_eq!(
+        Type::from_str_with("forall[a] {x : a | list[a]} -> a", &mut gen, &env)
+            .map_err(|error| error.to_string()),
+        Err(String::from("Type list[a] is not a row variable to extend"))
+    );
+    assert_eq!(
+        infer(
+            &Term::parse(r"(\r -> r.x : forall[a] {x : a | {y : int}} -> a)").unwrap(),
+            &mut env,
+            &mut gen
+        )
+        .map_err(|error| error.node.to_string()),
+        Err(String::from(
+            "Type {y : int} is not a row variable to extend"
+        ))
+    );
+}
+
+#[test]
+fn system_f() {
+    use crate::typecheck;
+
+    let mut env = Env::new();
+    let mut gen = Gen::new();
+    for (name, ty) in [("ref", "forall[a] a -> ref[a]"), ("+", "(int, int) -> int")] {
+        env.insert(name, Type::from_str(ty, &mut gen).unwrap());
+    }
+    // every elaborated term has the type of the name it defines and reads back the same
+    let mut elaborate = |code: &str| {
+        let (_, terms) = elaborate_decl(&Decl::parse(code).unwrap(), &mut env, &mut gen)
+            .map_err(|error| error.node.to_string())?;
+        let context = Context::from(&env);
+        let mut lines = Vec::new();
+        for (name, term) in terms {
+            let ty = typecheck(&term, &context).map_err(|error| error.node.to_string())?;
+            assert!(context.equivalent(context.lookup(&name).unwrap(), &ty));
+            let parsed = SystemF::parse(&term.to_string()).unwrap();
+            assert_eq!(parsed.to_string(), term.to_string());
+            assert_eq!(typecheck(&parsed, &context), Ok(ty));
+            lines.push(normalized(&format!("{} = {}", name, term)));
+        }
+        Ok::<Vec<String>, String>(lines)
+    };
+
+    elaborate("type list[a] = nil | cons(a, list[a])").unwrap();
+    assert_eq!(
+        elaborate(r"let id = \x -> x"),
+        Ok(vec![String::from(r"id = Λt1. \(x : t1) -> x")])
+    );
+    assert_eq!(
+        elaborate(r"let pairs = \x -> let f = \y -> (x, y) in (f(1), f(cons(x, nil)))"),
+        Ok(vec![String::from(
+            r"pairs = Λt1. \(x : t1) -> let f = Λt2. \(y : t2) -> (x, y) in (f[int](1), f[list[t1]](cons[t1](x, nil[t1])))"
+        )])
+    );
+    assert_eq!(
+        elaborate(r"let rec len = \xs -> match xs { nil -> 0, cons(_, t) -> 1 + len(t) }"),
+        Ok(vec![String::from(
+            r"len = Λt1. let rec len : forall[t2] list[t2] -> int = Λt2. \(xs : list[t2]) -> match xs { nil -> 0, cons(_, t) -> (1 + len[t2](t)) } in len[t1]"
+        )])
+    );
+    // a variable of the other binding of the group is never constrained in this one
+    assert_eq!(
+        elaborate(r"let rec f = \x -> let u = g(nil) in x and g = \y -> let v = f(1) in y"),
+        Ok(vec![
+            String::from(
+                r"f = let rec f : int -> int = \(x : int) -> let u = g[{}](nil[{}]) in x and g : forall[t1] list[t1] -> list[t1] = Λt1. \(y : list[t1]) -> let v = f(1) in y in f"
+            ),
+            String::from(
+                r"g = Λt1. let rec f : int -> int = \(x : int) -> let u = g[{}](nil[{}]) in x and g : forall[t2] list[t2] -> list[t2] = Λt2. \(y : list[t2]) -> let v = f(1) in y in g[t1]"
+            ),
+        ])
+    );
+    assert_eq!(
+        elaborate(r"let get = (\r -> r.x : forall[a, r] {x : a | r} -> a)"),
+        Ok(vec![String::from(
+            r"get = Λt1 t2. (Λt3 t4. \(r : {x : t3 | t4}) -> r.x)[t1, t2]"
+        )])
+    );
+    assert_eq!(
+        elaborate(r"let tag = \r -> { y = `Some(r.x) | {r - x} }"),
+        Ok(vec![String::from(
+            r"tag = Λt1 t2 t3. \(r : {x : t1 | t2}) -> {y = (`Some(r.x) : [Some : t1 | t3]) | {r - x}}"
+        )])
+    );
+    assert_eq!(
+        elaborate(r"let cell = ref(nil)"),
+        Ok(vec![String::from(r"cell = ref[list[_weak1]](nil[_weak1])")])
+    );
+
+    elaborate("class Eq[a] { eq : (a, a) -> bool }").unwrap();
+    elaborate("instance Eq[int]").unwrap();
+    assert_eq!(
+        elaborate(
+            r"instance Eq[a] => Eq[list[a]] { eq = \xs ys -> match (xs, ys) {
+                (nil, nil) -> true,
+                (cons(x, xs), cons(y, ys)) -> if eq(x, y) then eq(xs, ys) else false,
+                _ -> false
+            } }"
+        ),
+        Ok(vec![String::from(
+            r"dict_Eq_list = Λt1. \(dict_Eq_1 : Eq[t1]) -> {eq = \(xs : list[t1], ys : list[t1]) -> match (xs, ys) { (nil, nil) -> true, (cons(x, xs), cons(y, ys)) -> if eq[t1](dict_Eq_1)(x, y) then eq[list[t1]](dict_Eq_list[t1](dict_Eq_1))(xs, ys) else false, _ -> false }}"
+        )])
+    );
+    assert_eq!(
+        elaborate(r"let same = \x -> eq(cons(x, nil), cons(1, nil))"),
+        Ok(vec![String::from(
+            r"same = \(x : int) -> eq[list[int]](dict_Eq_list[int](dict_Eq_int))(cons[int](x, nil[int]), cons[int](1, nil[int]))"
+        )])
+    );
+    assert_eq!(
+        elaborate(r"let annotated = (\x y -> eq(x, y) : forall[a] Eq[a] => (a, a) -> bool)"),
+        Ok(vec![String::from(
+            r"annotated = Λt1. \(dict_Eq_1 : Eq[t1]) -> (Λt2. \(dict_Eq_2 : Eq[t2]) -> \(x : t2, y : t2) -> eq[t2](dict_Eq_2)(x, y))[t1](dict_Eq_1)"
+        )])
+    );
+
+    // the top level term is abstracted over its generalized variables
+    let (ty, term) =
+        super::elaborate(&Term::parse(r"\x -> (x, `A)").unwrap(), &mut env, &mut gen).unwrap();
+    assert_eq!(
+        typecheck(&term, &Context::from(&env)).map(|ty| normalized(&ty.to_string())),
+        Ok(String::from("forall[t1, t2] t1 -> (t1, [A : {} | t2])"))
+    );
+    assert_eq!(ty.to_string(), "forall[a, r] a -> (a, [A : {} | r])");
+
+    // the terms built in code have no spans to tell their uses apart
+    let var = |name: &str| Spanned::from(Term::Var(String::from(name)));
+    let call = |arg| Spanned::from(Term::App(Box::new(var("f")), vec![Spanned::from(arg)]));
+    let id = Term::Abs(vec![String::from("y")], Box::new(var("y")));
+    let uses = Term::Tuple(vec![
+        call(Term::Lit(Literal::Int(1))),
+        call(Term::Lit(Literal::Bool(true))),
+    ]);
+    let term = Term::Let(
+        String::from("f"),
+        Box::new(Spanned::from(id)),
+        Box::new(Spanned::from(uses)),
+    );
+    let (_, term) = super::elaborate(&Spanned::from(term), &mut env, &mut gen).unwrap();
+    assert_eq!(
+        typecheck(&term, &Context::from(&env)).map(|ty| ty.to_string()),
+        Ok(String::from("(int, bool)"))
+    );
+
+    // a name shadowing a constructor leaves the patterns to the constructor
+    let shadowed = Term::parse(r"\l -> let nil = 0 in match l { nil -> nil, cons(x, _) -> x }");
+    let (_, term) = super::elaborate(&shadowed.unwrap(), &mut env, &mut gen).unwrap();
+    assert_eq!(
+        typecheck(&term, &Context::from(&env)).map(|ty| ty.to_string()),
+        Ok(String::from("list[int] -> int"))
+    );
+}
+
+#[test]
+fn sessions() {
+    let mut session = Session::default();
+    for (name, ty) in [
+        ("ref", "forall[a] a -> ref[a]"),
+        (":=", "forall[a] (ref[a], a) -> {}"),
+        ("not", "bool -> bool"),
+        ("one", "int"),
+    ] {
+        session.define(name, ty).unwrap();
+    }
+    let declare = |session: &mut Session, code: &str| {
+        session
+            .declare(&Decl::parse(code).unwrap())
+            .map(|types| types.len())
+            .map_err(|error| error.node.to_string())
+    };
+    let infer = |session: &mut Session, code: &str| {
+        session
+            .infer(&Term::parse(code).unwrap())
+            .map(|ty| ty.to_string())
+            .map_err(|error| error.node.to_string())
+    };
+
+    assert_eq!(
+        declare(&mut session, "type list[a] = nil | cons(a, list[a])"),
+        Ok(2)
+    );
+    assert_eq!(declare(&mut session, r"let r = ref(nil)"), Ok(1));
+
+    // a query keeps what it learns of the weak variables, unless it fails
+    assert_eq!(
+        infer(&mut session, "r := cons(not(1), nil)"),
+        Err(String::from("Cannot unify bool with int"))
+    );
+    assert_eq!(
+        infer(&mut session, "r := cons(1, nil)"),
+        Ok(String::from("{}"))
+    );
+    assert_eq!(
+        infer(&mut session, "r := cons(true, nil)"),
+        Err(String::from("Cannot unify bool with int"))
+    );
+    assert_eq!(declare(&mut session, "let u = r := cons(1, nil)"), Ok(1));
+    let names = session.names();
+    let r = names.iter().find(|(name, _)| *name == "r");
+    assert_eq!(
+        r.map(|(_, t)| t.to_string()),
+        Some(String::from("ref[list[int]]"))
+    );
+
+    // the variables of a query that is not a value are weak as well
+    assert!(infer(&mut session, "ref(nil)")
+        .unwrap()
+        .starts_with("ref[list[_weak"));
+    assert_eq!(declare(&mut session, r"let s = ref(nil)"), Ok(1));
+    let (_, diagnostics) = session.diagnose(&Term::parse("s := cons(true, nil)").unwrap());
+    assert!(diagnostics.is_empty());
+    let (_, diagnostics) = session.diagnose(&Term::parse("s := cons(1, nil)").unwrap());
+    assert_eq!(diagnostics.len(), 1);
+
+    // a query failing under a shadowing let leaves the shadowed name
+    assert_eq!(
+        infer(&mut session, "let one = true in not(one)(one)"),
+        Err(String::from("Invalid type of function: bool"))
+    );
+    assert_eq!(infer(&mut session, "one"), Ok(String::from("int")));
+
+    // a failing declaration is not kept in part
+    assert_eq!(
+        declare(&mut session, "class Show[a] { show : a -> int }"),
+        Ok(1)
+    );
+    assert!(declare(&mut session, r"instance Show[int] { show = \x -> not(x) }").is_err());
+    assert_eq!(
+        infer(&mut session, "show(1)"),
+        Err(String::from("No instance of Show for int"))
+    );
+
+    // a defined name is qualified as a declared method is
+    let mut define = |name: &str, ty: &str| match session.define(name, ty) {
+        Ok(ty) => ty.to_string(),
+        Err(Error::Type(error)) => error.node.to_string(),
+        Err(error) => error.to_string(),
+    };
+    assert_eq!(
+        define("display", "forall[a] Show[a] => a -> string"),
+        "forall[a] Show[a] => a -> string"
+    );
+    assert_eq!(
+        define("compare", "forall[a] Ord[a] => (a, a) -> bool"),
+        "Undefined class 'Ord'"
+    );
+    assert_eq!(
+        define("default", "forall[a, b] Show[a] => b"),
+        "Ambiguous type variable in a constraint of class Show"
+    );
+    assert!(session.env().lookup("compare").is_none());
+    assert!(session.env().lookup("default").is_none());
+
+    // the fresh variables are never reused
+    let mut elaborate = |code: &str| {
+        session
+            .elaborate(&Term::parse(code).unwrap())
+            .unwrap()
+            .1
+            .to_string()
+    };
+    assert_ne!(elaborate(r"\x -> x"), elaborate(r"\x -> x"));
+
+    assert_eq!(
+        session.tentatively(|session| declare(session, "type box[a] = box(a)")),
+        Ok(1)
+    );
+    assert!(session.env().lookup_type("box").is_none());
+    assert!(session.env().lookup_constructor("box").is_none());
+
+    // a session started over, as on a reset, lists the weak variables its own queries bound
+    let mut session = Session::default();
+    session.define("ref", "forall[a] a -> ref[a]").unwrap();
+    session.define(":=", "forall[a] (ref[a], a) -> {}").unwrap();
+    for code in ["type list[a] = nil | cons(a, list[a])", "let r = ref(nil)"] {
+        declare(&mut session, code).unwrap();
+    }
+    let listed = |session: &Session| {
+        session
+            .names()
+            .into_iter()
+            .find(|(name, _)| *name == "r")
+            .map(|(_, t)| t.to_string())
+    };
+    assert!(listed(&session).unwrap().starts_with("ref[list[_weak"));
+    session.tentatively(|session| infer(session, "r := cons(true, nil)").unwrap());
+    assert!(listed(&session).unwrap().starts_with("ref[list[_weak"));
+    infer(&mut session, "r := cons(1, nil)").unwrap();
+    assert_eq!(listed(&session), Some(String::from("ref[list[int]]")));
+}
+
+#[test]
+fn scopes() {
+    let mut env = Env::new();
+    let mut gen = Gen::new();
+    env.insert("x", Type::from_str("int", &mut gen).unwrap());
+    env.insert("not", Type::from_str("bool -> bool", &mut gen).unwrap());
+
+    // the inference fails inside the scope of every binder, which still restores the outer x
+    for code in [
+        r"let x = true in not(x)(1)",
+        r"let rec x = not(x) in x(1)",
+        r"\x -> (not(x), x(1))",
+        r"match true { x -> not(x)(1) }",
+        r"let y = 1 in let x = true in let z = x in z(y)",
+    ] {
+        let term = Term::parse(code).unwrap();
+        assert!(Infer::new(&mut env, &mut gen).infer(&term, 0).is_err());
+        assert_eq!(env.lookup("x"), Some(&Type::Const(String::from("int"))));
+        assert_eq!(env.iter().count(), 2);
+    }
+}
+
+#[test]
+fn error_recovery() {
+    let mut env = Env::new();
+    let mut gen = Gen::new();
+    for (name, ty) in [
+        ("one", "int"),
+        ("not", "bool -> bool"),
+        ("+", "(int, int) -> int"),
+    ] {
+        env.insert(name, Type::from_str(ty, &mut gen).unwrap());
+    }
+    let mut diagnose = |code: &str| {
+        let (ty, diagnostics) = diagnose(&Term::parse(code).unwrap(), &mut env, &mut gen);
+        let diagnostics: Vec<String> = diagnostics
+            .iter()
+            .map(|error| error.node.to_string())
+            .collect();
+        (normalized(&ty.to_string()), diagnostics)
+    };
+
+    assert_eq!(
+        diagnose(r"\x -> x"),
+        (String::from("forall[a] a -> a"), Vec::new())
+    );
+    assert_eq!(
+        diagnose(r"(not(1), 1 + true, one)"),
+        (
+            String::from("(bool, int, int)"),
+            vec![
+                String::from("Cannot unify bool with int"),
+                String::from("Cannot unify int with bool"),
+            ]
+        )
+    );
+    assert_eq!(
+        diagnose(r"let f = \x -> x + true in f(one)(2)"),
+        (
+            String::from("?"),
+            vec![
+                String::from("Cannot unify int with bool"),
+                String::from("Invalid type of function: int"),
+            ]
+        )
+    );
+    assert_eq!(
+        diagnose(r"\x -> (x, y, if 1 then x else z)"),
+        (
+            String::from("forall[a] a -> (a, ?, a)"),
+            vec![
+                String::from("Undefined variable 'y'"),
+                String::from("Cannot unify bool with int"),
+                String::from("Undefined variable 'z'"),
+            ]
+        )
+    );
+
+    // a recursive binding is rejected without being made, and the inference goes on
+    assert_eq!(
+        diagnose(r"\x -> x(x)"),
+        (
+            String::from("forall[a, b] (a -> b) -> b"),
+            vec![String::from("Infinite type")]
+        )
+    );
+    assert_eq!(
+        diagnose(r"\x -> let y = x in (y(y), not(1), y)"),
+        (
+            String::from("forall[a, b] (a -> b) -> (b, bool, a -> b)"),
+            vec![
+                String::from("Infinite type"),
+                String::from("Cannot unify bool with int"),
+            ]
+        )
+    );
+
+    // the error type unifies with anything without causing errors of its own
+    assert_eq!(
+        diagnose(r"let g = h in (g(1), g.x, not(g), match g { 1 -> g, _ -> true })"),
+        (
+            String::from("(?, _weak1, bool, bool)"),
+            vec![String::from("Undefined variable 'h'")]
+        )
+    );
+}
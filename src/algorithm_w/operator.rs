@@ -0,0 +1,159 @@
+/*
+ * Copyright (c) 2021, Andrei Yaskovets
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+use std::collections::HashMap;
+
+use tokenstream::{fmap, satisfy, Parser, Spanned};
+
+use crate::{keyword, Token};
+
+pub type Precedence = u8;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Assoc {
+    Left,
+    Right,
+    None,
+}
+
+// the symbols the lexer knows that no syntax takes, they stand for functions in parentheses
+pub fn is_operator(name: &str) -> bool {
+    matches!(
+        name,
+        "+" | "-"
+            | "*"
+            | "/"
+            | "%"
+            | "<"
+            | ">"
+            | "<="
+            | ">="
+            | "=="
+            | "!="
+            | "and"
+            | "or"
+            | "not"
+            | "!"
+            | ":="
+    )
+}
+
+// the prefix operators of the default table, which the terms are printed with
+const PREFIX: [(&str, Precedence); 2] = [("not", 4), ("!", 8)];
+
+pub fn is_prefix(name: &str) -> bool {
+    PREFIX.iter().any(|(op, _)| *op == name)
+}
+
+// the table declares the operator symbols and any identifier that is not a keyword
+fn is_declarable(name: &str) -> bool {
+    let mut chars = name.chars();
+    is_operator(name)
+        || matches!(chars.next(), Some('a'..='z' | 'A'..='Z' | '_'))
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && keyword(name).is_none()
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Operators {
+    infix: HashMap<String, (Precedence, Assoc)>,
+    prefix: HashMap<String, Precedence>,
+}
+
+impl Operators {
+    pub fn new() -> Self {
+        Operators {
+            infix: HashMap::new(),
+            prefix: HashMap::new(),
+        }
+    }
+
+    // false if the name cannot be an operator
+    pub fn insert_infix(&mut self, name: &str, precedence: Precedence, assoc: Assoc) -> bool {
+        if !is_declarable(name) {
+            return false;
+        }
+        self.infix.insert(name.to_owned(), (precedence, assoc));
+        true
+    }
+
+    pub fn insert_prefix(&mut self, name: &str, precedence: Precedence) -> bool {
+        if !is_declarable(name) {
+            return false;
+        }
+        self.prefix.insert(name.to_owned(), precedence);
+        true
+    }
+
+    pub fn remove_infix(&mut self, name: &str) {
+        self.infix.remove(name);
+    }
+
+    pub fn infix(&self, name: &str) -> Option<(Precedence, Assoc)> {
+        self.infix.get(name).copied()
+    }
+
+    pub fn prefix(&self, name: &str) -> Option<Precedence> {
+        self.prefix.get(name).copied()
+    }
+}
+
+impl Default for Operators {
+    fn default() -> Self {
+        let mut operators = Operators::new();
+        for (names, precedence, assoc) in [
+            (&[":="][..], 1, Assoc::None),
+            (&["or"], 2, Assoc::Right),
+            (&["and"], 3, Assoc::Right),
+            (&["==", "!=", "<", ">", "<=", ">="], 4, Assoc::None),
+            (&["+", "-"], 6, Assoc::Left),
+            (&["*", "/", "%"], 7, Assoc::Left),
+        ] {
+            for name in names {
+                operators.insert_infix(name, precedence, assoc);
+            }
+        }
+        for (name, precedence) in PREFIX {
+            operators.insert_prefix(name, precedence);
+        }
+        operators
+    }
+}
+
+pub(crate) fn operator(
+    accept: impl Fn(&str) -> bool + 'static,
+) -> Parser<'static, Spanned<Token>, Spanned<String>> {
+    fmap(
+        |t: Spanned<Token>| t.map(|t| t.to_string()),
+        satisfy(move |t: Spanned<Token>| accept(&t.node.to_string())),
+    )
+    .label("operator")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Assoc, Operators};
+
+    #[test]
+    fn table() {
+        let mut operators = Operators::default();
+        assert_eq!(operators.infix("+"), Some((6, Assoc::Left)));
+        assert_eq!(operators.infix("not"), None);
+        assert_eq!(operators.prefix("not"), Some(4));
+
+        assert!(operators.insert_infix("+", 1, Assoc::Right));
+        assert_eq!(operators.infix("+"), Some((1, Assoc::Right)));
+        assert!(!operators.insert_prefix("->", 1));
+        assert!(!operators.insert_infix("let", 1, Assoc::Left));
+        assert!(!operators.insert_infix("1", 1, Assoc::Left));
+
+        // the identifiers become operators when the table declares them
+        assert!(operators.insert_infix("mod", 7, Assoc::Left));
+        assert_eq!(operators.infix("mod"), Some((7, Assoc::Left)));
+        assert_eq!(operators.infix("-"), Some((6, Assoc::Left)));
+    }
+}
@@ -0,0 +1,142 @@
+/*
+ * Copyright (c) 2021, Andrei Yaskovets
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+use tokenstream::{bind, fmap, spanned, Parser, Spanned};
+
+use crate::{comma_list1, lazy, literal, name, parens, spaced, token, Literal, Token};
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum Pattern {
+    Wildcard,
+    Var(String),
+    Lit(Literal),
+    Constructor(String, Vec<Spanned<Pattern>>),
+    Tuple(Vec<Spanned<Pattern>>),
+    Variant(String, Option<Box<Spanned<Pattern>>>),
+}
+
+impl std::fmt::Display for Pattern {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            Pattern::Wildcard => write!(fmt, "_"),
+            Pattern::Var(name) => write!(fmt, "{}", name),
+            Pattern::Lit(literal) => write!(fmt, "{}", literal),
+            Pattern::Constructor(name, args) => {
+                write!(fmt, "{}", name)?;
+                if !args.is_empty() {
+                    write!(fmt, "(")?;
+                    for (i, arg) in args.iter().enumerate() {
+                        if i > 0 {
+                            write!(fmt, ", ")?;
+                        }
+                        write!(fmt, "{}", arg)?;
+                    }
+                    write!(fmt, ")")?;
+                }
+                Ok(())
+            }
+            Pattern::Tuple(items) => {
+                write!(fmt, "({}", items[0])?;
+                for item in items.iter().skip(1) {
+                    write!(fmt, ", {}", item)?;
+                }
+                write!(fmt, ")")
+            }
+            Pattern::Variant(label, None) => write!(fmt, "`{}", label),
+            Pattern::Variant(label, Some(payload)) => match payload.node {
+                Pattern::Tuple(_) => write!(fmt, "`{}{}", label, payload),
+                _ => write!(fmt, "`{}({})", label, payload),
+            },
+        }
+    }
+}
+
+// a bare name is a variable until the type checker finds a constructor with that name
+pub(crate) fn pattern() -> Parser<'static, Spanned<Token>, Spanned<Pattern>> {
+    let p_lit = fmap(Pattern::Lit, literal());
+    let p_named = bind(name(), |name| {
+        let bare = match name.as_str() {
+            "_" => Pattern::Wildcard,
+            _ => Pattern::Var(name.clone()),
+        };
+        fmap(
+            move |args| Pattern::Constructor(name.clone(), args),
+            parens(comma_list1(spaced(pattern()))),
+        ) | Parser::pure(bare)
+    });
+
+    // a parenthesized list of two or more patterns is a tuple
+    fn parenthesized() -> Parser<'static, Spanned<Token>, Pattern> {
+        fmap(
+            |mut items: Vec<Spanned<Pattern>>| match items.len() {
+                1 => items.remove(0).node,
+                _ => Pattern::Tuple(items),
+            },
+            parens(comma_list1(spaced(pattern()))),
+        )
+    }
+    let p_parens = lazy!(parenthesized());
+    let p_variant = bind(token(Token::Backtick) >> name(), |label| {
+        let _label = label.clone();
+        fmap(
+            move |payload| Pattern::Variant(label.clone(), Some(Box::new(payload))),
+            spanned(parenthesized()),
+        ) | Parser::pure(Pattern::Variant(_label, None))
+    });
+
+    spanned(p_lit | p_named | p_parens | p_variant).label("pattern")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pattern, Pattern, Pattern::*};
+    use crate::{eof, parse, Literal};
+
+    fn collect(s: &str) -> Option<Pattern> {
+        parse(pattern() << eof(), s)
+            .ok()
+            .map(|pattern| pattern.node)
+    }
+
+    #[test]
+    fn patterns() {
+        assert_eq!(collect("_"), Some(Wildcard));
+        assert_eq!(collect("xs"), Some(Var(String::from("xs"))));
+        assert_eq!(collect("-1"), Some(Lit(Literal::Int(-1))));
+        assert_eq!(
+            collect("cons(x, _)"),
+            Some(Constructor(
+                String::from("cons"),
+                vec![Var(String::from("x")).into(), Wildcard.into()]
+            ))
+        );
+        assert_eq!(
+            collect("cons( some(\"a\") , nil)").unwrap().to_string(),
+            String::from("cons(some(\"a\"), nil)")
+        );
+        assert_eq!(collect("nil()"), None);
+        assert_eq!(collect("f(x"), None);
+        assert_eq!(collect("\\x"), None);
+        assert_eq!(
+            collect("`Ok( (x, `None) )"),
+            Some(Variant(
+                String::from("Ok"),
+                Some(Box::new(
+                    Tuple(vec![
+                        Var(String::from("x")).into(),
+                        Variant(String::from("None"), None).into()
+                    ])
+                    .into()
+                ))
+            ))
+        );
+        assert_eq!(collect("`Ok(x, y)").unwrap().to_string(), "`Ok(x, y)");
+        for pattern in ["`Ok(-1)", "`Ok(cons(x, `None))", "`Ok(`Some(_), 1.5)"] {
+            assert_eq!(collect(pattern).unwrap().to_string(), pattern);
+        }
+    }
+}
@@ -5,38 +5,121 @@
  * LICENSE file in the root directory of this source tree.
  */
 
-use tokenstream::{bind, fmap, Parser, Stream, Tokenizer};
+use std::rc::Rc;
 
-use crate::{comma_list, eof, ident, lazy, many_space, parens, spaced, token, Token};
+use tokenstream::{bind, fmap, spanned, Parser, Spanned};
+
+use crate::{
+    braces, comma_list, comma_list1, eof, forall, ident, is_operator, is_prefix, lazy, many_space,
+    number, operator, parens, parse, pattern, spaced, string, token, Assoc, Operators, ParseError,
+    Pattern, Precedence, Token, Ty,
+};
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum Literal {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+}
+
+impl std::fmt::Display for Literal {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            Literal::Int(int) => write!(fmt, "{}", int),
+            Literal::Float(float) => write!(fmt, "{:?}", float),
+            Literal::Bool(bool) => write!(fmt, "{}", bool),
+            Literal::Str(string) => write!(fmt, "{}", Token::Str(string.clone())),
+        }
+    }
+}
 
 #[derive(PartialEq, Clone, Debug)]
 pub enum Term {
+    Lit(Literal),
     Var(String),
-    Let(String, Box<Term>, Box<Term>),
-    Abs(Vec<String>, Box<Term>),
-    App(Box<Term>, Vec<Term>),
+    Let(String, Box<Spanned<Term>>, Box<Spanned<Term>>),
+    LetRec(Vec<(String, Spanned<Term>)>, Box<Spanned<Term>>),
+    Abs(Vec<String>, Box<Spanned<Term>>),
+    App(Box<Spanned<Term>>, Vec<Spanned<Term>>),
+    If(Box<Spanned<Term>>, Box<Spanned<Term>>, Box<Spanned<Term>>),
+    Tuple(Vec<Spanned<Term>>),
+    Record(Vec<(String, Spanned<Term>)>, Option<Box<Spanned<Term>>>),
+    Select(Box<Spanned<Term>>, String),
+    Restrict(Box<Spanned<Term>>, String),
+    Variant(String, Option<Box<Spanned<Term>>>),
+    Annot(Box<Spanned<Term>>, Spanned<Ty>),
+    Match(Box<Spanned<Term>>, Vec<(Spanned<Pattern>, Spanned<Term>)>),
+}
+
+impl Term {
+    pub fn parse(s: &str) -> Result<Spanned<Term>, ParseError> {
+        Term::parse_with(s, &Operators::default())
+    }
+
+    pub fn parse_with(s: &str, ops: &Operators) -> Result<Spanned<Term>, ParseError> {
+        parse(term(Rc::new(ops.clone())) << eof(), s)
+    }
 }
 
 impl std::str::FromStr for Term {
-    type Err = String;
+    type Err = ParseError;
     fn from_str(s: &str) -> std::result::Result<Self, <Self as std::str::FromStr>::Err> {
-        let mut lexer = Stream::new(Tokenizer::new(s.chars()));
-        let parser = term() << eof();
-        parser
-            .run(&mut lexer)
-            .ok_or(format!("Error while parsing {}", s))
+        Term::parse(s).map(|term| term.node)
     }
 }
 
+// whether the term extends as far to the right as it can, taking in whatever follows it
+fn open(term: &Term) -> bool {
+    matches!(
+        term,
+        Term::Let(_, _, _)
+            | Term::LetRec(_, _)
+            | Term::Abs(_, _)
+            | Term::If(_, _, _)
+            | Term::Match(_, _)
+    )
+}
+
+// whether the term is printed as a head that calls and selections chain to
+fn postfix(term: &Term) -> bool {
+    matches!(
+        term,
+        Term::Var(_)
+            | Term::App(_, _)
+            | Term::Tuple(_)
+            | Term::Record(_, _)
+            | Term::Select(_, _)
+            | Term::Restrict(_, _)
+            | Term::Annot(_, _)
+    )
+}
+
 impl std::fmt::Display for Term {
     fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
         match self {
+            Term::Lit(literal) => {
+                write!(fmt, "{}", literal)
+            }
+            Term::Var(name) if is_operator(name) => {
+                write!(fmt, "({})", name)
+            }
             Term::Var(name) => {
                 write!(fmt, "{}", name)
             }
             Term::Let(name, assign, body) => {
                 write!(fmt, "let {} = {} in {}", name, assign, body)
             }
+            Term::LetRec(bindings, body) => {
+                write!(fmt, "let rec ")?;
+                for (i, (name, assign)) in bindings.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, " and ")?;
+                    }
+                    write!(fmt, "{} = {}", name, assign)?;
+                }
+                write!(fmt, " in {}", body)
+            }
             Term::Abs(args, body) => {
                 write!(fmt, "\\")?;
                 if !args.is_empty() {
@@ -48,7 +131,29 @@ impl std::fmt::Display for Term {
                 write!(fmt, " -> {}", body)
             }
             Term::App(f, args) => {
-                write!(fmt, "{}(", f)?;
+                // an infix operator applied to one argument is a section called with it
+                match (&f.node, &args[..]) {
+                    (Term::Var(op), [arg]) if is_prefix(op) => {
+                        return write!(fmt, "({} {})", op, arg);
+                    }
+                    (Term::Var(op), [arg]) if is_operator(op) => {
+                        return write!(fmt, "({})({})", op, arg);
+                    }
+                    // the operands of an infix operator are parenthesized in turn, so only
+                    // a left one that would take the operator in is parenthesized again
+                    (Term::Var(op), [lhs, rhs]) if is_operator(op) && open(&lhs.node) => {
+                        return write!(fmt, "(({}) {} {})", lhs, op, rhs);
+                    }
+                    (Term::Var(op), [lhs, rhs]) if is_operator(op) => {
+                        return write!(fmt, "({} {} {})", lhs, op, rhs);
+                    }
+                    _ => {}
+                }
+                // any other head is parenthesized so the call stays on it
+                match postfix(&f.node) {
+                    true => write!(fmt, "{}(", f)?,
+                    false => write!(fmt, "({})(", f)?,
+                }
                 if !args.is_empty() {
                     write!(fmt, "{}", args[0])?;
                 }
@@ -57,73 +162,388 @@ impl std::fmt::Display for Term {
                 }
                 write!(fmt, ")")
             }
+            Term::If(cond, then, otherwise) => {
+                write!(fmt, "if {} then {} else {}", cond, then, otherwise)
+            }
+            Term::Tuple(items) => {
+                write!(fmt, "({}", items[0])?;
+                for item in items.iter().skip(1) {
+                    write!(fmt, ", {}", item)?;
+                }
+                write!(fmt, ")")
+            }
+            Term::Record(fields, rest) => {
+                write!(fmt, "{{")?;
+                for (i, (label, field)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, ", ")?;
+                    }
+                    write!(fmt, "{} = {}", label, field)?;
+                }
+                match rest {
+                    Some(rest) if fields.is_empty() => write!(fmt, "| {}}}", rest),
+                    Some(rest) => write!(fmt, " | {}}}", rest),
+                    None => write!(fmt, "}}"),
+                }
+            }
+            Term::Select(record, label) if postfix(&record.node) => {
+                write!(fmt, "{}.{}", record, label)
+            }
+            Term::Select(record, label) => {
+                write!(fmt, "({}).{}", record, label)
+            }
+            Term::Restrict(record, label) => {
+                write!(fmt, "{{{} - {}}}", record, label)
+            }
+            Term::Variant(label, None) => {
+                write!(fmt, "`{}", label)
+            }
+            Term::Variant(label, Some(payload)) => match payload.node {
+                Term::Tuple(_) => write!(fmt, "`{}{}", label, payload),
+                _ => write!(fmt, "`{}({})", label, payload),
+            },
+            Term::Annot(term, ty) => {
+                write!(fmt, "({} : {})", term, ty)
+            }
+            Term::Match(scrutinee, arms) => {
+                write!(fmt, "match {} {{ ", scrutinee)?;
+                for (i, (pattern, body)) in arms.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, ", ")?;
+                    }
+                    write!(fmt, "{} -> {}", pattern, body)?;
+                }
+                write!(fmt, " }}")
+            }
         }
     }
 }
 
-fn name() -> Parser<'static, Token, String> {
-    ident()
-        | fmap(|_| String::from("true"), token(Token::True))
-        | fmap(|_| String::from("false"), token(Token::False))
-        | fmap(|_| String::from("not"), token(Token::Not))
-        | fmap(|_| String::from("and"), token(Token::And))
-        | fmap(|_| String::from("or"), token(Token::Or))
+pub(crate) fn name() -> Parser<'static, Spanned<Token>, String> {
+    ident().label("identifier")
 }
 
-fn simple_term() -> Parser<'static, Token, Term> {
-    let p_var = fmap(Term::Var, name());
-    let p_term_parens = lazy!(parens(spaced(term())));
+pub(crate) fn literal() -> Parser<'static, Spanned<Token>, Literal> {
+    // a `-` right before a number is its sign, after an operand it is taken as an operator first
+    let p_signed = fmap(
+        |number| format!("-{}", number),
+        token(Token::Minus).hidden() >> number(),
+    ) | number();
+    let p_number = bind(p_signed, |number| {
+        if number.contains(['.', 'e']) {
+            number.parse().map(Literal::Float).ok()
+        } else {
+            number.parse().map(Literal::Int).ok()
+        }
+        .map_or_else(Parser::empty, Parser::pure)
+    });
+
+    (p_number
+        | fmap(|_| Literal::Bool(true), token(Token::True))
+        | fmap(|_| Literal::Bool(false), token(Token::False))
+        | fmap(Literal::Str, string()))
+    .label("literal")
+}
+
+fn simple_term(ops: Rc<Operators>) -> Parser<'static, Spanned<Token>, Spanned<Term>> {
+    let p_lit = spanned(fmap(Term::Lit, literal()));
+    // an operator in parentheses is the function it stands for
+    let p_section = fmap(|op| op.node, parens(spaced(operator(is_operator))));
+    let p_var = spanned(fmap(Term::Var, name() | p_section));
+    // a parenthesized list of two or more terms is a tuple
+    fn parenthesized(ops: Rc<Operators>) -> Parser<'static, Spanned<Token>, Spanned<Term>> {
+        fmap(
+            |mut items: Spanned<Vec<Spanned<Term>>>| match items.node.len() {
+                1 => items.node.remove(0),
+                _ => items.map(Term::Tuple),
+            },
+            spanned(parens(comma_list1(spaced(annotated(ops))))),
+        )
+    }
+    let _ops = ops.clone();
+    let p_term_parens = lazy!(parenthesized(_ops.clone()));
+    let _ops = ops.clone();
+    let p_variant = spanned(bind(token(Token::Backtick) >> name(), move |label| {
+        let _label = label.clone();
+        fmap(
+            move |payload| Term::Variant(label.clone(), Some(Box::new(payload))),
+            parenthesized(_ops.clone()),
+        ) | Parser::pure(Term::Variant(_label, None))
+    }));
+
+    fn args(ops: Rc<Operators>) -> Parser<'static, Spanned<Token>, Spanned<Vec<Spanned<Term>>>> {
+        spanned(parens(spaced(comma_list(spaced(term(ops)))))).label("argument list")
+    }
+
+    fn app(f: Spanned<Term>, args: Spanned<Vec<Spanned<Term>>>) -> Spanned<Term> {
+        let span = f.span.merge(args.span);
+        Spanned::new(Term::App(Box::new(f), args.node), span)
+    }
 
-    fn args() -> Parser<'static, Token, Vec<Term>> {
-        parens(spaced(comma_list(spaced(term()))))
+    fn select(record: Spanned<Term>, label: Spanned<String>) -> Spanned<Term> {
+        let span = record.span.merge(label.span);
+        Spanned::new(Term::Select(Box::new(record), label.node), span)
     }
 
-    bind(
-        bind(p_var, |f| {
-            let _f = f.clone();
-            fmap(move |args| Term::App(Box::new(f.clone()), args), args()) | Parser::pure(_f)
-        }) | bind(p_term_parens, |f| {
-            let _f = f.clone();
-            fmap(move |args| Term::App(Box::new(f.clone()), args), args()) | Parser::pure(_f)
-        }),
-        |app| {
-            let _call = app.clone();
-            fmap(move |args| Term::App(Box::new(app.clone()), args), args()) | Parser::pure(_call)
+    // calls and field selections chain to the right of the head
+    let _ops = ops.clone();
+    let p_head = p_var | p_term_parens | spanned(lazy!(record(_ops.clone())));
+    let p_postfix = bind(p_head, move |head| {
+        let ops = ops.clone();
+        Parser::new(move |s| {
+            let mut term = head.clone();
+            loop {
+                if let Some(args) = args(ops.clone()).run(s) {
+                    term = app(term, args);
+                } else if let Some(label) = (token(Token::Dot).hidden() >> spanned(name())).run(s) {
+                    term = select(term, label);
+                } else {
+                    return Some(term);
+                }
+            }
+        })
+    });
+
+    p_lit | p_variant | p_postfix
+}
+
+// `{label = term, ... | record}` extends the record, `{record - label}` removes the label
+fn record(ops: Rc<Operators>) -> Parser<'static, Spanned<Token>, Term> {
+    let _ops = ops.clone();
+    let p_field = bind(spaced(name()) << token(Token::Equals), move |label| {
+        fmap(
+            move |field| (label.clone(), field),
+            spaced(term(_ops.clone())),
+        )
+    });
+    let _ops = ops.clone();
+    let p_extend = bind(many_space() >> comma_list(p_field), move |fields| {
+        let _fields = fields.clone();
+        fmap(
+            move |rest| Term::Record(fields.clone(), Some(Box::new(rest))),
+            token(Token::Pipe) >> spaced(term(_ops.clone())),
+        ) | Parser::pure(Term::Record(_fields, None))
+    });
+    // `-` ends the record, so it is not an operator inside of it
+    let mut record_ops = (*ops).clone();
+    record_ops.remove_infix("-");
+    let p_restrict = bind(
+        spaced(term(Rc::new(record_ops))) << token(Token::Minus),
+        |record| {
+            fmap(
+                move |label| Term::Restrict(Box::new(record.clone()), label),
+                spaced(name()),
+            )
         },
-    )
+    );
+
+    braces(p_extend) | braces(p_restrict)
+}
+
+fn annotation() -> Parser<'static, Spanned<Token>, Spanned<Ty>> {
+    (token(Token::Colon) >> many_space() >> forall()).label("type annotation")
+}
+
+// the annotation spans both the term and the type, which comes first in a let binding
+fn annotate(term: Spanned<Term>, ty: Spanned<Ty>) -> Spanned<Term> {
+    let span = match ty.span.start < term.span.start {
+        true => ty.span.merge(term.span),
+        false => term.span.merge(ty.span),
+    };
+    Spanned::new(Term::Annot(Box::new(term), ty), span)
+}
+
+fn annotated(ops: Rc<Operators>) -> Parser<'static, Spanned<Token>, Spanned<Term>> {
+    bind(spaced(term(ops)), |term| {
+        let _term = term.clone();
+        fmap(
+            move |ty| annotate(term.clone(), ty),
+            annotation() << many_space(),
+        ) | Parser::pure(_term)
+    })
 }
 
-fn term() -> Parser<'static, Token, Term> {
+// `let name = term` or `let name : type = term`, where the type annotates the term
+pub(crate) fn let_binding(
+    ops: Rc<Operators>,
+) -> Parser<'static, Spanned<Token>, (String, Spanned<Term>)> {
+    let p_head = bind(token(Token::Let) >> spaced(name()), |name| {
+        let _name = name.clone();
+        fmap(
+            move |ty| (name.clone(), Some(ty)),
+            annotation() << many_space(),
+        ) | Parser::pure((_name, None))
+    });
+
+    bind(p_head << token(Token::Equals), move |(name, ty)| {
+        fmap(
+            move |assign| match &ty {
+                Some(ty) => (name.clone(), annotate(assign, ty.clone())),
+                None => (name.clone(), assign),
+            },
+            spaced(term(ops.clone())),
+        )
+    })
+}
+
+// `and` separates the bindings of a group, so it is not an operator inside of them
+pub(crate) fn rec_bindings(
+    ops: Rc<Operators>,
+) -> Parser<'static, Spanned<Token>, Vec<(String, Spanned<Term>)>> {
+    let mut group_ops = (*ops).clone();
+    group_ops.remove_infix("and");
+    let group_ops = Rc::new(group_ops);
+
+    let binding = bind(spaced(name()) << token(Token::Equals), move |name| {
+        fmap(
+            move |assign| (name.clone(), assign),
+            spaced(term(group_ops.clone())),
+        )
+    });
+
+    token(Token::Let) >> spaced(token(Token::Rec)) >> binding.sep_by1(token(Token::And))
+}
+
+fn operand(ops: Rc<Operators>) -> Parser<'static, Spanned<Token>, Spanned<Term>> {
+    let _ops = ops.clone();
     let p_let = bind(
-        token(Token::Let) >> spaced(name()) << token(Token::Equals),
-        |name| {
-            bind(spaced(term()) << token(Token::In), move |assign| {
-                let name = name.clone();
-                fmap(
-                    move |body| Term::Let(name.clone(), Box::new(assign.clone()), Box::new(body)),
-                    many_space() >> term(),
-                )
-            })
+        let_binding(ops.clone()) << token(Token::In),
+        move |(name, assign)| {
+            fmap(
+                move |body| Term::Let(name.clone(), Box::new(assign.clone()), Box::new(body)),
+                many_space() >> term(_ops.clone()),
+            )
+        },
+    );
+    let (_ops, group_ops) = (ops.clone(), ops.clone());
+    let p_let_rec = bind(
+        rec_bindings(group_ops) << token(Token::In),
+        move |bindings| {
+            fmap(
+                move |body| Term::LetRec(bindings.clone(), Box::new(body)),
+                many_space() >> term(_ops.clone()),
+            )
         },
     );
+    let _ops = ops.clone();
     let p_fn = bind(
         token(Token::Backslash) >> spaced(name().sep_by1(many_space())) << token(Token::Arrow),
-        |args| {
+        move |args| {
             fmap(
                 move |body| Term::Abs(args.clone(), Box::new(body)),
-                many_space() >> term(),
+                many_space() >> term(_ops.clone()),
             )
         },
     );
+    let (_ops, cond_ops) = (ops.clone(), ops.clone());
+    let p_if = bind(
+        token(Token::If) >> spaced(lazy!(term(cond_ops.clone()))) << token(Token::Then),
+        move |cond| {
+            let ops = _ops.clone();
+            bind(
+                spaced(term(ops.clone())) << token(Token::Else),
+                move |then| {
+                    let cond = cond.clone();
+                    fmap(
+                        move |otherwise| {
+                            Term::If(
+                                Box::new(cond.clone()),
+                                Box::new(then.clone()),
+                                Box::new(otherwise),
+                            )
+                        },
+                        many_space() >> term(ops.clone()),
+                    )
+                },
+            )
+        },
+    );
+    let (_ops, scrutinee_ops) = (ops.clone(), ops.clone());
+    let p_match = bind(
+        token(Token::Match) >> spaced(lazy!(term(scrutinee_ops.clone()))),
+        move |scrutinee| {
+            let ops = _ops.clone();
+            let p_arm = bind(spaced(pattern()) << token(Token::Arrow), move |pattern| {
+                fmap(
+                    move |body| (pattern.clone(), body),
+                    spaced(term(ops.clone())),
+                )
+            });
+            fmap(
+                move |arms| Term::Match(Box::new(scrutinee.clone()), arms),
+                braces(comma_list1(p_arm)),
+            )
+        },
+    );
+
+    let (_ops, prefix_ops) = (ops.clone(), ops.clone());
+    let p_prefix = bind(
+        operator(move |op| prefix_ops.prefix(op).is_some()).hidden() << many_space(),
+        move |op| {
+            let precedence = _ops.prefix(&op.node).unwrap_or_default();
+            fmap(
+                move |arg: Spanned<Term>| {
+                    let span = op.span.merge(arg.span);
+                    let f = Spanned::new(Term::Var(op.node.clone()), op.span);
+                    Spanned::new(Term::App(Box::new(f), vec![arg]), span)
+                },
+                expr(_ops.clone(), precedence),
+            )
+        },
+    );
+
+    spanned(p_let | p_let_rec | p_fn | p_if | p_match) | p_prefix | simple_term(ops)
+}
 
-    p_let | p_fn | simple_term()
+// precedence climbing over operands, only operators binding at least as tight as min are taken
+fn expr(ops: Rc<Operators>, min: Precedence) -> Parser<'static, Spanned<Token>, Spanned<Term>> {
+    Parser::new(move |s| {
+        let mut lhs = operand(ops.clone()).run(s)?;
+        let mut cap = None;
+        loop {
+            let state = s.len();
+            let infix_ops = ops.clone();
+            let op = (many_space() >> operator(move |op| infix_ops.infix(op).is_some()).hidden()
+                << many_space())
+            .run(s);
+
+            let (op, precedence, assoc) = match op {
+                Some(op) => {
+                    let (precedence, assoc) = ops.infix(&op.node).unwrap_or((0, Assoc::None));
+                    if precedence < min || Some(precedence) == cap {
+                        s.undo(s.len() - state);
+                        return Some(lhs);
+                    }
+                    (op, precedence, assoc)
+                }
+                None => return Some(lhs),
+            };
+
+            let rhs = match assoc {
+                Assoc::Right => expr(ops.clone(), precedence),
+                Assoc::Left | Assoc::None => expr(ops.clone(), precedence + 1),
+            }
+            .run(s)?;
+            cap = (assoc == Assoc::None).then_some(precedence);
+
+            let span = lhs.span.merge(rhs.span);
+            let f = Spanned::new(Term::Var(op.node), op.span);
+            lhs = Spanned::new(Term::App(Box::new(f), vec![lhs, rhs]), span);
+        }
+    })
+}
+
+pub(crate) fn term(ops: Rc<Operators>) -> Parser<'static, Spanned<Token>, Spanned<Term>> {
+    expr(ops, 0).label("term")
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Term, Term::*};
+    use super::{Literal, Term, Term::*};
+    use crate::{Assoc, Operators, Pattern, Ty};
 
     use std::str::FromStr;
+    use tokenstream::{Position, Span, Spanned};
 
     fn collect(s: &str) -> Option<Term> {
         Term::from_str(s).ok()
@@ -141,60 +561,527 @@ mod tests {
         assert_eq!(collect("(f a)"), None);
     }
 
+    #[test]
+    fn errors() {
+        let error = |s: &str| Term::from_str(s).unwrap_err().to_string();
+        assert_eq!(error("let x = y )"), "expected `in` at 1:11, found `)`");
+        assert_eq!(error("f(a, )"), "expected term at 1:6, found `)`");
+        assert_eq!(
+            error("\\x y x"),
+            "expected identifier or `->` at 1:7, found end of input"
+        );
+        assert_eq!(
+            error("let f = g(a\n  in f"),
+            "expected `,` or `)` at 2:3, found `in`"
+        );
+        assert_eq!(error("f(@)"), "unexpected character `@` at 1:3");
+        assert_eq!(error("f(\"ab\\q\")"), "unknown escape `\\q` at 1:6");
+        assert_eq!(error("\"\n\\q\""), "unknown escape `\\q` at 2:1");
+        assert_eq!(
+            error("a | b"),
+            "expected argument list or end of input at 1:3, found `|`"
+        );
+        assert_eq!(
+            error("let x = y \n "),
+            "expected `in` at 2:2, found end of input"
+        );
+    }
+
     #[test]
     fn var() {
         assert_eq!(collect("a"), Some(Var(String::from("a"))));
         assert_eq!(collect("(((a)))"), Some(Var(String::from("a"))));
     }
 
+    #[test]
+    fn lit() {
+        assert_eq!(collect("42"), Some(Lit(Literal::Int(42))));
+        assert_eq!(collect("-0.5"), Some(Lit(Literal::Float(-0.5))));
+        assert_eq!(collect("1e3"), Some(Lit(Literal::Float(1000.0))));
+        assert_eq!(collect("(true)"), Some(Lit(Literal::Bool(true))));
+        assert_eq!(
+            collect(r#""a\tb""#),
+            Some(Lit(Literal::Str(String::from("a\tb"))))
+        );
+        assert_eq!(
+            collect(r#"f(1, false, "")"#),
+            Some(App(
+                Box::new(Var(String::from("f")).into()),
+                vec![
+                    Lit(Literal::Int(1)).into(),
+                    Lit(Literal::Bool(false)).into(),
+                    Lit(Literal::Str(String::new())).into()
+                ]
+            ))
+        );
+        assert_eq!(
+            collect(r#"pair(1.0, "\"")"#).unwrap().to_string(),
+            String::from(r#"pair(1.0, "\"")"#)
+        );
+        assert_eq!(collect("99999999999999999999"), None);
+        assert_eq!(collect(r"\true -> 1"), None);
+    }
+
     #[test]
     fn let_in() {
         assert_eq!(
             collect(r"let c = \f g x -> f(g(x)) in c(f, g)"),
             Some(Let(
                 String::from("c"),
-                Box::new(Abs(
-                    vec![String::from("f"), String::from("g"), String::from("x")],
-                    Box::new(App(
-                        Box::new(Var(String::from("f"))),
-                        vec![App(
-                            Box::new(Var(String::from("g"))),
-                            vec![Var(String::from("x"))]
-                        )]
-                    )),
-                )),
-                Box::new(App(
-                    Box::new(Var(String::from("c"))),
-                    vec![Var(String::from("f")), Var(String::from("g"))]
-                ))
+                Box::new(
+                    Abs(
+                        vec![String::from("f"), String::from("g"), String::from("x")],
+                        Box::new(
+                            App(
+                                Box::new(Var(String::from("f")).into()),
+                                vec![App(
+                                    Box::new(Var(String::from("g")).into()),
+                                    vec![Var(String::from("x")).into()]
+                                )
+                                .into()]
+                            )
+                            .into()
+                        ),
+                    )
+                    .into()
+                ),
+                Box::new(
+                    App(
+                        Box::new(Var(String::from("c")).into()),
+                        vec![Var(String::from("f")).into(), Var(String::from("g")).into()]
+                    )
+                    .into()
+                )
             ))
         );
         assert_eq!(
             collect("let a = x in let b = y in f(x, y)"),
             Some(Let(
                 String::from("a"),
-                Box::new(Var(String::from("x"))),
-                Box::new(Let(
-                    String::from("b"),
-                    Box::new(Var(String::from("y"))),
-                    Box::new(App(
-                        Box::new(Var(String::from("f"))),
-                        vec![Var(String::from("x")), Var(String::from("y"))]
-                    ))
-                ))
+                Box::new(Var(String::from("x")).into()),
+                Box::new(
+                    Let(
+                        String::from("b"),
+                        Box::new(Var(String::from("y")).into()),
+                        Box::new(
+                            App(
+                                Box::new(Var(String::from("f")).into()),
+                                vec![Var(String::from("x")).into(), Var(String::from("y")).into()]
+                            )
+                            .into()
+                        )
+                    )
+                    .into()
+                )
             ))
         )
     }
 
+    #[test]
+    fn let_rec() {
+        assert_eq!(
+            collect("let rec f = g(x) and g = \\x -> f(x) in f"),
+            Some(LetRec(
+                vec![
+                    (
+                        String::from("f"),
+                        App(
+                            Box::new(Var(String::from("g")).into()),
+                            vec![Var(String::from("x")).into()]
+                        )
+                        .into()
+                    ),
+                    (
+                        String::from("g"),
+                        Abs(
+                            vec![String::from("x")],
+                            Box::new(
+                                App(
+                                    Box::new(Var(String::from("f")).into()),
+                                    vec![Var(String::from("x")).into()]
+                                )
+                                .into()
+                            )
+                        )
+                        .into()
+                    )
+                ],
+                Box::new(Var(String::from("f")).into())
+            ))
+        );
+        assert_eq!(
+            collect("let rec f = (and)(a, b) and g = a or b in f and g")
+                .unwrap()
+                .to_string(),
+            String::from("let rec f = (a and b) and g = (a or b) in (f and g)")
+        );
+        assert_eq!(collect("let rec f = a and b in f"), None);
+        assert_eq!(collect("let rec in f"), None);
+        assert_eq!(collect("let rec = a in f"), None);
+    }
+
     #[test]
     fn abs() {
         assert_eq!(
             collect(r"\x y -> x"),
             Some(Abs(
                 vec![String::from("x"), String::from("y")],
-                Box::new(Var(String::from("x")))
+                Box::new(Var(String::from("x")).into())
+            ))
+        );
+    }
+
+    #[test]
+    fn if_then_else() {
+        assert_eq!(
+            collect("if f(x) then\n  (a)\nelse if b then c else d"),
+            Some(If(
+                Box::new(
+                    App(
+                        Box::new(Var(String::from("f")).into()),
+                        vec![Var(String::from("x")).into()]
+                    )
+                    .into()
+                ),
+                Box::new(Var(String::from("a")).into()),
+                Box::new(
+                    If(
+                        Box::new(Var(String::from("b")).into()),
+                        Box::new(Var(String::from("c")).into()),
+                        Box::new(Var(String::from("d")).into())
+                    )
+                    .into()
+                )
             ))
         );
+        assert_eq!(
+            collect("if a then let x = b in x else c")
+                .unwrap()
+                .to_string(),
+            String::from("if a then let x = b in x else c")
+        );
+        assert_eq!(collect("if a then b"), None);
+        assert_eq!(collect("if a else b"), None);
+    }
+
+    #[test]
+    fn operators() {
+        let var = |name: &str| Box::new(Var(String::from(name)).into());
+        let binary = |op: &str, lhs: Term, rhs: Term| App(var(op), vec![lhs.into(), rhs.into()]);
+        let a = || Var(String::from("a"));
+        let b = || Var(String::from("b"));
+        let c = || Var(String::from("c"));
+
+        assert_eq!(
+            collect("a + b * c"),
+            Some(binary("+", a(), binary("*", b(), c())))
+        );
+        assert_eq!(
+            collect("a*b+c"),
+            Some(binary("+", binary("*", a(), b()), c()))
+        );
+        let one = || Lit(Literal::Int(1));
+        assert_eq!(collect("a-1"), Some(binary("-", a(), one())));
+        assert_eq!(collect("a -1"), Some(binary("-", a(), one())));
+        assert_eq!(
+            collect("a - -1"),
+            Some(binary("-", a(), Lit(Literal::Int(-1))))
+        );
+        assert_eq!(
+            collect("a or b or c"),
+            Some(binary("or", a(), binary("or", b(), c())))
+        );
+        assert_eq!(
+            collect("not a == b"),
+            Some(App(var("not"), vec![binary("==", a(), b()).into()]))
+        );
+        assert_eq!(collect("(+)"), Some(Var(String::from("+"))));
+        assert_eq!(
+            collect("(<=)(a, b)"),
+            Some(App(var("<="), vec![a().into(), b().into()]))
+        );
+        assert_eq!(
+            collect("a - b - c"),
+            Some(binary("-", binary("-", a(), b()), c()))
+        );
+        assert_eq!(
+            collect("{a - b}"),
+            Some(Restrict(Box::new(a().into()), String::from("b")))
+        );
+        assert_eq!(collect("a == b == c"), None);
+        assert_eq!(collect("a +"), None);
+        assert_eq!(collect("not"), None);
+
+        let display = |s: &str| collect(s).unwrap().to_string();
+        assert_eq!(display(r"\x -> x + 1 < f(x)"), r"\x -> ((x + 1) < f(x))");
+        assert_eq!(
+            display("let x = a % 2 in if not x then b else b / 2"),
+            "let x = (a % 2) in if (not x) then b else (b / 2)"
+        );
+        assert_eq!(display("r := !r + 1"), "(r := ((! r) + 1))");
+        assert_eq!(display("(:=)(r, !f(x))"), "(r := (! f(x)))");
+
+        let mut ops = Operators::new();
+        ops.insert_infix("+", 1, Assoc::Right);
+        ops.insert_infix("*", 2, Assoc::Left);
+        assert_eq!(
+            Term::parse_with("a + b + c * b * a", &ops).unwrap().node,
+            binary(
+                "+",
+                a(),
+                binary("+", b(), binary("*", binary("*", c(), b()), a()))
+            )
+        );
+        assert!(Term::parse_with("a / b", &ops).is_err());
+
+        ops.insert_infix("mod", 3, Assoc::Left);
+        assert_eq!(
+            Term::parse_with("a mod b * c", &ops).unwrap().node,
+            binary("*", binary("mod", a(), b()), c())
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let var = |name: &str| Spanned::from(Var(String::from(name)));
+        let app = |f, args| Spanned::from(App(Box::new(f), args));
+        for term in [
+            app(app(var("=="), vec![var("d")]), vec![var("x"), var("y")]),
+            app(var("-"), vec![var("x")]),
+            app(var("not"), vec![app(var("+"), vec![var("x")])]),
+            app(
+                var("-"),
+                vec![var("x"), Spanned::from(Lit(Literal::Int(-1)))],
+            ),
+            app(var("mod"), vec![var("x"), var("y")]),
+            app(
+                Spanned::from(Abs(vec![String::from("x")], Box::new(var("x")))),
+                vec![var("y")],
+            ),
+        ] {
+            assert_eq!(Term::from_str(&term.to_string()), Ok(term.node));
+        }
+
+        // the operands that would take in the rest of the operation are parenthesized
+        for (code, printed) in [
+            ("(if c then a else b) + 1", "((if c then a else b) + 1)"),
+            ("(let x = 1 in x) * 2", "((let x = 1 in x) * 2)"),
+            (r"(\x -> x) - y", r"((\x -> x) - y)"),
+            ("(match x { _ -> a }) == b", "((match x { _ -> a }) == b)"),
+            ("(a + b) * (c - d)", "((a + b) * (c - d))"),
+            ("a - (b - c)", "(a - (b - c))"),
+            ("a + if c then a else b", "(a + if c then a else b)"),
+        ] {
+            let term = Term::from_str(code).unwrap();
+            assert_eq!(term.to_string(), printed);
+            assert_eq!(Term::from_str(printed), Ok(term));
+        }
+    }
+
+    #[test]
+    fn annot() {
+        assert_eq!(
+            collect("( f : int -> int )"),
+            Some(Annot(
+                Box::new(Var(String::from("f")).into()),
+                Ty::Arrow(
+                    vec![Ty::Const(String::from("int")).into()],
+                    Box::new(Ty::Const(String::from("int")).into())
+                )
+                .into()
+            ))
+        );
+        assert_eq!(
+            collect("let id : forall[a] a -> a = \\x -> x in id"),
+            Some(Let(
+                String::from("id"),
+                Box::new(
+                    Annot(
+                        Box::new(
+                            Abs(
+                                vec![String::from("x")],
+                                Box::new(Var(String::from("x")).into())
+                            )
+                            .into()
+                        ),
+                        Ty::Forall(
+                            vec![String::from("a")],
+                            Box::new(
+                                Ty::Arrow(
+                                    vec![Ty::Const(String::from("a")).into()],
+                                    Box::new(Ty::Const(String::from("a")).into())
+                                )
+                                .into()
+                            )
+                        )
+                        .into()
+                    )
+                    .into()
+                ),
+                Box::new(Var(String::from("id")).into())
+            ))
+        );
+        assert_eq!(
+            collect("let x : int = (1 + 2 : int) in x")
+                .unwrap()
+                .to_string(),
+            String::from("let x = (((1 + 2) : int) : int) in x")
+        );
+        assert_eq!(collect("f : int"), None);
+        assert_eq!(collect("(f : )"), None);
+        assert_eq!(collect("let x : = 1 in x"), None);
+    }
+
+    #[test]
+    fn tuple() {
+        assert_eq!(
+            collect("(a, (b, 1))"),
+            Some(Tuple(vec![
+                Var(String::from("a")).into(),
+                Tuple(vec![
+                    Var(String::from("b")).into(),
+                    Lit(Literal::Int(1)).into()
+                ])
+                .into()
+            ]))
+        );
+        assert_eq!(
+            collect("f((a, b), (c : int))").unwrap().to_string(),
+            String::from("f((a, b), (c : int))")
+        );
+        assert_eq!(
+            collect("match p { (x, _) -> x }").unwrap().to_string(),
+            String::from("match p { (x, _) -> x }")
+        );
+        assert_eq!(collect("(a, )"), None);
+    }
+
+    #[test]
+    fn record() {
+        assert_eq!(
+            collect("{x = 1, y = r.x}"),
+            Some(Record(
+                vec![
+                    (String::from("x"), Lit(Literal::Int(1)).into()),
+                    (
+                        String::from("y"),
+                        Select(Box::new(Var(String::from("r")).into()), String::from("x")).into()
+                    )
+                ],
+                None
+            ))
+        );
+        assert_eq!(
+            collect("{r - x}"),
+            Some(Restrict(
+                Box::new(Var(String::from("r")).into()),
+                String::from("x")
+            ))
+        );
+        assert_eq!(collect("{}"), Some(Record(vec![], None)));
+        assert_eq!(
+            collect("{ x = 1 | { f(r).x - y } }.z(a).w")
+                .unwrap()
+                .to_string(),
+            String::from("{x = 1 | {f(r).x - y}}.z(a).w")
+        );
+        assert_eq!(
+            collect("f(a)(b)(c)").unwrap().to_string(),
+            String::from("f(a)(b)(c)")
+        );
+        assert_eq!(
+            collect(r"(\x -> x)(a)").unwrap().to_string(),
+            String::from(r"(\x -> x)(a)")
+        );
+        // the receivers that a selection would not chain to are parenthesized
+        for (code, printed) in [
+            ("f(x).a", "f(x).a"),
+            ("(a + b).x", "(a + b).x"),
+            ("(not b).x", "(not b).x"),
+            ("(if c then a else b).x", "(if c then a else b).x"),
+            (r"(\x -> x).y", r"(\x -> x).y"),
+            ("(`A(x)).y", "(`A(x)).y"),
+            ("(1).y", "(1).y"),
+        ] {
+            let term = collect(code).unwrap();
+            assert_eq!(term.to_string(), printed);
+            assert_eq!(collect(printed), Some(term));
+        }
+        assert_eq!(collect("{x = 1,}"), None);
+        assert_eq!(collect("{x}"), None);
+        assert_eq!(collect("r.(x)"), None);
+    }
+
+    #[test]
+    fn variant() {
+        assert_eq!(
+            collect("`Ok(x)"),
+            Some(Variant(
+                String::from("Ok"),
+                Some(Box::new(Var(String::from("x")).into()))
+            ))
+        );
+        assert_eq!(collect("`None"), Some(Variant(String::from("None"), None)));
+        assert_eq!(
+            collect("match `Pair(1, `A) { `Pair(x, `A) -> x, `B(`C) -> 0 }")
+                .unwrap()
+                .to_string(),
+            String::from("match `Pair(1, `A) { `Pair(x, `A) -> x, `B(`C) -> 0 }")
+        );
+        // a payload of any form reads back as the same term
+        for (code, printed) in [
+            ("`A(-1)", "`A(-1)"),
+            ("`A(a + b)", "`A((a + b))"),
+            ("`A(not b)", "`A((not b))"),
+            ("`A(if c then a else b)", "`A(if c then a else b)"),
+            (r"`A(\x -> x)", r"`A(\x -> x)"),
+            ("`A(let x = 1 in x)", "`A(let x = 1 in x)"),
+            ("`A((x : int))", "`A((x : int))"),
+            ("`A((a, b))", "`A(a, b)"),
+            ("`A(`B(x), f(y).z)", "`A(`B(x), f(y).z)"),
+        ] {
+            let term = collect(code).unwrap();
+            assert_eq!(term.to_string(), printed);
+            assert_eq!(collect(printed), Some(term));
+        }
+        assert_eq!(collect("` Ok"), None);
+        assert_eq!(collect("`Ok()"), None);
+    }
+
+    #[test]
+    fn match_with() {
+        assert_eq!(
+            collect("match l { nil -> 0, cons(x, _) -> x }"),
+            Some(Match(
+                Box::new(Var(String::from("l")).into()),
+                vec![
+                    (
+                        Pattern::Var(String::from("nil")).into(),
+                        Lit(Literal::Int(0)).into()
+                    ),
+                    (
+                        Pattern::Constructor(
+                            String::from("cons"),
+                            vec![
+                                Pattern::Var(String::from("x")).into(),
+                                Pattern::Wildcard.into()
+                            ]
+                        )
+                        .into(),
+                        Var(String::from("x")).into()
+                    )
+                ]
+            ))
+        );
+        assert_eq!(
+            collect("match f(x) {\n  0 -> \\y -> y,\n  n -> match n { _ -> id }\n}")
+                .unwrap()
+                .to_string(),
+            String::from("match f(x) { 0 -> \\y -> y, n -> match n { _ -> id } }")
+        );
+        assert_eq!(collect("match l { }"), None);
+        assert_eq!(collect("match l { x }"), None);
+        assert_eq!(collect("match l { x -> y, }"), None);
     }
 
     #[test]
@@ -202,45 +1089,80 @@ mod tests {
         assert_eq!(
             collect("(fn)((a), b)"),
             Some(App(
-                Box::new(Var(String::from("fn"))),
-                vec![Var(String::from("a")), Var(String::from("b"))]
+                Box::new(Var(String::from("fn")).into()),
+                vec![Var(String::from("a")).into(), Var(String::from("b")).into()]
             ))
         );
         assert_eq!(
             collect("(f)(a, g(b))"),
             Some(App(
-                Box::new(Var(String::from("f"))),
+                Box::new(Var(String::from("f")).into()),
                 vec![
-                    Var(String::from("a")),
+                    Var(String::from("a")).into(),
                     App(
-                        Box::new(Var(String::from("g"))),
-                        vec![Var(String::from("b"))]
+                        Box::new(Var(String::from("g")).into()),
+                        vec![Var(String::from("b")).into()]
                     )
+                    .into()
                 ]
             ))
         );
         assert_eq!(
             collect("((((fn(a)))((b))))"),
             Some(App(
-                Box::new(App(
-                    Box::new(Var(String::from("fn"))),
-                    vec![Var(String::from("a"))]
-                )),
-                vec![Var(String::from("b"))]
+                Box::new(
+                    App(
+                        Box::new(Var(String::from("fn")).into()),
+                        vec![Var(String::from("a")).into()]
+                    )
+                    .into()
+                ),
+                vec![Var(String::from("b")).into()]
             ))
         );
         assert_eq!(
             collect("f(a)(g(b))"),
             Some(App(
-                Box::new(App(
-                    Box::new(Var(String::from("f"))),
-                    vec![Var(String::from("a"))]
-                )),
+                Box::new(
+                    App(
+                        Box::new(Var(String::from("f")).into()),
+                        vec![Var(String::from("a")).into()]
+                    )
+                    .into()
+                ),
                 vec![App(
-                    Box::new(Var(String::from("g"))),
-                    vec![Var(String::from("b"))]
-                )]
+                    Box::new(Var(String::from("g")).into()),
+                    vec![Var(String::from("b")).into()]
+                )
+                .into()]
             ))
         );
     }
+
+    #[test]
+    fn spans() {
+        let at = |offset, line, column| Position {
+            offset,
+            line,
+            column,
+        };
+
+        let term = Term::parse("let f = \\x -> x in\n  f(a, g(b))").unwrap();
+        assert_eq!(term.span, Span::new(at(0, 1, 1), at(31, 2, 13)));
+        match term.node {
+            Let(_, assign, body) => {
+                assert_eq!(assign.span, Span::new(at(8, 1, 9), at(15, 1, 16)));
+                assert_eq!(body.span, Span::new(at(21, 2, 3), at(31, 2, 13)));
+                match body.node {
+                    App(f, args) => {
+                        assert_eq!(f.span, Span::new(at(21, 2, 3), at(22, 2, 4)));
+                        assert_eq!(args[0].span, Span::new(at(23, 2, 5), at(24, 2, 6)));
+                        assert_eq!(args[1].span, Span::new(at(26, 2, 8), at(30, 2, 12)));
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
 }
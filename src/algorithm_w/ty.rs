@@ -6,30 +6,38 @@
  */
 
 use std::collections::HashMap;
-use std::str::FromStr;
 
-use tokenstream::{bind, fmap, Parser, Stream, Tokenizer};
+use tokenstream::{bind, fmap, spanned, Parser, Spanned};
 
-use crate::{Bindable, Env, Gen, Id, Level};
+use crate::{Bindable, Env, Error, Gen, Id, Level, TypeError};
 
-use crate::{brackets, comma_list1, eof, ident, lazy, many_space, parens, spaced, token, Token};
+use crate::{
+    brackets, comma_list, comma_list1, eof, ident, lazy, many_space, parens, parse, spaced, token,
+    ParseError, Token,
+};
 
 #[derive(PartialEq, Clone, Debug)]
 pub enum Ty {
     Const(String),
-    App(Box<Ty>, Vec<Ty>),
-    Arrow(Vec<Ty>, Box<Ty>),
-    Forall(Vec<String>, Box<Ty>),
+    App(Box<Spanned<Ty>>, Vec<Spanned<Ty>>),
+    Arrow(Vec<Spanned<Ty>>, Box<Spanned<Ty>>),
+    Tuple(Vec<Spanned<Ty>>),
+    Record(Vec<(String, Spanned<Ty>)>, Option<Box<Spanned<Ty>>>),
+    Variant(Vec<(String, Spanned<Ty>)>, Option<Box<Spanned<Ty>>>),
+    Qualified(Vec<(String, Spanned<Ty>)>, Box<Spanned<Ty>>),
+    Forall(Vec<String>, Box<Spanned<Ty>>),
+}
+
+impl Ty {
+    pub fn parse(s: &str) -> Result<Spanned<Ty>, ParseError> {
+        parse((ty() << eof()) | (forall() << eof()), s)
+    }
 }
 
 impl std::str::FromStr for Ty {
-    type Err = String;
+    type Err = ParseError;
     fn from_str(s: &str) -> std::result::Result<Self, <Self as std::str::FromStr>::Err> {
-        let mut lexer = Stream::new(Tokenizer::new(s.chars()));
-        let parser = (ty() << eof()) | (forall() << eof());
-        parser
-            .run(&mut lexer)
-            .ok_or(format!("Error while parsing {}", s))
+        Ty::parse(s).map(|ty| ty.node)
     }
 }
 
@@ -44,8 +52,8 @@ impl std::fmt::Display for Ty {
                 if !params.is_empty() {
                     write!(fmt, "{}", params[0])?;
                 }
-                for i in 1..params.len() {
-                    write!(fmt, ", {}", params[i])?;
+                for param in params.iter().skip(1) {
+                    write!(fmt, ", {}", param)?;
                 }
                 write!(fmt, "]")
             }
@@ -54,31 +62,67 @@ impl std::fmt::Display for Ty {
                     0 => {
                         write!(fmt, "() -> ")?;
                     }
-                    1 => match init[0] {
-                        Ty::Const(_) | Ty::App(_, _) => {
+                    1 => match init[0].node {
+                        Ty::Const(_) | Ty::App(_, _) | Ty::Record(_, _) | Ty::Variant(_, _) => {
                             write!(fmt, "{} -> ", init[0])?;
                         }
                         _ => {
                             write!(fmt, "({}) -> ", init[0])?;
                         }
                     },
-                    len @ _ => {
+                    _ => {
                         write!(fmt, "({}", init[0])?;
-                        for i in 1..len {
-                            write!(fmt, ", {}", init[i])?;
+                        for param in init.iter().skip(1) {
+                            write!(fmt, ", {}", param)?;
                         }
                         write!(fmt, ") -> ")?;
                     }
                 };
                 write!(fmt, "{}", tail)
             }
+            Ty::Tuple(items) => {
+                write!(fmt, "({}", items[0])?;
+                for item in items.iter().skip(1) {
+                    write!(fmt, ", {}", item)?;
+                }
+                write!(fmt, ")")
+            }
+            Ty::Record(fields, rest) | Ty::Variant(fields, rest) => {
+                let (open, close) = match self {
+                    Ty::Record(_, _) => ("{", "}"),
+                    _ => ("[", "]"),
+                };
+                write!(fmt, "{}", open)?;
+                for (i, (label, ty)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, ", ")?;
+                    }
+                    write!(fmt, "{} : {}", label, ty)?;
+                }
+                match rest {
+                    Some(rest) if fields.is_empty() => write!(fmt, "| {}", rest)?,
+                    Some(rest) => write!(fmt, " | {}", rest)?,
+                    None => {}
+                }
+                write!(fmt, "{}", close)
+            }
+            Ty::Qualified(context, ty) => {
+                let context: Vec<String> = context
+                    .iter()
+                    .map(|(class, ty)| format!("{}[{}]", class, ty))
+                    .collect();
+                match context.len() {
+                    1 => write!(fmt, "{} => {}", context[0], ty),
+                    _ => write!(fmt, "({}) => {}", context.join(", "), ty),
+                }
+            }
             Ty::Forall(params, arrow) => {
                 write!(fmt, "forall[")?;
                 if !params.is_empty() {
                     write!(fmt, "{}", params[0])?;
                 }
-                for i in 1..params.len() {
-                    write!(fmt, ", {}", params[i])?;
+                for param in params.iter().skip(1) {
+                    write!(fmt, ", {}", param)?;
                 }
                 write!(fmt, "] {}", arrow)
             }
@@ -86,69 +130,125 @@ impl std::fmt::Display for Ty {
     }
 }
 
-fn simple_ty() -> Parser<'static, Token, Ty> {
-    let p_const = fmap(Ty::Const, ident());
-    let p_ty_parens = lazy!(parens(spaced(ty())));
+// a forall nested in a type extends as far to the right as it can,
+// so it is parenthesized unless a delimiter ends it
+pub(crate) fn simple_ty() -> Parser<'static, Spanned<Token>, Spanned<Ty>> {
+    let p_const = spanned(fmap(Ty::Const, ident()));
+    // a parenthesized list of two or more types is a tuple
+    let p_ty_parens = lazy!(fmap(
+        |mut items: Spanned<Vec<Spanned<Ty>>>| match items.node.len() {
+            1 => items.node.remove(0),
+            _ => items.map(Ty::Tuple),
+        },
+        spanned(parens(spaced(comma_list1(spaced(forall())))))
+    ));
+
+    // `{label : type, ... | row}` and `[label : type, ... | row]`, where the row is the other labels,
+    // the empty variant has no values and is not written
+    type Fields = Vec<(String, Spanned<Ty>)>;
+    fn row(
+        open: Token,
+        close: Token,
+        node: fn(Fields, Option<Box<Spanned<Ty>>>) -> Ty,
+    ) -> Parser<'static, Spanned<Token>, Spanned<Ty>> {
+        let nonempty = close == Token::Rbracket;
+        let closed = move |fields: Fields| match nonempty && fields.is_empty() {
+            true => Parser::empty(),
+            _ => Parser::pure(node(fields, None)),
+        };
+        let p_field = bind(spaced(ident()) << token(Token::Colon), |label| {
+            fmap(move |ty| (label.clone(), ty), spaced(forall()))
+        });
+        let p_fields = token(open) >> many_space() >> comma_list(p_field);
+        spanned(
+            bind(p_fields, move |fields| {
+                let _fields = fields.clone();
+                fmap(
+                    move |rest| node(fields.clone(), Some(Box::new(rest))),
+                    token(Token::Pipe) >> spaced(ty()),
+                ) | closed(_fields)
+            }) << many_space()
+                << token(close),
+        )
+    }
+    let p_record = lazy!(row(Token::Lbrace, Token::Rbrace, Ty::Record));
+    let p_variant = lazy!(row(Token::Lbracket, Token::Rbracket, Ty::Variant));
+
+    fn ty_params() -> Parser<'static, Spanned<Token>, Spanned<Vec<Spanned<Ty>>>> {
+        spanned(brackets(spaced(comma_list1(spaced(forall()))))).label("type argument list")
+    }
 
-    fn ty_params() -> Parser<'static, Token, Vec<Ty>> {
-        brackets(spaced(comma_list1(spaced(ty()))))
+    fn app(ty: Spanned<Ty>, params: Spanned<Vec<Spanned<Ty>>>) -> Spanned<Ty> {
+        let span = ty.span.merge(params.span);
+        Spanned::new(Ty::App(Box::new(ty), params.node), span)
     }
 
     bind(
         bind(p_const, move |name| {
             let _name = name.clone();
-            fmap(
-                move |params| Ty::App(Box::new(_name.clone()), params),
-                ty_params(),
-            ) | Parser::pure(name)
-        }) | bind(p_ty_parens, move |ty| {
+            fmap(move |params| app(_name.clone(), params), ty_params()) | Parser::pure(name)
+        }) | p_record
+            | p_variant
+            | bind(p_ty_parens, move |ty| {
+                let _ty = ty.clone();
+                fmap(move |params| app(_ty.clone(), params), ty_params()) | Parser::pure(ty)
+            }),
+        move |ty| {
             let _ty = ty.clone();
-            fmap(
-                move |params| Ty::App(Box::new(_ty.clone()), params),
-                ty_params(),
-            ) | Parser::pure(ty)
-        }),
-        move |app| {
-            let _app = app.clone();
-            fmap(
-                move |params| Ty::App(Box::new(_app.clone()), params),
-                ty_params(),
-            ) | Parser::pure(app)
+            fmap(move |params| app(_ty.clone(), params), ty_params()) | Parser::pure(ty)
         },
     )
 }
 
-fn ty() -> Parser<'static, Token, Ty> {
+pub(crate) fn ty() -> Parser<'static, Spanned<Token>, Spanned<Ty>> {
     let p_arrow = bind(
         fmap(|_| vec![], token(Token::Lparen) >> token(Token::Rparen))
-            | fmap(|ty| vec![ty], simple_ty())
             | lazy!(parens(
-                (spaced(ty()) << token(Token::Comma)) & spaced(comma_list1(spaced(ty())))
-            )),
+                (spaced(forall()) << token(Token::Comma)) & spaced(comma_list1(spaced(forall())))
+            ))
+            | fmap(|ty| vec![ty], simple_ty()),
         move |init| {
             fmap(
                 move |tail| Ty::Arrow(init.clone(), Box::new(tail)),
-                spaced(token(Token::Arrow)) >> ty(),
+                spaced(token(Token::Arrow)) >> forall(),
             )
         },
     );
 
-    p_arrow | simple_ty()
+    (spanned(p_arrow) | simple_ty()).label("type")
 }
 
-fn forall() -> Parser<'static, Token, Ty> {
+// `class[type]`, the constraint that the type is an instance of the class
+pub(crate) fn predicate() -> Parser<'static, Spanned<Token>, (String, Spanned<Ty>)> {
+    bind(ident(), |class| {
+        fmap(move |ty| (class.clone(), ty), brackets(spaced(ty())))
+    })
+    .label("constraint")
+}
+
+// the constraints before `=>`, parenthesized if there are several
+pub(crate) fn context() -> Parser<'static, Spanned<Token>, Vec<(String, Spanned<Ty>)>> {
+    (fmap(|predicate| vec![predicate], predicate())
+        | parens(spaced(comma_list1(spaced(predicate())))))
+        << spaced(token(Token::FatArrow))
+}
+
+pub(crate) fn forall() -> Parser<'static, Spanned<Token>, Spanned<Ty>> {
+    let p_qualified = spanned(bind(context(), |context| {
+        fmap(move |ty| Ty::Qualified(context.clone(), Box::new(ty)), ty())
+    }));
     let p_forall = bind(
         (token(Token::Forall) >> token(Token::Lbracket))
             >> (spaced(comma_list1(spaced(ident()))) << token(Token::Rbracket)),
         move |params| {
             fmap(
                 move |ty| Ty::Forall(params.clone(), Box::new(ty)),
-                many_space() >> ty(),
+                many_space() >> (p_qualified.clone() | ty()),
             )
         },
     );
 
-    p_forall | ty()
+    (spanned(p_forall) | ty()).label("type")
 }
 
 #[cfg(test)]
@@ -171,6 +271,20 @@ mod tests {
         assert_eq!(collect("() -> t[]"), None);
     }
 
+    #[test]
+    fn errors() {
+        let error = |s: &str| Ty::from_str(s).unwrap_err().to_string();
+        assert_eq!(error("t[]"), "expected type at 1:3, found `]`");
+        assert_eq!(
+            error("(a, b) ->"),
+            "expected type at 1:10, found end of input"
+        );
+        assert_eq!(
+            error("forall[a b] a"),
+            "expected `,` or `]` at 1:10, found `b`"
+        );
+    }
+
     #[test]
     fn type_const() {
         assert_eq!(collect("int"), Some(Const(String::from("int"))));
@@ -186,7 +300,7 @@ mod tests {
     fn arrow() {
         assert_eq!(
             collect("() -> b"),
-            Some(Arrow(vec![], Box::new(Const(String::from("b")))))
+            Some(Arrow(vec![], Box::new(Const(String::from("b")).into())))
         );
         assert_eq!(
             format!("{}", collect("() -> b").unwrap()),
@@ -196,10 +310,13 @@ mod tests {
             collect("() -> a -> b"),
             Some(Arrow(
                 vec![],
-                Box::new(Arrow(
-                    vec![Const(String::from("a"))],
-                    Box::new(Const(String::from("b")))
-                ))
+                Box::new(
+                    Arrow(
+                        vec![Const(String::from("a")).into()],
+                        Box::new(Const(String::from("b")).into())
+                    )
+                    .into()
+                )
             ))
         );
         assert_eq!(
@@ -209,8 +326,8 @@ mod tests {
         assert_eq!(
             collect("(( () -> a ) -> b)"),
             Some(Arrow(
-                vec![Arrow(vec![], Box::new(Const(String::from("a"))))],
-                Box::new(Const(String::from("b")))
+                vec![Arrow(vec![], Box::new(Const(String::from("a")).into())).into()],
+                Box::new(Const(String::from("b")).into())
             ))
         );
         assert_eq!(
@@ -220,11 +337,17 @@ mod tests {
         assert_eq!(
             collect("a -> ( a , b ) -> b"),
             Some(Arrow(
-                vec![Const(String::from("a")),],
-                Box::new(Arrow(
-                    vec![Const(String::from("a")), Const(String::from("b"))],
-                    Box::new(Const(String::from("b")))
-                ))
+                vec![Const(String::from("a")).into(),],
+                Box::new(
+                    Arrow(
+                        vec![
+                            Const(String::from("a")).into(),
+                            Const(String::from("b")).into()
+                        ],
+                        Box::new(Const(String::from("b")).into())
+                    )
+                    .into()
+                )
             ))
         );
         assert_eq!(
@@ -238,8 +361,8 @@ mod tests {
         assert_eq!(
             collect("t[a]"),
             Some(App(
-                Box::new(Const(String::from("t"))),
-                vec![Const(String::from("a"))]
+                Box::new(Const(String::from("t")).into()),
+                vec![Const(String::from("a")).into()]
             ))
         );
         assert_eq!(
@@ -249,8 +372,11 @@ mod tests {
         assert_eq!(
             collect("t[ a , ( b ) ]"),
             Some(App(
-                Box::new(Const(String::from("t"))),
-                vec![Const(String::from("a")), Const(String::from("b"))]
+                Box::new(Const(String::from("t")).into()),
+                vec![
+                    Const(String::from("a")).into(),
+                    Const(String::from("b")).into()
+                ]
             ))
         );
         assert_eq!(
@@ -260,16 +386,21 @@ mod tests {
         assert_eq!(
             collect("t[() -> a, b[(c, d) -> c]]"),
             Some(App(
-                Box::new(Const(String::from("t"))),
+                Box::new(Const(String::from("t")).into()),
                 vec![
-                    Arrow(vec![], Box::new(Const(String::from("a")))),
+                    Arrow(vec![], Box::new(Const(String::from("a")).into())).into(),
                     App(
-                        Box::new(Const(String::from("b"))),
+                        Box::new(Const(String::from("b")).into()),
                         vec![Arrow(
-                            vec![Const(String::from("c")), Const(String::from("d"))],
-                            Box::new(Const(String::from("c")))
-                        )]
+                            vec![
+                                Const(String::from("c")).into(),
+                                Const(String::from("d")).into()
+                            ],
+                            Box::new(Const(String::from("c")).into())
+                        )
+                        .into()]
                     )
+                    .into()
                 ]
             ))
         );
@@ -279,28 +410,286 @@ mod tests {
         );
     }
 
+    #[test]
+    fn tuple() {
+        let (a, b, c) = (
+            || Const(String::from("a")),
+            || Const(String::from("b")),
+            || Const(String::from("c")),
+        );
+        assert_eq!(
+            collect("(a, (b, c))"),
+            Some(Tuple(vec![
+                a().into(),
+                Tuple(vec![b().into(), c().into()]).into()
+            ]))
+        );
+        assert_eq!(
+            collect("(a, b) -> c"),
+            Some(Arrow(vec![a().into(), b().into()], Box::new(c().into())))
+        );
+        assert_eq!(
+            collect("((a, b)) -> c"),
+            Some(Arrow(
+                vec![Tuple(vec![a().into(), b().into()]).into()],
+                Box::new(c().into())
+            ))
+        );
+        assert_eq!(
+            format!("{}", collect("( ( a,b ) ) -> t[(a, b)]").unwrap()),
+            String::from("((a, b)) -> t[(a, b)]")
+        );
+        assert_eq!(
+            format!("{}", collect("((a, b), c) -> (a, b -> c)").unwrap()),
+            String::from("((a, b), c) -> (a, b -> c)")
+        );
+    }
+
+    #[test]
+    fn record() {
+        assert_eq!(
+            collect("{x : int | r}"),
+            Some(Record(
+                vec![(String::from("x"), Const(String::from("int")).into())],
+                Some(Box::new(Const(String::from("r")).into()))
+            ))
+        );
+        assert_eq!(collect("{ }"), Some(Record(vec![], None)));
+        assert_eq!(
+            format!("{}", collect("{ x : a , y : {} }-> {|r}").unwrap()),
+            String::from("{x : a, y : {}} -> {| r}")
+        );
+        assert_eq!(collect("{x : int,}"), None);
+        assert_eq!(collect("{x = int}"), None);
+    }
+
+    #[test]
+    fn variant() {
+        assert_eq!(
+            collect("[Ok : int, Err : e]"),
+            Some(Variant(
+                vec![
+                    (String::from("Ok"), Const(String::from("int")).into()),
+                    (String::from("Err"), Const(String::from("e")).into())
+                ],
+                None
+            ))
+        );
+        assert_eq!(
+            format!("{}", collect("[ None : {} | r ] -> [|r]").unwrap()),
+            String::from("[None : {} | r] -> [| r]")
+        );
+        assert_eq!(collect("[Ok]"), None);
+    }
+
     #[test]
     fn forall() {
         assert_eq!(
             collect("forall[a, b] (a -> b, c) -> a"),
             Some(Forall(
                 vec![String::from("a"), String::from("b")],
-                Box::new(Arrow(
-                    vec![
-                        Arrow(
-                            vec![Const(String::from("a"))],
-                            Box::new(Const(String::from("b")))
-                        ),
-                        Const(String::from("c"))
-                    ],
-                    Box::new(Const(String::from("a")))
-                ))
+                Box::new(
+                    Arrow(
+                        vec![
+                            Arrow(
+                                vec![Const(String::from("a")).into()],
+                                Box::new(Const(String::from("b")).into())
+                            )
+                            .into(),
+                            Const(String::from("c")).into()
+                        ],
+                        Box::new(Const(String::from("a")).into())
+                    )
+                    .into()
+                )
             ))
         );
         assert_eq!(
             format!("{}", collect("forall[a, b] (a -> b, c) -> a").unwrap()),
             String::from("forall[a, b] (a -> b, c) -> a")
         );
+        assert_eq!(
+            collect("(forall[a] a -> a) -> int"),
+            Some(Arrow(
+                vec![Forall(
+                    vec![String::from("a")],
+                    Box::new(
+                        Arrow(
+                            vec![Const(String::from("a")).into()],
+                            Box::new(Const(String::from("a")).into())
+                        )
+                        .into()
+                    )
+                )
+                .into()],
+                Box::new(Const(String::from("int")).into())
+            ))
+        );
+        for nested in [
+            "(forall[a] a -> a) -> int",
+            "int -> forall[a] a -> a",
+            "forall[a] a -> forall[b] (a, b) -> b",
+            "list[forall[a] a -> a]",
+            "(forall[a] a, {x : forall[b] b -> b})",
+        ] {
+            assert_eq!(
+                format!("{}", collect(nested).unwrap()),
+                String::from(nested)
+            );
+        }
+        assert_eq!(
+            collect("forall[a] a -> a -> int"),
+            collect("forall[a] a -> (a -> int)")
+        );
+    }
+
+    #[test]
+    fn qualified() {
+        assert_eq!(
+            collect("forall[a] Eq[a] => a -> bool"),
+            Some(Forall(
+                vec![String::from("a")],
+                Box::new(
+                    Qualified(
+                        vec![(String::from("Eq"), Const(String::from("a")).into())],
+                        Box::new(
+                            Arrow(
+                                vec![Const(String::from("a")).into()],
+                                Box::new(Const(String::from("bool")).into())
+                            )
+                            .into()
+                        )
+                    )
+                    .into()
+                )
+            ))
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                collect("forall[a, b] ( Eq[list[a]] ,Show[b] )=>(a, b) -> string").unwrap()
+            ),
+            String::from("forall[a, b] (Eq[list[a]], Show[b]) => (a, b) -> string")
+        );
+        assert_eq!(collect("Eq[a] => a"), None);
+        assert_eq!(collect("forall[a] Eq[a] =>"), None);
+        assert_eq!(collect("forall[a] Eq[a, a] => a"), None);
+    }
+}
+
+// the types of inference quantify only at the top, so a nested forall is left to System F
+pub(crate) fn first_order(ty: &Spanned<Ty>) -> std::result::Result<(), Box<Spanned<TypeError>>> {
+    match &ty.node {
+        Ty::Const(_) => Ok(()),
+        Ty::App(t, params) => {
+            first_order(t)?;
+            params.iter().try_for_each(first_order)
+        }
+        Ty::Arrow(init, tail) => {
+            init.iter().try_for_each(first_order)?;
+            first_order(tail)
+        }
+        Ty::Tuple(items) => items.iter().try_for_each(first_order),
+        Ty::Record(fields, rest) | Ty::Variant(fields, rest) => {
+            fields
+                .iter()
+                .try_for_each(|(_, field)| first_order(field))?;
+            rest.iter().try_for_each(|rest| first_order(rest))
+        }
+        Ty::Qualified(context, t) => {
+            context.iter().try_for_each(|(_, t)| first_order(t))?;
+            first_order(t)
+        }
+        Ty::Forall(_, _) => Err(Box::new(Spanned::new(
+            TypeError::NestedForall(ty.node.clone()),
+            ty.span,
+        ))),
+    }
+}
+
+// the number of parameters of a builtin or a declared type, None for the other names
+pub(crate) fn arity(name: &str, env: &Env<Type>) -> Option<usize> {
+    match name {
+        "int" | "float" | "bool" | "string" => Some(0),
+        "ref" => Some(1),
+        _ => env.lookup_type(name).map(|data| data.params.len()),
+    }
+}
+
+// every type with a known arity is applied to as many parameters as it takes,
+// where the quantified variables take none, and every row ends with a variable or is closed
+pub(crate) fn kinds(
+    ty: &Spanned<Ty>,
+    arity: &dyn Fn(&str) -> Option<usize>,
+    variable: &dyn Fn(&str) -> bool,
+) -> std::result::Result<(), Box<Spanned<TypeError>>> {
+    let mismatch = |name: &str, expected, found| {
+        Err(Box::new(Spanned::new(
+            TypeError::KindMismatch {
+                name: name.to_owned(),
+                expected,
+                found,
+            },
+            ty.span,
+        )))
+    };
+    match &ty.node {
+        Ty::Const(name) => match arity(name) {
+            Some(expected) if expected != 0 => mismatch(name, expected, 0),
+            _ => Ok(()),
+        },
+        Ty::App(t, params) => {
+            match &t.node {
+                Ty::Const(name) => match arity(name) {
+                    Some(expected) if expected != params.len() => {
+                        return mismatch(name, expected, params.len());
+                    }
+                    _ => {}
+                },
+                _ => kinds(t, arity, variable)?,
+            }
+            params
+                .iter()
+                .try_for_each(|param| kinds(param, arity, variable))
+        }
+        Ty::Arrow(init, tail) => {
+            init.iter()
+                .try_for_each(|param| kinds(param, arity, variable))?;
+            kinds(tail, arity, variable)
+        }
+        Ty::Tuple(items) => items
+            .iter()
+            .try_for_each(|item| kinds(item, arity, variable)),
+        Ty::Record(fields, rest) | Ty::Variant(fields, rest) => {
+            fields
+                .iter()
+                .try_for_each(|(_, field)| kinds(field, arity, variable))?;
+            match rest {
+                Some(rest) if matches!(&rest.node, Ty::Const(name) if variable(name)) => Ok(()),
+                Some(rest) => Err(Box::new(Spanned::new(
+                    TypeError::NotARow(rest.node.clone()),
+                    rest.span,
+                ))),
+                None => Ok(()),
+            }
+        }
+        Ty::Qualified(context, t) => {
+            context
+                .iter()
+                .try_for_each(|(_, t)| kinds(t, arity, variable))?;
+            kinds(t, arity, variable)
+        }
+        Ty::Forall(params, t) => {
+            let quantified = |name: &str| params.iter().any(|param| param == name);
+            kinds(
+                t,
+                &|name| match quantified(name) {
+                    true => Some(0),
+                    false => arity(name),
+                },
+                &|name| quantified(name) || variable(name),
+            )
+        }
     }
 }
 
@@ -309,8 +698,20 @@ pub enum Type {
     Const(String),
     App(Box<Type>, Vec<Type>),
     Arrow(Vec<Type>, Box<Type>),
+    Tuple(Vec<Type>),
+    Record(Box<Type>),
+    Variant(Box<Type>),
+    // a scheme whose generic variables are constrained to instances of the classes
+    Qualified(Vec<(String, Type)>, Box<Type>),
+    // rows are the field lists of records, extended one label at a time
+    RowEmpty,
+    RowExtend(String, Box<Type>, Box<Type>),
     TypeVar(Id, Level),
     Generic(Id),
+    // rigid variable of an annotation, unifies only with itself
+    Skolem(Id, String),
+    // the type of a term that failed to infer, unifies with anything and learns nothing
+    Error,
 }
 
 impl From<(Id, Option<Level>)> for Type {
@@ -334,22 +735,45 @@ impl Bindable for Type {
 }
 
 impl Type {
-    fn from(ty: Ty, gen: &mut Gen<Type>) -> Self {
+    pub(crate) fn from(ty: Ty, gen: &mut Gen<Type>) -> Self {
         let mut env = Env::new();
         match ty {
             Ty::Const(name) => Type::Const(name),
             Ty::App(ty, params) => Type::App(
-                Box::new(Type::from(*ty, gen)),
+                Box::new(Type::from(ty.node, gen)),
                 params
                     .into_iter()
-                    .map(|t_param| Type::from(t_param, gen))
+                    .map(|t_param| Type::from(t_param.node, gen))
                     .collect(),
             ),
             Ty::Arrow(init, tail) => Type::Arrow(
                 init.into_iter()
-                    .map(|t_param| Type::from(t_param, gen))
+                    .map(|t_param| Type::from(t_param.node, gen))
                     .collect(),
-                Box::new(Type::from(*tail, gen)),
+                Box::new(Type::from(tail.node, gen)),
+            ),
+            Ty::Tuple(items) => Type::Tuple(
+                items
+                    .into_iter()
+                    .map(|item| Type::from(item.node, gen))
+                    .collect(),
+            ),
+            Ty::Record(fields, rest) => {
+                Type::Record(Box::new(Type::row(fields, rest, &mut |ty| {
+                    Type::from(ty, gen)
+                })))
+            }
+            Ty::Variant(fields, rest) => {
+                Type::Variant(Box::new(Type::row(fields, rest, &mut |ty| {
+                    Type::from(ty, gen)
+                })))
+            }
+            Ty::Qualified(context, ty) => Type::Qualified(
+                context
+                    .into_iter()
+                    .map(|(class, ty)| (class, Type::from(ty.node, gen)))
+                    .collect(),
+                Box::new(Type::from(ty.node, gen)),
             ),
             Ty::Forall(params, arrow) => {
                 for param in params {
@@ -357,37 +781,90 @@ impl Type {
                     env.insert(&param, t_param);
                 }
 
-                fn lookup(ty: Ty, env: &Env<Type>) -> Type {
-                    match ty {
-                        Ty::Const(name) => env
-                            .lookup(&name)
-                            .map(|t_const| t_const.clone())
-                            .unwrap_or(Type::Const(name)),
-                        Ty::App(ty, params) => Type::App(
-                            Box::new(lookup(*ty, env)),
-                            params
-                                .into_iter()
-                                .map(|t_param| lookup(t_param, env))
-                                .collect(),
-                        ),
-                        Ty::Arrow(init, tail) => Type::Arrow(
-                            init.into_iter()
-                                .map(|t_param| lookup(t_param, env))
-                                .collect(),
-                            Box::new(lookup(*tail, env)),
-                        ),
-                        Ty::Forall(_, _) => unreachable!("Nested forall is not possible"),
-                    }
-                }
+                Type::lookup(arrow.node, &env)
+            }
+        }
+    }
 
-                lookup(*arrow, &mut env)
+    // names bound in the environment are substituted, the others are constants
+    pub(crate) fn lookup(ty: Ty, env: &Env<Type>) -> Self {
+        match ty {
+            Ty::Const(name) => env.lookup(&name).cloned().unwrap_or(Type::Const(name)),
+            Ty::App(ty, params) => Type::App(
+                Box::new(Type::lookup(ty.node, env)),
+                params
+                    .into_iter()
+                    .map(|t_param| Type::lookup(t_param.node, env))
+                    .collect(),
+            ),
+            Ty::Arrow(init, tail) => Type::Arrow(
+                init.into_iter()
+                    .map(|t_param| Type::lookup(t_param.node, env))
+                    .collect(),
+                Box::new(Type::lookup(tail.node, env)),
+            ),
+            Ty::Tuple(items) => Type::Tuple(
+                items
+                    .into_iter()
+                    .map(|item| Type::lookup(item.node, env))
+                    .collect(),
+            ),
+            Ty::Record(fields, rest) => {
+                Type::Record(Box::new(Type::row(fields, rest, &mut |ty| {
+                    Type::lookup(ty, env)
+                })))
             }
+            Ty::Variant(fields, rest) => {
+                Type::Variant(Box::new(Type::row(fields, rest, &mut |ty| {
+                    Type::lookup(ty, env)
+                })))
+            }
+            Ty::Qualified(context, ty) => Type::Qualified(
+                context
+                    .into_iter()
+                    .map(|(class, ty)| (class, Type::lookup(ty.node, env)))
+                    .collect(),
+                Box::new(Type::lookup(ty.node, env)),
+            ),
+            Ty::Forall(_, _) => unreachable!("Nested forall is rejected before the conversion"),
         }
     }
 
-    pub fn from_str(s: &str, gen: &mut Gen<Type>) -> std::result::Result<Self, String> {
-        let ty = Ty::from_str(s)?;
-        Ok(Type::from(ty, gen))
+    fn row(
+        fields: Vec<(String, Spanned<Ty>)>,
+        rest: Option<Box<Spanned<Ty>>>,
+        convert: &mut dyn FnMut(Ty) -> Type,
+    ) -> Self {
+        let rest = rest.map_or(Type::RowEmpty, |rest| convert(rest.node));
+        fields.into_iter().rev().fold(rest, |row, (label, field)| {
+            Type::RowExtend(label, Box::new(convert(field.node)), Box::new(row))
+        })
+    }
+
+    pub fn from_str(s: &str, gen: &mut Gen<Type>) -> std::result::Result<Self, Error> {
+        Type::from_str_with(s, gen, &Env::new())
+    }
+
+    // the types declared in the environment are checked to be applied to their parameters
+    pub fn from_str_with(
+        s: &str,
+        gen: &mut Gen<Type>,
+        env: &Env<Type>,
+    ) -> std::result::Result<Self, Error> {
+        Ok(Type::checked(Ty::parse(s)?, gen, env)?)
+    }
+
+    pub(crate) fn checked(
+        ty: Spanned<Ty>,
+        gen: &mut Gen<Type>,
+        env: &Env<Type>,
+    ) -> std::result::Result<Self, Box<Spanned<TypeError>>> {
+        match &ty.node {
+            Ty::Forall(_, t) => first_order(t)?,
+            _ => first_order(&ty)?,
+        }
+        kinds(&ty, &|name| arity(name, env), &|_| false)?;
+        Ok(Type::from(ty.node, gen))
     }
 }
 
@@ -397,12 +874,47 @@ impl std::fmt::Display for Type {
             let mut name = String::new();
             while id != 0 {
                 id -= 1;
-                name.insert(0, ('a' as u8 + (id % 26) as u8) as char);
+                name.insert(0, (b'a' + (id % 26) as u8) as char);
                 id /= 26;
             }
             name
         }
 
+        // row variables are named apart from the others, as r, r1, r2...
+        fn name(id: Id, row: bool, generics: &mut HashMap<Id, String>) -> String {
+            if let Some(name) = generics.get(&id) {
+                return name.clone();
+            }
+            let name = (0..)
+                .map(|i| match (row, i) {
+                    (true, 0) => String::from("r"),
+                    (true, i) => format!("r{}", i),
+                    (false, i) => id_to_name(i + 1),
+                })
+                .find(|name| !generics.values().any(|other| other == name))
+                .unwrap_or_default();
+            generics.insert(id, name.clone());
+            name
+        }
+
+        fn row_to_string(mut row: &Type, generics: &mut HashMap<Id, String>) -> String {
+            let mut fields = Vec::new();
+            while let Type::RowExtend(label, field, rest) = row {
+                fields.push(format!("{} : {}", label, to_string(field, generics)));
+                row = rest;
+            }
+            let fields = fields.join(", ");
+            match row {
+                Type::RowEmpty => fields,
+                Type::Generic(id) if fields.is_empty() => {
+                    format!("| {}", name(*id, true, generics))
+                }
+                Type::Generic(id) => format!("{} | {}", fields, name(*id, true, generics)),
+                rest if fields.is_empty() => format!("| {}", to_string(rest, generics)),
+                rest => format!("{} | {}", fields, to_string(rest, generics)),
+            }
+        }
+
         fn to_string(t: &Type, generics: &mut HashMap<Id, String>) -> String {
             match t {
                 Type::Const(name) => name.clone(),
@@ -411,8 +923,8 @@ impl std::fmt::Display for Type {
                     if !params.is_empty() {
                         string += &to_string(&params[0], generics);
                     }
-                    for i in 1..params.len() {
-                        string += &format!(", {}", to_string(&params[i], generics));
+                    for param in params.iter().skip(1) {
+                        string += &format!(", {}", to_string(param, generics));
                     }
                     string + "]"
                 }
@@ -420,39 +932,60 @@ impl std::fmt::Display for Type {
                     let mut string: String;
                     match init.len() {
                         0 => {
-                            string = format!("() -> ");
+                            string = String::from("() -> ");
                         }
                         1 => match init[0] {
                             Type::Const(_)
                             | Type::App(_, _)
+                            | Type::Record(_)
+                            | Type::Variant(_)
                             | Type::TypeVar(_, _)
-                            | Type::Generic(_) => {
+                            | Type::Generic(_)
+                            | Type::Skolem(_, _)
+                            | Type::Error => {
                                 string = format!("{} -> ", to_string(&init[0], generics));
                             }
                             _ => {
                                 string = format!("({}) -> ", to_string(&init[0], generics));
                             }
                         },
-                        len @ _ => {
+                        _ => {
                             string = format!("({}", to_string(&init[0], generics));
-                            for i in 1..len {
-                                string += &format!(", {}", to_string(&init[i], generics));
+                            for param in init.iter().skip(1) {
+                                string += &format!(", {}", to_string(param, generics));
                             }
                             string.push_str(") -> ");
                         }
                     };
-                    string + &format!("{}", to_string(tail, generics))
+                    string + &to_string(tail, generics)
                 }
-                Type::TypeVar(id, level) => format!("[{},{}]", id, level),
-                Type::Generic(id) => {
-                    if let Some(name) = generics.get(id) {
-                        format!("{}", name)
-                    } else {
-                        let name = id_to_name(generics.len() + 1);
-                        generics.insert(*id, name.clone());
-                        name
+                Type::Tuple(items) => {
+                    let items: Vec<String> =
+                        items.iter().map(|item| to_string(item, generics)).collect();
+                    format!("({})", items.join(", "))
+                }
+                Type::Record(row) => format!("{{{}}}", row_to_string(row, generics)),
+                Type::Variant(row) => format!("[{}]", row_to_string(row, generics)),
+                Type::RowEmpty | Type::RowExtend(_, _, _) => {
+                    format!("{{{}}}", row_to_string(t, generics))
+                }
+                Type::Qualified(context, t) => {
+                    let t = to_string(t, generics);
+                    let context: Vec<String> = context
+                        .iter()
+                        .map(|(class, t)| format!("{}[{}]", class, to_string(t, generics)))
+                        .collect();
+                    match context.len() {
+                        1 => format!("{} => {}", context[0], t),
+                        _ => format!("({}) => {}", context.join(", "), t),
                     }
                 }
+                // the variables below the top level are the weak ones, never generalized
+                Type::TypeVar(id, level) if *level < 0 => format!("_weak{}", id),
+                Type::TypeVar(id, level) => format!("[{},{}]", id, level),
+                Type::Skolem(_, name) => name.clone(),
+                Type::Generic(id) => name(*id, false, generics),
+                Type::Error => String::from("?"),
             }
         }
 
@@ -5,6 +5,7 @@
  * LICENSE file in the root directory of this source tree.
  */
 
+use crate::UnionFind;
 use std::collections::HashMap;
 
 pub type Id = usize;
@@ -40,64 +41,334 @@ where
         T::from((next_id, level))
     }
 
+    pub fn newid(&mut self) -> Id {
+        let next_id = self.id;
+        self.id += 1;
+        next_id
+    }
+
     pub fn reset(&mut self) {
         self.id = 1;
     }
 }
 
+impl<T> Default for Gen<T>
+where
+    T: From<(Id, Option<Level>)>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct DataType {
+    pub params: Vec<String>,
+    pub constructors: Vec<String>,
+}
+
+// the method types are over the parameter, without the constraint of the class itself
+#[derive(PartialEq, Clone, Debug)]
+pub struct Class<T> {
+    pub param: T,
+    pub methods: Vec<(String, T)>,
+}
+
+// the head is an instance of the class whenever the context holds
+#[derive(PartialEq, Clone, Debug)]
+pub struct Instance<T> {
+    pub context: Vec<(String, T)>,
+    pub head: T,
+}
+
+// what a change to the environment overwrote, undone in reverse order
+#[derive(Clone)]
+enum Change<T> {
+    Name(String, Option<T>),
+    Type(String, Option<DataType>),
+    Constructor(String, Option<T>),
+    Class(String, Option<Class<T>>, Vec<Instance<T>>),
+    Instance(String),
+}
+
+// the point the environment goes back to on a rollback
+#[must_use]
+pub struct Snapshot {
+    changes: usize,
+    bound: (usize, usize),
+}
+
 #[derive(Clone)]
 pub struct Env<T>
 where
     T: From<(Id, Option<Level>)> + Bindable,
 {
     env: HashMap<String, T>,
-    bound: HashMap<(Id, Level), T>,
+    bound: UnionFind<T>,
+    types: HashMap<String, DataType>,
+    constructors: HashMap<String, String>,
+    // the constructor schemes, which stay the same when a binding shadows the name
+    schemes: HashMap<String, T>,
+    classes: HashMap<String, Class<T>>,
+    instances: HashMap<String, Vec<Instance<T>>>,
+    // the names every open scope shadowed with their old values, restored when it is popped
+    scopes: Vec<Vec<(String, Option<T>)>>,
+    // the changes since the oldest open snapshot
+    changes: Vec<Change<T>>,
+    snapshots: usize,
 }
 
 impl<T> Env<T>
 where
-    T: From<(Id, Option<Level>)> + Bindable + std::fmt::Display,
+    T: From<(Id, Option<Level>)> + Bindable + Clone + std::fmt::Display,
 {
     pub fn new() -> Self {
         Env {
             env: HashMap::new(),
-            bound: HashMap::new(),
+            bound: UnionFind::new(),
+            types: HashMap::new(),
+            constructors: HashMap::new(),
+            schemes: HashMap::new(),
+            classes: HashMap::new(),
+            instances: HashMap::new(),
+            scopes: Vec::new(),
+            changes: Vec::new(),
+            snapshots: 0,
+        }
+    }
+
+    fn record(&mut self, change: impl FnOnce() -> Change<T>) {
+        if self.snapshots > 0 {
+            self.changes.push(change());
         }
     }
 
     pub fn insert(&mut self, k: &str, v: T) -> Option<T> {
-        self.env.insert(k.to_owned(), v.into())
+        self.replace(k, Some(v))
     }
 
     pub fn remove(&mut self, k: &str) -> Option<T> {
-        self.env.remove(k)
+        self.replace(k, None)
+    }
+
+    fn replace(&mut self, k: &str, v: Option<T>) -> Option<T> {
+        let old = match v {
+            Some(v) => self.env.insert(k.to_owned(), v),
+            None => self.env.remove(k),
+        };
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push((k.to_owned(), old.clone()));
+        }
+        self.record(|| Change::Name(k.to_owned(), old.clone()));
+        old
+    }
+
+    // the names inserted or removed until the scope is popped are only visible within it
+    pub fn push_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    pub fn pop_scope(&mut self) {
+        let shadowed = self
+            .scopes
+            .pop()
+            .expect("Scopes are pushed before they are popped");
+        for (k, v) in shadowed.into_iter().rev() {
+            let old = match v {
+                Some(v) => self.env.insert(k.clone(), v),
+                None => self.env.remove(&k),
+            };
+            self.record(|| Change::Name(k, old));
+        }
     }
 
     pub fn lookup(&self, k: &str) -> Option<&T> {
         self.env.get(k)
     }
 
-    pub fn bind(&mut self, id: Id, level: Level, v: T) -> Option<T> {
-        self.bound.insert((id, level), v)
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &T)> {
+        self.env.iter()
     }
 
-    pub fn unbind(&mut self, id: Id, level: Level) -> Option<T> {
-        self.bound.remove(&(id, level))
+    // the constructor schemes are inserted with insert_constructor
+    pub fn insert_type(&mut self, name: &str, data: DataType) -> Option<DataType> {
+        let old = self.replace_type(name, Some(data));
+        self.record(|| Change::Type(name.to_owned(), old.clone()));
+        old
     }
 
-    pub fn lookup_binding(&self, mut id: Id, mut level: Level) -> Option<&T> {
-        let mut prev: Option<&T> = None;
-        loop {
-            if let Some(next) = self.bound.get(&(id, level)) {
-                prev = Some(next);
-                if let Some((id2, level2)) = Bindable::get_unbound_id_level(next) {
-                    id = id2;
-                    level = level2;
-                    continue;
+    fn replace_type(&mut self, name: &str, data: Option<DataType>) -> Option<DataType> {
+        let old = self.types.remove(name);
+        if let Some(old) = &old {
+            for constructor in &old.constructors {
+                self.constructors.remove(constructor);
+            }
+        }
+        if let Some(data) = data {
+            for constructor in &data.constructors {
+                self.constructors
+                    .insert(constructor.to_owned(), name.to_owned());
+            }
+            self.types.insert(name.to_owned(), data);
+        }
+        old
+    }
+
+    pub fn lookup_type(&self, name: &str) -> Option<&DataType> {
+        self.types.get(name)
+    }
+
+    pub fn types(&self) -> impl Iterator<Item = (&String, &DataType)> {
+        self.types.iter()
+    }
+
+    pub fn lookup_constructor(&self, constructor: &str) -> Option<&String> {
+        self.constructors.get(constructor)
+    }
+
+    // the scheme is inserted as an ordinary name as well
+    pub fn insert_constructor(&mut self, constructor: &str, scheme: T) -> Option<T> {
+        let old = self.schemes.insert(constructor.to_owned(), scheme.clone());
+        self.record(|| Change::Constructor(constructor.to_owned(), old.clone()));
+        self.insert(constructor, scheme);
+        old
+    }
+
+    pub fn lookup_constructor_scheme(&self, constructor: &str) -> Option<&T> {
+        self.schemes.get(constructor)
+    }
+
+    // the method schemes are inserted as ordinary names, the instances of an old class are dropped
+    pub fn insert_class(&mut self, name: &str, class: Class<T>) -> Option<Class<T>> {
+        let instances = self.instances.remove(name).unwrap_or_default();
+        let old = self.classes.insert(name.to_owned(), class);
+        self.record(|| Change::Class(name.to_owned(), old.clone(), instances));
+        old
+    }
+
+    pub fn lookup_class(&self, name: &str) -> Option<&Class<T>> {
+        self.classes.get(name)
+    }
+
+    pub fn classes(&self) -> impl Iterator<Item = (&String, &Class<T>)> {
+        self.classes.iter()
+    }
+
+    pub fn insert_instance(&mut self, class: &str, instance: Instance<T>) {
+        self.instances
+            .entry(class.to_owned())
+            .or_default()
+            .push(instance);
+        self.record(|| Change::Instance(class.to_owned()));
+    }
+
+    pub fn instances(&self, class: &str) -> &[Instance<T>] {
+        self.instances.get(class).map_or(&[], |instances| instances)
+    }
+
+    // everything changed after the snapshot is tentative until it is committed
+    pub fn snapshot(&mut self) -> Snapshot {
+        self.snapshots += 1;
+        Snapshot {
+            changes: self.changes.len(),
+            bound: self.bound.snapshot(),
+        }
+    }
+
+    // the changes are kept, they are forgotten with the last open snapshot
+    pub fn commit(&mut self, snapshot: Snapshot) {
+        self.snapshots -= 1;
+        if self.snapshots == 0 {
+            self.changes.clear();
+        }
+        self.bound.commit(snapshot.bound);
+    }
+
+    pub fn rollback(&mut self, snapshot: Snapshot) {
+        for change in self.changes.split_off(snapshot.changes).into_iter().rev() {
+            match change {
+                Change::Name(k, Some(v)) => {
+                    self.env.insert(k, v);
+                }
+                Change::Name(k, None) => {
+                    self.env.remove(&k);
+                }
+                Change::Type(name, data) => {
+                    self.replace_type(&name, data);
+                }
+                Change::Constructor(constructor, Some(scheme)) => {
+                    self.schemes.insert(constructor, scheme);
+                }
+                Change::Constructor(constructor, None) => {
+                    self.schemes.remove(&constructor);
+                }
+                Change::Class(name, class, instances) => {
+                    match class {
+                        Some(class) => self.classes.insert(name.clone(), class),
+                        None => self.classes.remove(&name),
+                    };
+                    self.instances.insert(name, instances);
+                }
+                Change::Instance(class) => {
+                    self.instances.get_mut(&class).and_then(Vec::pop);
                 }
             }
-            break;
         }
-        prev
+        self.snapshots -= 1;
+        self.bound.rollback(snapshot.bound);
+    }
+
+    pub fn bind(&mut self, id: Id, level: Level, v: T) -> Option<T> {
+        self.bound.bind(id, level, v)
+    }
+
+    // the type the variable is bound to, or the variable it resolves to at its current level
+    pub fn lookup_binding(&self, id: Id, level: Level) -> Option<&T> {
+        self.bound.lookup(id, level)
+    }
+}
+
+impl<T> Default for Env<T>
+where
+    T: From<(Id, Option<Level>)> + Bindable + Clone + std::fmt::Display,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Type;
+
+    #[test]
+    fn scopes() {
+        let int = || Type::Const(String::from("int"));
+        let bool = || Type::Const(String::from("bool"));
+        let mut env = Env::new();
+        env.insert("x", int());
+
+        env.push_scope();
+        env.insert("x", bool());
+        env.insert("y", bool());
+        env.push_scope();
+        env.remove("x");
+        assert_eq!(env.lookup("x"), None);
+        env.pop_scope();
+        assert_eq!(env.lookup("x"), Some(&bool()));
+        env.pop_scope();
+        assert_eq!(env.lookup("x"), Some(&int()));
+        assert_eq!(env.lookup("y"), None);
+
+        // a rollback undoes what the scopes restored as well
+        let snapshot = env.snapshot();
+        env.push_scope();
+        env.insert("x", bool());
+        env.pop_scope();
+        env.insert("y", int());
+        env.rollback(snapshot);
+        assert_eq!(env.lookup("x"), Some(&int()));
+        assert_eq!(env.lookup("y"), None);
     }
 }
@@ -0,0 +1,333 @@
+/*
+ * Copyright (c) 2021, Andrei Yaskovets
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+use tokenstream::{Position, Spanned};
+
+use crate::{Id, Pattern, Term, Token, Ty, Type};
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum ParseError {
+    Syntax {
+        position: Position,
+        expected: Vec<String>,
+        found: Option<Token>,
+    },
+    Lexical {
+        position: Position,
+        found: char,
+    },
+    Escape {
+        position: Position,
+        found: char,
+    },
+}
+
+impl ParseError {
+    pub fn position(&self) -> Position {
+        match self {
+            ParseError::Syntax { position, .. }
+            | ParseError::Lexical { position, .. }
+            | ParseError::Escape { position, .. } => *position,
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            ParseError::Syntax {
+                position,
+                expected,
+                found,
+            } => {
+                match expected.split_last() {
+                    None => write!(fmt, "unexpected input")?,
+                    Some((last, [])) => write!(fmt, "expected {}", last)?,
+                    Some((last, init)) => write!(fmt, "expected {} or {}", init.join(", "), last)?,
+                }
+                write!(fmt, " at {}, found ", position)?;
+                match found {
+                    Some(token) => write!(fmt, "`{}`", token),
+                    None => write!(fmt, "end of input"),
+                }
+            }
+            ParseError::Lexical { position, found } => {
+                write!(fmt, "unexpected character `{}` at {}", found, position)
+            }
+            ParseError::Escape { position, found } => {
+                write!(fmt, "unknown escape `\\{}` at {}", found, position)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum TypeError {
+    Mismatch {
+        expected: Type,
+        found: Type,
+    },
+    InfiniteType {
+        var: Id,
+        ty: Type,
+    },
+    MultipleInstances {
+        var: Id,
+    },
+    ArityMismatch {
+        expected: usize,
+        found: usize,
+    },
+    UndefinedVariable(String),
+    NotAFunction(Type),
+    DuplicateBinder {
+        name: String,
+        term: Box<Term>,
+    },
+    BranchMismatch {
+        then: Type,
+        otherwise: Type,
+    },
+    LessGeneral {
+        declared: Type,
+        inferred: Type,
+    },
+    UndefinedType(String),
+    DuplicateDefinition(String),
+    UndefinedConstructor(String),
+    NonExhaustive(Pattern),
+    UnreachablePattern(Pattern),
+    MissingLabel(String),
+    UndefinedClass(String),
+    InvalidInstance {
+        class: String,
+        head: Ty,
+    },
+    MissingInstance {
+        class: String,
+        ty: Type,
+    },
+    Ambiguous {
+        class: String,
+    },
+    UndefinedMethod {
+        class: String,
+        method: String,
+    },
+    MissingMethod {
+        class: String,
+        method: String,
+    },
+    NestedForall(Ty),
+    UnequalArity {
+        expected: Type,
+        found: Type,
+        arities: (usize, usize),
+    },
+    KindMismatch {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    NotARow(Ty),
+    Internal(String),
+}
+
+impl std::fmt::Display for TypeError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            TypeError::Mismatch { expected, found } => {
+                write!(fmt, "Cannot unify {} with {}", expected, found)
+            }
+            TypeError::InfiniteType { .. } => write!(fmt, "Infinite type"),
+            TypeError::MultipleInstances { .. } => write!(fmt, "Multiple instances of variable"),
+            TypeError::ArityMismatch { expected, found } => write!(
+                fmt,
+                "Incorrect number of arguments: expected {}, found {}",
+                expected, found
+            ),
+            TypeError::UndefinedVariable(name) => write!(fmt, "Undefined variable '{}'", name),
+            TypeError::NotAFunction(ty) => write!(fmt, "Invalid type of function: {}", ty),
+            TypeError::DuplicateBinder { name, term } => {
+                write!(fmt, "Conflicting definitions of {} in {}", name, term)
+            }
+            TypeError::BranchMismatch { then, otherwise } => write!(
+                fmt,
+                "Branches of if have different types: then is {}, else is {}",
+                then, otherwise
+            ),
+            TypeError::LessGeneral { declared, inferred } => write!(
+                fmt,
+                "Type {} is less general than the annotation {}",
+                inferred, declared
+            ),
+            TypeError::UndefinedType(name) => write!(fmt, "Undefined type '{}'", name),
+            TypeError::DuplicateDefinition(name) => {
+                write!(fmt, "Conflicting definitions of {}", name)
+            }
+            TypeError::UndefinedConstructor(name) => {
+                write!(fmt, "Undefined constructor '{}'", name)
+            }
+            TypeError::NonExhaustive(missing) => {
+                write!(fmt, "Non-exhaustive match, {} is not covered", missing)
+            }
+            TypeError::UnreachablePattern(pattern) => {
+                write!(fmt, "Unreachable pattern {}", pattern)
+            }
+            TypeError::MissingLabel(label) => write!(fmt, "Row has no label {}", label),
+            TypeError::UndefinedClass(name) => write!(fmt, "Undefined class '{}'", name),
+            TypeError::InvalidInstance { class, head } => write!(
+                fmt,
+                "Instance {}[{}] is not a type constructor applied to distinct variables",
+                class, head
+            ),
+            TypeError::MissingInstance { class, ty } => {
+                write!(fmt, "No instance of {} for {}", class, ty)
+            }
+            TypeError::Ambiguous { class } => write!(
+                fmt,
+                "Ambiguous type variable in a constraint of class {}",
+                class
+            ),
+            TypeError::UndefinedMethod { class, method } => {
+                write!(fmt, "Class {} has no method '{}'", class, method)
+            }
+            TypeError::MissingMethod { class, method } => {
+                write!(fmt, "Instance of {} does not define '{}'", class, method)
+            }
+            TypeError::NestedForall(ty) => {
+                write!(
+                    fmt,
+                    "Type {} is not first order, only System F checks it",
+                    ty
+                )
+            }
+            TypeError::UnequalArity {
+                expected,
+                found,
+                arities: (arity1, arity2),
+            } => write!(
+                fmt,
+                "Cannot unify {} of arity {} with {} of arity {}",
+                expected, arity1, found, arity2
+            ),
+            TypeError::KindMismatch {
+                name,
+                expected,
+                found,
+            } => write!(
+                fmt,
+                "Type {} takes {} parameter{}, found {}",
+                name,
+                expected,
+                if *expected == 1 { "" } else { "s" },
+                found
+            ),
+            TypeError::NotARow(ty) => {
+                write!(fmt, "Type {} is not a row variable to extend", ty)
+            }
+            TypeError::Internal(message) => write!(fmt, "Internal error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for TypeError {}
+
+// the errors of the explicitly typed terms, which are checked and never inferred
+#[derive(PartialEq, Clone, Debug)]
+pub enum SystemFError {
+    Mismatch { expected: Ty, found: Ty },
+    UndefinedVariable(String),
+    NotAFunction(Ty),
+    NotPolymorphic(Ty),
+    ArityMismatch { expected: usize, found: usize },
+    TypeArityMismatch { expected: usize, found: usize },
+    UndefinedType(String),
+    NotARecord(Ty),
+    MissingField(String),
+    InvalidPattern { pattern: Pattern, ty: Ty },
+}
+
+impl std::fmt::Display for SystemFError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            SystemFError::Mismatch { expected, found } => {
+                write!(fmt, "Expected type {}, found {}", expected, found)
+            }
+            SystemFError::UndefinedVariable(name) => write!(fmt, "Undefined variable '{}'", name),
+            SystemFError::NotAFunction(ty) => write!(fmt, "Invalid type of function: {}", ty),
+            SystemFError::NotPolymorphic(ty) => {
+                write!(fmt, "Type {} cannot be applied to types", ty)
+            }
+            SystemFError::ArityMismatch { expected, found } => write!(
+                fmt,
+                "Incorrect number of arguments: expected {}, found {}",
+                expected, found
+            ),
+            SystemFError::TypeArityMismatch { expected, found } => write!(
+                fmt,
+                "Incorrect number of type arguments: expected {}, found {}",
+                expected, found
+            ),
+            SystemFError::UndefinedType(name) => write!(fmt, "Undefined type '{}'", name),
+            SystemFError::NotARecord(ty) => write!(fmt, "Type {} is not a record", ty),
+            SystemFError::MissingField(label) => write!(fmt, "Record has no field {}", label),
+            SystemFError::InvalidPattern { pattern, ty } => {
+                write!(fmt, "Pattern {} does not match type {}", pattern, ty)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SystemFError {}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum Error {
+    Parse(ParseError),
+    Type(Box<Spanned<TypeError>>),
+    SystemF(Box<Spanned<SystemFError>>),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            Error::Parse(error) => write!(fmt, "{}", error),
+            Error::Type(error) => write!(fmt, "{}", error),
+            Error::SystemF(error) => write!(fmt, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Parse(error) => Some(error),
+            Error::Type(error) => Some(&error.node),
+            Error::SystemF(error) => Some(&error.node),
+        }
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(error: ParseError) -> Self {
+        Error::Parse(error)
+    }
+}
+
+impl From<Box<Spanned<TypeError>>> for Error {
+    fn from(error: Box<Spanned<TypeError>>) -> Self {
+        Error::Type(error)
+    }
+}
+
+impl From<Box<Spanned<SystemFError>>> for Error {
+    fn from(error: Box<Spanned<SystemFError>>) -> Self {
+        Error::SystemF(error)
+    }
+}
@@ -8,6 +8,9 @@
 mod algorithm_w;
 pub use algorithm_w::*;
 
+mod system_f;
+pub use system_f::*;
+
 mod token;
 pub use token::*;
 
@@ -16,3 +19,9 @@ pub use util::*;
 
 mod env;
 pub use env::*;
+
+mod union_find;
+pub use union_find::*;
+
+mod error;
+pub use error::*;
@@ -1,180 +1,342 @@
-pub use tokenstream::{Parser, Stream, Tokenizer};
+pub use tokenstream::Spanned;
 pub use typesystems::*;
 
-use std::str::FromStr;
+use std::io::{BufRead, IsTerminal, Write};
 
-fn test() -> Result<(), String> {
-    struct Testing {
-        env: Env<Type>,
-        gen: Gen<Type>,
+const PRELUDE_DECLS: &[&str] = &[
+    "type list[a] = nil | cons(a, list[a])",
+    "type pair[a, b] = pair(a, b)",
+    "class Eq[a] { eq : (a, a) -> bool }",
+    "instance Eq[int]",
+    "instance Eq[float]",
+    "instance Eq[bool]",
+    "instance Eq[string]",
+    "instance Eq[a] => Eq[list[a]]",
+    "instance (Eq[a], Eq[b]) => Eq[pair[a, b]]",
+    "instance (Eq[a], Eq[b]) => Eq[(a, b)]",
+];
+
+const PRELUDE: &[(&str, &str)] = &[
+    ("head", "forall[a] list[a] -> a"),
+    ("tail", "forall[a] list[a] -> list[a]"),
+    ("cons_curry", "forall[a] a -> list[a] -> list[a]"),
+    ("map", "forall[a, b] (a -> b, list[a]) -> list[b]"),
+    ("map_curry", "forall[a, b] (a -> b) -> list[a] -> list[b]"),
+    ("one", "int"),
+    ("zero", "int"),
+    ("succ", "int -> int"),
+    ("plus", "(int, int) -> int"),
+    ("eq_curry", "forall[a] Eq[a] => a -> a -> bool"),
+    ("not", "bool -> bool"),
+    ("pair_curry", "forall[a, b] a -> b -> pair[a, b]"),
+    ("first", "forall[a, b] pair[a, b] -> a"),
+    ("second", "forall[a, b] pair[a, b] -> b"),
+    ("id", "forall[a] a -> a"),
+    ("const", "forall[a, b] a -> b -> a"),
+    ("apply", "forall[a, b] (a -> b, a) -> b"),
+    ("apply_curry", "forall[a, b] (a -> b) -> a -> b"),
+    ("choose", "forall[a] (a, a) -> a"),
+    ("choose_curry", "forall[a] a -> a -> a"),
+    ("+", "(int, int) -> int"),
+    ("-", "(int, int) -> int"),
+    ("*", "(int, int) -> int"),
+    ("/", "(int, int) -> int"),
+    ("%", "(int, int) -> int"),
+    ("<", "(int, int) -> bool"),
+    (">", "(int, int) -> bool"),
+    ("<=", "(int, int) -> bool"),
+    (">=", "(int, int) -> bool"),
+    ("==", "forall[a] Eq[a] => (a, a) -> bool"),
+    ("!=", "forall[a] Eq[a] => (a, a) -> bool"),
+    ("and", "(bool, bool) -> bool"),
+    ("or", "(bool, bool) -> bool"),
+    ("ref", "forall[a] a -> ref[a]"),
+    ("!", "forall[a] ref[a] -> a"),
+    (":=", "forall[a] (ref[a], a) -> {}"),
+];
+
+const HELP: &str = "\
+<expr>                   infer the type of an expression
+let <name> = <expr>      infer, generalize and define a name
+let rec <name> = <expr> and ...
+                         define a group of mutually recursive names
+type <name>[<params>] = <ctor>(<types>) | ...
+                         declare an algebraic data type
+class <name>[<param>] { <method> : <type>, ... }
+                         declare a type class and its overloaded methods
+instance <context> => <class>[<type>]
+                         declare an instance of a class under the context
+match <expr> { <pattern> -> <expr>, ... }
+                         match a value against exhaustive patterns
+{<label> = <expr>, ... | <expr>}
+                         build or extend a record, r.label selects, {r - label} removes
+`<Label>(<expr>)
+                         inject into an open variant, matched by `<Label>(<pattern>)
+ref(<expr>), !<expr>, <expr> := <expr>
+                         allocate, read and write a reference, only values generalize
+:define <name> : <type>  define a name with the given type
+:infixl <prec> <op>      declare a left associative operator
+:infixr <prec> <op>      declare a right associative operator
+:infix <prec> <op>       declare a non-associative operator
+:prefix <prec> <op>      declare a prefix operator
+:type <expr>             infer the type of an expression
+:core <expr|decl>        show the translation with the dictionaries passed explicitly
+:systemf <expr|decl>     show the elaboration to System F, checked against its type
+:check <term>            check the type of an explicitly typed System F term
+:env                     list the definitions
+:reset                   restore the prelude and operators
+:load <file>             run every line of a file
+:quit                    exit";
+
+enum Action {
+    Continue,
+    Quit,
+}
+
+struct Repl {
+    session: Session,
+    ops: Operators,
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        let mut repl = Repl {
+            session: Session::default(),
+            ops: Operators::default(),
+        };
+        repl.reset();
+        repl
     }
 
-    impl Testing {
-        pub fn new() -> Self {
-            Testing {
-                env: Env::new(),
-                gen: Gen::new(),
-            }
+    pub fn reset(&mut self) {
+        self.session = Session::default();
+        self.ops = Operators::default();
+        for decl in PRELUDE_DECLS {
+            let decl = Decl::parse(decl).expect("Prelude declarations are valid");
+            self.session
+                .declare(&decl)
+                .expect("Prelude declarations are valid");
+        }
+        for (name, ty) in PRELUDE {
+            self.session
+                .define(name, ty)
+                .expect("Prelude types are valid");
         }
+    }
 
-        pub fn define(&mut self, name: &str, ty: &str) -> Result<(), String> {
-            self.env.insert(name, Type::from_str(ty, &mut self.gen)?);
-            Ok(())
+    pub fn define(&mut self, name: &str, ty: &str) -> Result<Type, Error> {
+        self.session.define(name, ty)
+    }
+
+    pub fn deduce(&mut self, code: &str) -> Result<Type, Error> {
+        Ok(self.session.infer(&Term::parse_with(code, &self.ops)?)?)
+    }
+
+    pub fn declare(&mut self, decl: &Spanned<Decl>) -> Result<String, Error> {
+        let types = self.session.declare(decl)?;
+        let lines: Vec<String> = types
+            .iter()
+            .map(|(name, ty)| format!("{} : {}", name, ty))
+            .collect();
+        Ok(lines.join("\n"))
+    }
+
+    // the declarations are translated without being kept
+    pub fn core(&mut self, code: &str) -> Result<String, Error> {
+        if let Ok(decl) = Decl::parse_with(code, &self.ops) {
+            let (_, cores) = self
+                .session
+                .tentatively(|session| session.translate_decl(&decl))?;
+            let lines: Vec<String> = cores
+                .iter()
+                .map(|(name, core)| format!("{} = {}", name, core))
+                .collect();
+            return Ok(lines.join("\n"));
         }
+        let (_, core) = self
+            .session
+            .translate(&Term::parse_with(code, &self.ops)?)?;
+        Ok(core.to_string())
+    }
 
-        pub fn deduce(&mut self, code: &str) -> Result<Type, String> {
-            Ok(infer(&Term::from_str(code)?, &mut self.env, &mut self.gen)?)
+    // the elaborated terms are checked independently of the inference
+    pub fn system_f(&mut self, code: &str) -> Result<String, Error> {
+        let check = |term: &Spanned<SystemF>, context: &Context| match typecheck(term, context) {
+            Ok(ty) => format!("{}\n  : {}", term, ty),
+            Err(error) => format!("{}\n  ! {} at {}", term, error.node, error.span.start),
+        };
+        if let Ok(decl) = Decl::parse_with(code, &self.ops) {
+            return self.session.tentatively(|session| {
+                let (_, terms) = session.elaborate_decl(&decl)?;
+                let context = Context::from(session.env());
+                let lines: Vec<String> = terms
+                    .iter()
+                    .map(|(name, term)| format!("{} = {}", name, check(term, &context)))
+                    .collect();
+                Ok(lines.join("\n"))
+            });
         }
+        let (_, term) = self
+            .session
+            .elaborate(&Term::parse_with(code, &self.ops)?)?;
+        Ok(check(&term, &Context::from(self.session.env())))
+    }
+
+    pub fn check(&mut self, code: &str) -> Result<Ty, Error> {
+        Ok(typecheck(
+            &SystemF::parse(code)?,
+            &Context::from(self.session.env()),
+        )?)
     }
 
-    let mut testing = Testing::new();
-
-    testing.define("head", "forall[a] list[a] -> a")?;
-    testing.define("tail", "forall[a] list[a] -> list[a]")?;
-    testing.define("nil", "forall[a] list[a]")?;
-    testing.define("cons", "forall[a] (a, list[a]) -> list[a]")?;
-    testing.define("cons_curry", "forall[a] a -> list[a] -> list[a]")?;
-    testing.define("map", "forall[a, b] (a -> b, list[a]) -> list[b]")?;
-    testing.define("map_curry", "forall[a, b] (a -> b) -> list[a] -> list[b]")?;
-    testing.define("one", "int")?;
-    testing.define("zero", "int")?;
-    testing.define("succ", "int -> int")?;
-    testing.define("plus", "(int, int) -> int")?;
-    testing.define("eq", "forall[a] (a, a) -> bool")?;
-    testing.define("eq_curry", "forall[a] a -> a -> bool")?;
-    testing.define("not", "bool -> bool")?;
-    testing.define("true", "bool")?;
-    testing.define("false", "bool")?;
-    testing.define("pair", "forall[a, b] (a, b) -> pair[a, b]")?;
-    testing.define("pair_curry", "forall[a, b] a -> b -> pair[a, b]")?;
-    testing.define("first", "forall[a, b] pair[a, b] -> a")?;
-    testing.define("second", "forall[a, b] pair[a, b] -> b")?;
-    testing.define("id", "forall[a] a -> a")?;
-    testing.define("const", "forall[a, b] a -> b -> a")?;
-    testing.define("apply", "forall[a, b] (a -> b, a) -> b")?;
-    testing.define("apply_curry", "forall[a, b] (a -> b) -> a -> b")?;
-    testing.define("choose", "forall[a] (a, a) -> a")?;
-    testing.define("choose_curry", "forall[a] a -> a -> a")?;
-
-    let mut testcase = |code: &str, result: &str| -> Result<(), String> {
-        match testing.deduce(code) {
-            Ok(ty) => {
-                let t_signature = Type::from_str(result, &mut testing.gen)?;
-                assert_eq!(ty.to_string(), t_signature.to_string());
+    pub fn eval(&mut self, line: &str) -> Result<Action, String> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("--") {
+            return Ok(Action::Continue);
+        }
+
+        let (command, argument) = match line.find(char::is_whitespace) {
+            Some(i) => (&line[..i], line[i..].trim()),
+            None => (line, ""),
+        };
+        match command {
+            ":quit" | ":q" => return Ok(Action::Quit),
+            ":help" | ":h" => println!("{}", HELP),
+            ":env" => {
+                for (name, ty) in self.session.names() {
+                    println!("{} : {}", name, ty);
+                }
             }
-            Err(error) => {
-                assert_eq!(error, result)
+            ":reset" => self.reset(),
+            ":type" | ":t" => {
+                let ty = self.deduce(argument).map_err(|error| report(&error))?;
+                println!("{} : {}", argument, ty);
             }
+            ":core" | ":c" => {
+                let core = self.core(argument).map_err(|error| report(&error))?;
+                if !core.is_empty() {
+                    println!("{}", core);
+                }
+            }
+            ":systemf" | ":f" => {
+                let elaborated = self.system_f(argument).map_err(|error| report(&error))?;
+                if !elaborated.is_empty() {
+                    println!("{}", elaborated);
+                }
+            }
+            ":check" => {
+                let ty = self.check(argument).map_err(|error| report(&error))?;
+                println!("{} : {}", argument, ty);
+            }
+            ":define" | ":d" => {
+                let (name, ty) = argument
+                    .split_once(':')
+                    .map(|(name, ty)| (name.trim(), ty.trim()))
+                    .filter(|(name, _)| !name.is_empty() && !name.contains(char::is_whitespace))
+                    .ok_or_else(|| String::from("usage: :define <name> : <type>"))?;
+                let ty = self.define(name, ty).map_err(|error| report(&error))?;
+                println!("{} : {}", name, ty);
+            }
+            ":infixl" | ":infixr" | ":infix" | ":prefix" => {
+                let (precedence, op) = argument
+                    .split_once(char::is_whitespace)
+                    .and_then(|(precedence, op)| Some((precedence.parse().ok()?, op.trim())))
+                    .ok_or_else(|| format!("usage: {} <precedence> <operator>", command))?;
+                let declared = match command {
+                    ":infixl" => self.ops.insert_infix(op, precedence, Assoc::Left),
+                    ":infixr" => self.ops.insert_infix(op, precedence, Assoc::Right),
+                    ":infix" => self.ops.insert_infix(op, precedence, Assoc::None),
+                    _ => self.ops.insert_prefix(op, precedence),
+                };
+                if !declared {
+                    return Err(format!("{} is not an operator symbol", op));
+                }
+            }
+            ":load" | ":l" => return self.load(argument),
+            _ if command.starts_with(':') => {
+                return Err(format!("unknown command {}, try :help", command));
+            }
+            _ => match Decl::parse_with(line, &self.ops) {
+                Ok(decl) => {
+                    let declared = self.declare(&decl).map_err(|e| report(&e))?;
+                    if !declared.is_empty() {
+                        println!("{}", declared);
+                    }
+                }
+                Err(decl_error) => match Term::parse_with(line, &self.ops) {
+                    Ok(term) => {
+                        // every error of the term is reported, aligned under the first one
+                        let (ty, diagnostics) = self.session.diagnose(&term);
+                        if !diagnostics.is_empty() {
+                            let reports: Vec<String> = diagnostics
+                                .into_iter()
+                                .map(|error| report(&Error::from(Box::new(error))))
+                                .collect();
+                            return Err(reports.join("\n       "));
+                        }
+                        println!("{}", ty);
+                    }
+                    Err(term_error) => {
+                        let error = if decl_error.position() > term_error.position() {
+                            decl_error
+                        } else {
+                            term_error
+                        };
+                        return Err(report(&Error::from(error)));
+                    }
+                },
+            },
         }
-        Ok(())
-    };
-
-    testcase(r"id", r"forall[a] a -> a")?;
-    testcase(r"one", r"int")?;
-    testcase(r"x", r"Undefined variable 'x'")?;
-    testcase(r"let x = x in x", r"Undefined variable 'x'")?;
-    testcase(r"let x = id in x", r"forall[a] a -> a")?;
-    testcase(r"let x = \y -> y in x", r"forall[a] a -> a")?;
-    testcase(r"\x -> x", r"forall[a] a -> a")?;
-    testcase(r"\x -> x", r"forall[int] int -> int")?;
-    testcase(r"pair", r"forall[a, b] (a, b) -> pair[a, b]")?;
-    testcase(r"pair", r"forall[z, x] (x, z) -> pair[x, z]")?;
-    testcase(r"\x -> let y = \z -> z in y", r"forall[a, b] a -> b -> b")?;
-    testcase(r"let f = \x -> x in let id = \y -> y in eq(f, id)", r"bool")?;
-    testcase(
-        r"let f = \x -> x in let id = \y -> y in eq_curry(f)(id)",
-        r"bool",
-    )?;
-    testcase(r"let f = \x -> x in eq(f, succ)", r"bool")?;
-    testcase(r"let f = \x -> x in eq_curry(f)(succ)", r"bool")?;
-    testcase(r"\x -> x(false)", r"forall[a] (bool -> a) -> a")?;
-    testcase(
-        r"let f = \x -> x in pair(f(one), f(true))",
-        r"pair[int, bool]",
-    )?;
-    testcase(r"let f = id in pair(f(one), f(true))", r"pair[int, bool]")?;
-    testcase(
-        r"\f -> pair(f(one), f(true))",
-        r"Cannot unify int with bool",
-    )?;
-    testcase(
-        r"let f = \x y -> let a = eq(x, y) in eq(x, y) in f",
-        r"forall[a] (a, a) -> bool",
-    )?;
-    testcase(
-        r"let f = \x y -> let a = eq_curry(x)(y) in eq_curry(x)(y) in f",
-        r"forall[a] (a, a) -> bool",
-    )?;
-    testcase(r"id(id)", r"forall[a] a -> a")?;
-    testcase(r"choose(\x y -> x, \x y -> y)", r"forall[a] (a, a) -> a")?;
-    testcase(
-        r"choose_curry(\x y -> x)(\x y -> y)",
-        r"forall[a] (a, a) -> a",
-    )?;
-    testcase(
-        r"let x = id in let y = let z = x(id) in z in y",
-        r"forall[a] a -> a",
-    )?;
-    testcase(r"cons(id, nil)", r"forall[a] list[a -> a]")?;
-    testcase(r"cons_curry(id)(nil)", r"forall[a] list[a -> a]")?;
-    testcase(
-        r"let lst1 = cons(id, nil) in let lst2 = cons(succ, lst1) in lst2",
-        r"list[int -> int]",
-    )?;
-    testcase(
-        r"cons_curry(id)(cons_curry(succ)(cons_curry(id)(nil)))",
-        r"list[int -> int]",
-    )?;
-    testcase(r"plus(one, true)", "Cannot unify int with bool")?;
-    testcase(r"plus(one)", "Incorrect number of arguments. Must be 1")?;
-    testcase(r"\x -> let y = x in y", r"forall[a] a -> a")?;
-    testcase(
-        r"\x -> let y = let z = x(\x -> x) in z in y",
-        r"forall[a, b] ((a -> a) -> b) -> b",
-    )?;
-    testcase(
-        r"\x -> \y -> let x = x(y) in x(y)",
-        r"forall[a, b] (a -> a -> b) -> a -> b",
-    )?;
-    testcase(r"\x -> let y = \z -> x in y", r"forall[a, b] a -> b -> a")?;
-    testcase(
-        r"\x -> \y -> let x = x(y) in \x -> y(x)",
-        r"forall[a, b, c] ((a -> b) -> c) -> (a -> b) -> a -> b",
-    )?;
-    testcase(
-        r"\x -> let y = \z -> z in y(y)",
-        r"forall[a, b] a -> b -> b",
-    )?;
-    testcase(r"one(id)", "Invalid type of function: int")?;
-    testcase(
-        r"let const = \x -> \y -> x in const",
-        r"forall[a, b] a -> b -> a",
-    )?;
-    testcase(
-        r"let apply = \f x -> f(x) in apply",
-        r"forall[a, b] (a -> b, a) -> b",
-    )?;
-    testcase(
-        r"let apply_curry = \f -> \x -> f(x) in apply_curry",
-        r"forall[a, b] (a -> b) -> a -> b",
-    )?;
-    testcase(
-        r"\x -> let y = \z -> x(z) in y",
-        r"forall[a, b] (a -> b) -> a -> b",
-    )?;
-    testcase(
-        r"\f -> let x = \g y -> let _ = g(y) in eq(f, g) in x",
-        r"forall[a, b] (a -> b) -> (a -> b, a) -> bool",
-    )?;
-    testcase(r"\x -> let y = x in y(y)", "Infinite type")?;
-    testcase(r"\x -> x(x)", "Infinite type")?;
-
-    Ok(())
+        Ok(Action::Continue)
+    }
+
+    pub fn load(&mut self, path: &str) -> Result<Action, String> {
+        let source =
+            std::fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+        for (i, line) in source.lines().enumerate() {
+            match self.eval(line) {
+                Ok(Action::Continue) => {}
+                Ok(Action::Quit) => return Ok(Action::Quit),
+                Err(error) => return Err(format!("{}:{}: {}", path, i + 1, error)),
+            }
+        }
+        Ok(Action::Continue)
+    }
+}
+
+fn report(error: &Error) -> String {
+    match error {
+        Error::Parse(error) => error.to_string(),
+        Error::Type(error) => format!("{} at {}", error.node, error.span.start),
+        Error::SystemF(error) => format!("{} at {}", error.node, error.span.start),
+    }
 }
 
 fn main() {
-    match test() {
-        Ok(_) => {}
-        Err(error) => println!("{}", error),
-    };
+    let mut repl = Repl::new();
+    let interactive = std::io::stdin().is_terminal();
+
+    for path in std::env::args().skip(1) {
+        if let Err(error) = repl.load(&path) {
+            eprintln!("error: {}", error);
+        }
+    }
+
+    let stdin = std::io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        if interactive {
+            print!("> ");
+            std::io::stdout().flush().ok();
+        }
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break,
+        };
+        match repl.eval(&line) {
+            Ok(Action::Continue) => {}
+            Ok(Action::Quit) => break,
+            Err(error) => eprintln!("error: {}", error),
+        }
+    }
 }
@@ -0,0 +1,12 @@
+/*
+ * Copyright (c) 2021, Andrei Yaskovets
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+mod term;
+pub use term::*;
+
+mod check;
+pub use check::*;
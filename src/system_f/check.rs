@@ -0,0 +1,888 @@
+/*
+ * Copyright (c) 2021, Andrei Yaskovets
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+use std::collections::{HashMap, HashSet};
+
+use tokenstream::{Span, Spanned};
+
+use crate::{Literal, Pattern, SystemF, SystemFError, Ty};
+
+type Row = (Vec<(String, Spanned<Ty>)>, Option<Box<Spanned<Ty>>>);
+
+// the types of the names in scope, the type constructors and the methods of the classes,
+// whose dictionaries are the records of the methods at the type of the instance
+#[derive(Clone, Debug)]
+pub struct Context {
+    names: HashMap<String, Ty>,
+    constructors: HashMap<String, Ty>,
+    types: HashSet<String>,
+    classes: HashMap<String, (String, Vec<(String, Ty)>)>,
+}
+
+impl Context {
+    pub fn new() -> Self {
+        Context {
+            names: HashMap::new(),
+            constructors: HashMap::new(),
+            types: ["int", "float", "bool", "string", "ref"]
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            classes: HashMap::new(),
+        }
+    }
+
+    pub fn insert_type(&mut self, name: &str) -> bool {
+        self.types.insert(name.to_owned())
+    }
+
+    pub fn insert(&mut self, name: &str, ty: Ty) -> Option<Ty> {
+        self.names.insert(name.to_owned(), ty)
+    }
+
+    // a bare name in a pattern is the constructor rather than a variable, the patterns
+    // resolve it to its own type even when a name shadows it
+    pub fn insert_constructor(&mut self, name: &str, ty: Ty) -> Option<Ty> {
+        self.constructors.insert(name.to_owned(), ty.clone());
+        self.insert(name, ty)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Ty> {
+        self.names.remove(name)
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<&Ty> {
+        self.names.get(name)
+    }
+
+    // the method types are over the parameter, which the type of the dictionary substitutes
+    pub fn insert_class(&mut self, name: &str, param: &str, methods: Vec<(String, Ty)>) {
+        self.classes
+            .insert(name.to_owned(), (param.to_owned(), methods));
+    }
+
+    // the types are the same up to the names of the quantified variables, the order of the labels
+    // of a row and the dictionaries written as the records of their methods
+    pub fn equivalent(&self, t1: &Ty, t2: &Ty) -> bool {
+        self.normalize(t1, 0) == self.normalize(t2, 0)
+    }
+
+    // the dictionary of a class at the head of the type is expanded to its record
+    fn expand(&self, ty: &Ty) -> Ty {
+        if let Ty::App(t, params) = ty {
+            if let (Ty::Const(class), [t_param]) = (&t.node, &params[..]) {
+                if let Some((param, methods)) = self.classes.get(class) {
+                    let subst = HashMap::from([(param.clone(), t_param.node.clone())]);
+                    let fields = methods
+                        .iter()
+                        .map(|(method, t)| (method.clone(), Spanned::from(substitute(t, &subst))))
+                        .collect();
+                    return Ty::Record(fields, None);
+                }
+            }
+        }
+        ty.clone()
+    }
+
+    // the quantified variables are named by their depth and the labels of the rows are sorted
+    fn normalize(&self, ty: &Ty, depth: usize) -> Ty {
+        let normalize = |ty: &Spanned<Ty>| Spanned::from(self.normalize(&ty.node, depth));
+        match self.expand(ty) {
+            Ty::Const(name) => Ty::Const(name),
+            Ty::App(t, params) => Ty::App(
+                Box::new(normalize(&t)),
+                params.iter().map(normalize).collect(),
+            ),
+            Ty::Arrow(init, tail) => Ty::Arrow(
+                init.iter().map(normalize).collect(),
+                Box::new(normalize(&tail)),
+            ),
+            Ty::Tuple(items) => Ty::Tuple(items.iter().map(normalize).collect()),
+            Ty::Record(fields, rest) => {
+                let (fields, rest) = self.normalize_row(&fields, &rest, depth);
+                Ty::Record(fields, rest)
+            }
+            Ty::Variant(fields, rest) => {
+                let (fields, rest) = self.normalize_row(&fields, &rest, depth);
+                Ty::Variant(fields, rest)
+            }
+            Ty::Qualified(context, t) => Ty::Qualified(
+                context
+                    .iter()
+                    .map(|(class, t)| (class.clone(), normalize(t)))
+                    .collect(),
+                Box::new(normalize(&t)),
+            ),
+            Ty::Forall(params, t) => {
+                let names: Vec<String> = (depth..depth + params.len())
+                    .map(|i| format!("#{}", i))
+                    .collect();
+                let subst = params
+                    .iter()
+                    .cloned()
+                    .zip(names.iter().map(|name| Ty::Const(name.clone())))
+                    .collect();
+                let t = substitute(&t.node, &subst);
+                Ty::Forall(
+                    names,
+                    Box::new(Spanned::from(self.normalize(&t, depth + params.len()))),
+                )
+            }
+        }
+    }
+
+    // the sort is stable so the fields of a duplicate label keep their order
+    fn normalize_row(
+        &self,
+        fields: &[(String, Spanned<Ty>)],
+        rest: &Option<Box<Spanned<Ty>>>,
+        depth: usize,
+    ) -> Row {
+        let mut fields: Vec<(String, Spanned<Ty>)> = fields
+            .iter()
+            .map(|(label, field)| {
+                (
+                    label.clone(),
+                    Spanned::from(self.normalize(&field.node, depth)),
+                )
+            })
+            .collect();
+        fields.sort_by(|(label1, _), (label2, _)| label1.cmp(label2));
+        let rest = rest
+            .as_ref()
+            .map(|rest| Box::new(Spanned::from(self.normalize(&rest.node, depth))));
+        (fields, rest)
+    }
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// the names the type mentions outside of its quantifiers
+fn free(ty: &Ty, names: &mut HashSet<String>) {
+    match ty {
+        Ty::Const(name) => {
+            names.insert(name.clone());
+        }
+        Ty::App(t, params) => {
+            free(&t.node, names);
+            params.iter().for_each(|param| free(&param.node, names));
+        }
+        Ty::Arrow(init, tail) => {
+            init.iter().for_each(|param| free(&param.node, names));
+            free(&tail.node, names);
+        }
+        Ty::Tuple(items) => items.iter().for_each(|item| free(&item.node, names)),
+        Ty::Record(fields, rest) | Ty::Variant(fields, rest) => {
+            fields
+                .iter()
+                .for_each(|(_, field)| free(&field.node, names));
+            rest.iter().for_each(|rest| free(&rest.node, names));
+        }
+        Ty::Qualified(context, t) => {
+            context.iter().for_each(|(_, t)| free(&t.node, names));
+            free(&t.node, names);
+        }
+        Ty::Forall(params, t) => {
+            let mut inner = HashSet::new();
+            free(&t.node, &mut inner);
+            names.extend(inner.into_iter().filter(|name| !params.contains(name)));
+        }
+    }
+}
+
+// the name with the smallest suffix that is not taken
+fn fresh(name: &str, taken: &HashSet<String>) -> String {
+    (1..)
+        .map(|i| format!("{}{}", name, i))
+        .find(|fresh| !taken.contains(fresh))
+        .expect("Some suffix is not taken")
+}
+
+// the type with the variables replaced, where a row variable replaced by a row
+// splices its labels into the enclosing one
+fn substitute(ty: &Ty, subst: &HashMap<String, Ty>) -> Ty {
+    let substitute = |ty: &Spanned<Ty>| Spanned::new(substitute(&ty.node, subst), ty.span);
+    match ty {
+        Ty::Const(name) => subst.get(name).cloned().unwrap_or_else(|| ty.clone()),
+        Ty::App(t, params) => Ty::App(
+            Box::new(substitute(t)),
+            params.iter().map(substitute).collect(),
+        ),
+        Ty::Arrow(init, tail) => Ty::Arrow(
+            init.iter().map(substitute).collect(),
+            Box::new(substitute(tail)),
+        ),
+        Ty::Tuple(items) => Ty::Tuple(items.iter().map(substitute).collect()),
+        Ty::Record(fields, rest) | Ty::Variant(fields, rest) => {
+            let mut fields: Vec<(String, Spanned<Ty>)> = fields
+                .iter()
+                .map(|(label, field)| (label.clone(), substitute(field)))
+                .collect();
+            let rest = match rest.as_ref().map(|rest| substitute(rest).node) {
+                Some(Ty::Record(more, rest) | Ty::Variant(more, rest)) => {
+                    fields.extend(more);
+                    rest
+                }
+                rest => rest.map(|rest| Box::new(Spanned::from(rest))),
+            };
+            match ty {
+                Ty::Record(_, _) => Ty::Record(fields, rest),
+                _ => Ty::Variant(fields, rest),
+            }
+        }
+        Ty::Qualified(context, t) => Ty::Qualified(
+            context
+                .iter()
+                .map(|(class, t)| (class.clone(), substitute(t)))
+                .collect(),
+            Box::new(substitute(t)),
+        ),
+        // a quantified variable that a substituted type mentions is renamed, so it does not capture it
+        Ty::Forall(params, t) => {
+            let mut subst = subst.clone();
+            for param in params {
+                subst.remove(param);
+            }
+            let mut taken = HashSet::new();
+            free(&t.node, &mut taken);
+            let mut captured = HashSet::new();
+            for (_, ty) in subst.iter().filter(|(name, _)| taken.contains(*name)) {
+                free(ty, &mut captured);
+            }
+            taken.extend(captured.iter().cloned());
+            taken.extend(params.iter().cloned());
+
+            let mut renamed = Vec::new();
+            for param in params {
+                match captured.contains(param) {
+                    true => {
+                        let name = fresh(param, &taken);
+                        taken.insert(name.clone());
+                        subst.insert(param.clone(), Ty::Const(name.clone()));
+                        renamed.push(name);
+                    }
+                    false => renamed.push(param.clone()),
+                }
+            }
+            let t = Spanned::new(self::substitute(&t.node, &subst), t.span);
+            Ty::Forall(renamed, Box::new(t))
+        }
+    }
+}
+
+// the weak variables of the inference stay free in its terms until they are resolved
+fn is_weak(name: &str) -> bool {
+    name.strip_prefix("_weak")
+        .is_some_and(|id| id.parse::<usize>().is_ok())
+}
+
+struct Check {
+    context: Context,
+    // the variables of the enclosing type abstractions
+    scope: Vec<String>,
+}
+
+impl Check {
+    pub fn new(context: Context) -> Self {
+        Check {
+            context,
+            scope: Vec::new(),
+        }
+    }
+
+    // every name in the type is a type constructor, a class or a variable bound around it
+    fn well_formed(
+        &self,
+        ty: &Spanned<Ty>,
+        bound: &[String],
+    ) -> Result<(), Box<Spanned<SystemFError>>> {
+        let well_formed = |ty| self.well_formed(ty, bound);
+        match &ty.node {
+            Ty::Const(name)
+                if bound.contains(name)
+                    || self.scope.contains(name)
+                    || self.context.types.contains(name)
+                    || self.context.classes.contains_key(name)
+                    || is_weak(name) =>
+            {
+                Ok(())
+            }
+            Ty::Const(name) => Err(Box::new(Spanned::new(
+                SystemFError::UndefinedType(name.clone()),
+                ty.span,
+            ))),
+            Ty::App(t, params) => {
+                well_formed(t)?;
+                params.iter().try_for_each(well_formed)
+            }
+            Ty::Arrow(init, tail) => {
+                init.iter().try_for_each(well_formed)?;
+                well_formed(tail)
+            }
+            Ty::Tuple(items) => items.iter().try_for_each(well_formed),
+            Ty::Record(fields, rest) | Ty::Variant(fields, rest) => {
+                fields
+                    .iter()
+                    .try_for_each(|(_, field)| well_formed(field))?;
+                rest.iter().try_for_each(|rest| well_formed(rest))
+            }
+            Ty::Qualified(context, t) => {
+                context.iter().try_for_each(|(_, t)| well_formed(t))?;
+                well_formed(t)
+            }
+            Ty::Forall(params, t) => {
+                let bound: Vec<String> = bound.iter().chain(params).cloned().collect();
+                self.well_formed(t, &bound)
+            }
+        }
+    }
+
+    fn expect(
+        &self,
+        expected: &Ty,
+        found: &Ty,
+        span: Span,
+    ) -> Result<(), Box<Spanned<SystemFError>>> {
+        if self.context.equivalent(expected, found) {
+            Ok(())
+        } else {
+            Err(Box::new(Spanned::new(
+                SystemFError::Mismatch {
+                    expected: expected.clone(),
+                    found: found.clone(),
+                },
+                span,
+            )))
+        }
+    }
+
+    // the names are bound while the closure runs, shadowing the outer ones
+    fn scoped<T>(
+        &mut self,
+        bound: &[(String, Ty)],
+        f: impl FnOnce(&mut Self) -> Result<T, Box<Spanned<SystemFError>>>,
+    ) -> Result<T, Box<Spanned<SystemFError>>> {
+        let shadowing: Vec<(&String, Option<Ty>)> = bound
+            .iter()
+            .map(|(name, ty)| (name, self.context.insert(name, ty.clone())))
+            .collect();
+        let result = f(self);
+        for (name, t_old) in shadowing.into_iter().rev() {
+            self.context.remove(name);
+            if let Some(t_old) = t_old {
+                self.context.insert(name, t_old);
+            }
+        }
+        result
+    }
+
+    // binds the quantified variables of the constructor type so that it becomes the matched one
+    fn instance(
+        &self,
+        t: &Ty,
+        ty: &Ty,
+        params: &[String],
+        subst: &mut HashMap<String, Ty>,
+    ) -> bool {
+        match (t, ty) {
+            (Ty::Const(name), ty) if params.contains(name) => match subst.get(name) {
+                Some(t) => self.context.equivalent(t, ty),
+                None => {
+                    subst.insert(name.clone(), ty.clone());
+                    true
+                }
+            },
+            (Ty::App(t1, params1), Ty::App(t2, params2)) => {
+                params1.len() == params2.len()
+                    && self.instance(&t1.node, &t2.node, params, subst)
+                    && params1.iter().zip(params2).all(|(param1, param2)| {
+                        self.instance(&param1.node, &param2.node, params, subst)
+                    })
+            }
+            (t, ty) => self.context.equivalent(t, ty),
+        }
+    }
+
+    // the variables of the pattern are collected into bound with the parts of the type they match
+    fn pattern(
+        &self,
+        pattern: &Spanned<Pattern>,
+        ty: &Ty,
+        bound: &mut Vec<(String, Ty)>,
+    ) -> Result<(), Box<Spanned<SystemFError>>> {
+        let invalid = || {
+            Box::new(Spanned::new(
+                SystemFError::InvalidPattern {
+                    pattern: pattern.node.clone(),
+                    ty: ty.clone(),
+                },
+                pattern.span,
+            ))
+        };
+        match &pattern.node {
+            Pattern::Wildcard => Ok(()),
+            Pattern::Var(name) if self.context.constructors.contains_key(name) => {
+                let constructor = Pattern::Constructor(name.clone(), Vec::new());
+                self.pattern(&Spanned::new(constructor, pattern.span), ty, bound)
+            }
+            Pattern::Var(name) => {
+                bound.push((name.clone(), ty.clone()));
+                Ok(())
+            }
+            Pattern::Lit(literal) => match self.context.equivalent(&literal_ty(literal), ty) {
+                true => Ok(()),
+                false => Err(invalid()),
+            },
+            Pattern::Tuple(items) => match self.context.expand(ty) {
+                Ty::Tuple(t_items) if t_items.len() == items.len() => items
+                    .iter()
+                    .zip(t_items)
+                    .try_for_each(|(item, t_item)| self.pattern(item, &t_item.node, bound)),
+                _ => Err(invalid()),
+            },
+            Pattern::Variant(label, payload) => {
+                let Ty::Variant(fields, _) = ty else {
+                    return Err(invalid());
+                };
+                let Some((_, field)) = fields.iter().find(|(name, _)| name == label) else {
+                    return Err(invalid());
+                };
+                match payload {
+                    Some(payload) => self.pattern(payload, &field.node, bound),
+                    None => Ok(()),
+                }
+            }
+            Pattern::Constructor(name, args) => {
+                let Some(t_constructor) = self.context.constructors.get(name) else {
+                    return Err(Box::new(Spanned::new(
+                        SystemFError::UndefinedVariable(name.clone()),
+                        pattern.span,
+                    )));
+                };
+                let (params, t) = match t_constructor {
+                    Ty::Forall(params, t) => (&params[..], &t.node),
+                    t => (&[][..], t),
+                };
+                let (init, t_result) = match t {
+                    Ty::Arrow(init, tail) => (&init[..], &tail.node),
+                    t => (&[][..], t),
+                };
+                if init.len() != args.len() {
+                    return Err(Box::new(Spanned::new(
+                        SystemFError::ArityMismatch {
+                            expected: init.len(),
+                            found: args.len(),
+                        },
+                        pattern.span,
+                    )));
+                }
+
+                let mut subst = HashMap::new();
+                if !self.instance(t_result, ty, params, &mut subst) {
+                    return Err(invalid());
+                }
+                for (arg, t_arg) in args.iter().zip(init) {
+                    self.pattern(arg, &substitute(&t_arg.node, &subst), bound)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub fn check(&mut self, term: &Spanned<SystemF>) -> Result<Ty, Box<Spanned<SystemFError>>> {
+        let at = |error| Box::new(Spanned::new(error, term.span));
+        match &term.node {
+            SystemF::Lit(literal) => Ok(literal_ty(literal)),
+            SystemF::Var(name) => match self.context.lookup(name) {
+                Some(ty) => Ok(ty.clone()),
+                None => Err(at(SystemFError::UndefinedVariable(name.clone()))),
+            },
+            SystemF::Abs(args, body) => {
+                for (_, ty) in args {
+                    self.well_formed(ty, &[])?;
+                }
+                let bound: Vec<(String, Ty)> = args
+                    .iter()
+                    .map(|(arg, ty)| (arg.clone(), ty.node.clone()))
+                    .collect();
+                let t_body = self.scoped(&bound, |check| check.check(body))?;
+                Ok(Ty::Arrow(
+                    args.iter().map(|(_, ty)| ty.clone()).collect(),
+                    Box::new(Spanned::from(t_body)),
+                ))
+            }
+            SystemF::App(f, args) => {
+                let t_f = self.check(f)?;
+                let Ty::Arrow(init, tail) = t_f else {
+                    return Err(at(SystemFError::NotAFunction(t_f)));
+                };
+                if init.len() != args.len() {
+                    return Err(at(SystemFError::ArityMismatch {
+                        expected: init.len(),
+                        found: args.len(),
+                    }));
+                }
+                for (arg, t_param) in args.iter().zip(init.iter()) {
+                    let t_arg = self.check(arg)?;
+                    self.expect(&t_param.node, &t_arg, arg.span)?;
+                }
+                Ok(tail.node)
+            }
+            // a variable of the same name in the types in scope is renamed while the body is checked
+            // and renamed back in the result, where the quantifier no longer captures it,
+            // the primed name cannot be written in the body so it does not clash with its types
+            SystemF::TyAbs(params, body) => {
+                let mut taken = HashSet::new();
+                for ty in self.context.names.values() {
+                    free(ty, &mut taken);
+                }
+                let mut renaming = HashMap::new();
+                let mut inverse = HashMap::new();
+                for param in params {
+                    if !taken.contains(param) {
+                        continue;
+                    }
+                    let mut name = format!("{}'", param);
+                    while taken.contains(&name) {
+                        name.push('\'');
+                    }
+                    taken.insert(name.clone());
+                    renaming.insert(param.clone(), Ty::Const(name.clone()));
+                    inverse.insert(name, Ty::Const(param.clone()));
+                }
+                let depth = self.scope.len();
+                self.scope.extend(params.iter().cloned());
+                if renaming.is_empty() {
+                    let t_body = self.check(body);
+                    self.scope.truncate(depth);
+                    return Ok(Ty::Forall(params.clone(), Box::new(Spanned::from(t_body?))));
+                }
+
+                let outer = self.context.clone();
+                for ty in self.context.names.values_mut() {
+                    *ty = substitute(ty, &renaming);
+                }
+                let t_body = self.check(body);
+                self.context = outer;
+                self.scope.truncate(depth);
+                let t = Ty::Forall(params.clone(), Box::new(Spanned::from(t_body?)));
+                Ok(substitute(&t, &inverse))
+            }
+            SystemF::TyApp(f, args) => {
+                let t_f = self.check(f)?;
+                let Ty::Forall(params, t) = t_f else {
+                    return Err(at(SystemFError::NotPolymorphic(t_f)));
+                };
+                if params.len() != args.len() {
+                    return Err(at(SystemFError::TypeArityMismatch {
+                        expected: params.len(),
+                        found: args.len(),
+                    }));
+                }
+                for arg in args {
+                    self.well_formed(arg, &[])?;
+                }
+                let subst = params
+                    .into_iter()
+                    .zip(args.iter().map(|arg| arg.node.clone()))
+                    .collect();
+                Ok(substitute(&t.node, &subst))
+            }
+            SystemF::Let(name, assign, body) => {
+                let t_assign = self.check(assign)?;
+                self.scoped(&[(name.clone(), t_assign)], |check| check.check(body))
+            }
+            SystemF::LetRec(bindings, body) => {
+                for (_, ty, _) in bindings {
+                    self.well_formed(ty, &[])?;
+                }
+                let bound: Vec<(String, Ty)> = bindings
+                    .iter()
+                    .map(|(name, ty, _)| (name.clone(), ty.node.clone()))
+                    .collect();
+                self.scoped(&bound, |check| {
+                    for (_, ty, assign) in bindings {
+                        let t_assign = check.check(assign)?;
+                        check.expect(&ty.node, &t_assign, assign.span)?;
+                    }
+                    check.check(body)
+                })
+            }
+            SystemF::If(cond, then, otherwise) => {
+                let t_cond = self.check(cond)?;
+                self.expect(&Ty::Const(String::from("bool")), &t_cond, cond.span)?;
+                let t_then = self.check(then)?;
+                let t_otherwise = self.check(otherwise)?;
+                self.expect(&t_then, &t_otherwise, otherwise.span)?;
+                Ok(t_then)
+            }
+            SystemF::Tuple(items) => {
+                let mut t_items = Vec::new();
+                for item in items {
+                    t_items.push(Spanned::from(self.check(item)?));
+                }
+                Ok(Ty::Tuple(t_items))
+            }
+            SystemF::Record(fields, rest) => {
+                let mut t_fields = Vec::new();
+                for (label, field) in fields {
+                    t_fields.push((label.clone(), Spanned::from(self.check(field)?)));
+                }
+                let t_rest = match rest {
+                    Some(rest) => match self.check(rest).map(|t| self.context.expand(&t))? {
+                        Ty::Record(more, t_rest) => {
+                            t_fields.extend(more);
+                            t_rest
+                        }
+                        t_rest => {
+                            return Err(Box::new(Spanned::new(
+                                SystemFError::NotARecord(t_rest),
+                                rest.span,
+                            )))
+                        }
+                    },
+                    None => None,
+                };
+                Ok(Ty::Record(t_fields, t_rest))
+            }
+            SystemF::Select(record, label) | SystemF::Restrict(record, label) => {
+                let t_record = self.check(record)?;
+                let Ty::Record(mut fields, rest) = self.context.expand(&t_record) else {
+                    return Err(Box::new(Spanned::new(
+                        SystemFError::NotARecord(t_record),
+                        record.span,
+                    )));
+                };
+                let Some(i) = fields.iter().position(|(name, _)| name == label) else {
+                    return Err(at(SystemFError::MissingField(label.clone())));
+                };
+                let (_, field) = fields.remove(i);
+                match &term.node {
+                    SystemF::Select(_, _) => Ok(field.node),
+                    _ => Ok(Ty::Record(fields, rest)),
+                }
+            }
+            SystemF::Variant(label, payload, ty) => {
+                self.well_formed(ty, &[])?;
+                let t_payload = match payload {
+                    Some(payload) => self.check(payload)?,
+                    None => Ty::Record(Vec::new(), None),
+                };
+                let field = match &ty.node {
+                    Ty::Variant(fields, _) => fields.iter().find(|(name, _)| name == label),
+                    _ => None,
+                };
+                match field {
+                    Some((_, field)) => self.expect(&field.node, &t_payload, term.span)?,
+                    None => {
+                        return Err(at(SystemFError::Mismatch {
+                            expected: Ty::Variant(
+                                vec![(label.clone(), Spanned::from(t_payload))],
+                                None,
+                            ),
+                            found: ty.node.clone(),
+                        }))
+                    }
+                }
+                Ok(ty.node.clone())
+            }
+            SystemF::Match(scrutinee, arms) => {
+                let t_scrutinee = self.check(scrutinee)?;
+                let mut t_match = None;
+                for (pattern, body) in arms {
+                    let mut bound = Vec::new();
+                    self.pattern(pattern, &t_scrutinee, &mut bound)?;
+                    let t_body = self.scoped(&bound, |check| check.check(body))?;
+                    match &t_match {
+                        Some(t_match) => self.expect(t_match, &t_body, body.span)?,
+                        None => t_match = Some(t_body),
+                    }
+                }
+                Ok(t_match.unwrap_or(Ty::Variant(Vec::new(), None)))
+            }
+        }
+    }
+}
+
+fn literal_ty(literal: &Literal) -> Ty {
+    Ty::Const(String::from(match literal {
+        Literal::Int(_) => "int",
+        Literal::Float(_) => "float",
+        Literal::Bool(_) => "bool",
+        Literal::Str(_) => "string",
+    }))
+}
+
+// the type of the term, which has to carry the types of all its binders
+pub fn typecheck(
+    term: &Spanned<SystemF>,
+    context: &Context,
+) -> Result<Ty, Box<Spanned<SystemFError>>> {
+    Check::new(context.clone()).check(term)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{typecheck, Context};
+
+    use std::str::FromStr;
+
+    use tokenstream::Spanned;
+
+    use crate::{Literal, SystemF, SystemFError, Ty};
+
+    fn ty(s: &str) -> Ty {
+        Ty::from_str(s).unwrap()
+    }
+
+    fn boxed(term: SystemF) -> Box<Spanned<SystemF>> {
+        Box::new(Spanned::from(term))
+    }
+
+    #[test]
+    fn equivalence() {
+        let mut context = Context::new();
+        context.insert_class("Eq", "a", vec![(String::from("eq"), ty("(a, a) -> bool"))]);
+
+        let equivalent = |t1: &str, t2: &str| context.equivalent(&ty(t1), &ty(t2));
+        assert!(equivalent("forall[a, b] a -> b", "forall[x, y] x -> y"));
+        assert!(!equivalent("forall[a, b] a -> b", "forall[x, y] y -> x"));
+        assert!(equivalent(
+            "{x : int, y : bool | r}",
+            "{y : bool, x : int | r}"
+        ));
+        assert!(!equivalent("{x : int | r}", "{x : int}"));
+        assert!(equivalent("Eq[int]", "{eq : (int, int) -> bool}"));
+        assert!(!equivalent("Eq[int]", "{eq : (bool, bool) -> bool}"));
+        assert!(equivalent(
+            "forall[a] (forall[b] b -> a) -> a",
+            "forall[x] (forall[y] y -> x) -> x"
+        ));
+        assert!(!equivalent(
+            "forall[a] (forall[b] b -> a) -> a",
+            "forall[x] (forall[y] x -> y) -> x"
+        ));
+    }
+
+    #[test]
+    fn check() {
+        let mut context = Context::new();
+        context.insert("id", ty("forall[a] a -> a"));
+
+        let id = |args: Vec<&str>| {
+            SystemF::TyApp(
+                boxed(SystemF::Var(String::from("id"))),
+                args.into_iter().map(|arg| Spanned::from(ty(arg))).collect(),
+            )
+        };
+        let apply = |f: SystemF, arg: Literal| {
+            Spanned::from(SystemF::App(
+                boxed(f),
+                vec![Spanned::from(SystemF::Lit(arg))],
+            ))
+        };
+        let check = |term: &Spanned<SystemF>| typecheck(term, &context).map_err(|error| error.node);
+
+        assert_eq!(
+            check(&apply(id(vec!["int"]), Literal::Int(1))),
+            Ok(ty("int"))
+        );
+        assert_eq!(
+            check(&apply(id(vec!["int"]), Literal::Bool(true))),
+            Err(SystemFError::Mismatch {
+                expected: ty("int"),
+                found: ty("bool")
+            })
+        );
+        assert_eq!(
+            check(&Spanned::from(id(vec!["int", "bool"]))),
+            Err(SystemFError::TypeArityMismatch {
+                expected: 1,
+                found: 2
+            })
+        );
+        assert_eq!(
+            check(&Spanned::from(SystemF::TyApp(
+                boxed(id(vec!["int"])),
+                vec![Spanned::from(ty("int"))]
+            ))),
+            Err(SystemFError::NotPolymorphic(ty("int -> int")))
+        );
+        assert_eq!(
+            check(&Spanned::from(SystemF::TyAbs(
+                vec![String::from("b")],
+                boxed(SystemF::Abs(
+                    vec![(String::from("x"), Spanned::from(ty("b")))],
+                    boxed(id(vec!["b"]))
+                ))
+            ))),
+            Ok(ty("forall[b] b -> b -> b"))
+        );
+    }
+
+    #[test]
+    fn polymorphism() {
+        let mut context = Context::new();
+        context.insert("pair", ty("forall[a, b] (a, b) -> (a, b)"));
+
+        let check = |term: &str| {
+            typecheck(&SystemF::parse(term).unwrap(), &context)
+                .map(|ty| ty.to_string())
+                .map_err(|error| error.node.to_string())
+        };
+
+        // rank 2 and impredicative instantiation
+        assert_eq!(
+            check(r"\f : (forall[a] a -> a) -> pair[int, bool](f[int](1), f[bool](true))"),
+            Ok(String::from("(forall[a] a -> a) -> (int, bool)"))
+        );
+        assert_eq!(
+            check(r"let id = Λa. \x : a -> x in id[forall[a] a -> a](id)"),
+            Ok(String::from("forall[a] a -> a"))
+        );
+        assert_eq!(
+            check(r"(\f : (forall[a] a -> a) -> f[int](1))(\x : int -> x)"),
+            Err(String::from(
+                "Expected type forall[a] a -> a, found int -> int"
+            ))
+        );
+
+        // the substitution renames the quantifier that would capture the argument
+        assert_eq!(
+            check(r"Λb. (Λa. Λb. \(x : a, y : b) -> x)[b]"),
+            Ok(String::from("forall[b] forall[b1] (b, b1) -> b"))
+        );
+        // the type names are bound by the context or an enclosing abstraction
+        assert_eq!(
+            check(r"\x : foo -> x"),
+            Err(String::from("Undefined type 'foo'"))
+        );
+        assert_eq!(
+            check(r"(Λa. \x : a -> x)[list[int]]"),
+            Err(String::from("Undefined type 'list'"))
+        );
+        assert_eq!(
+            check(r"(Λa. \x : a -> x)[int, bool]"),
+            Err(String::from(
+                "Incorrect number of type arguments: expected 1, found 2"
+            ))
+        );
+        // the inner quantifier does not capture the outer variable of the same name
+        assert_eq!(
+            check(r"Λa. \x : a -> Λa. \y : a -> x"),
+            Ok(String::from("forall[a] a -> forall[a1] a1 -> a"))
+        );
+        assert_eq!(
+            check(r"Λa. \x : a -> Λa. \y : a -> pair[a, a](x, y)"),
+            Err(String::from("Expected type a, found a'"))
+        );
+    }
+}
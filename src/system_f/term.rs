@@ -0,0 +1,523 @@
+/*
+ * Copyright (c) 2021, Andrei Yaskovets
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+use tokenstream::{bind, fmap, spanned, Parser, Spanned};
+
+use crate::{
+    braces, brackets, comma_list, comma_list1, eof, forall, ident, is_operator, lazy, literal,
+    many_space, name, operator, parens, parse, pattern, simple_ty, spaced, token, Literal,
+    ParseError, Pattern, Token, Ty,
+};
+
+// the explicitly typed terms, where every binder carries its type and every polymorphic
+// value is abstracted over its type variables and applied to types where it is used
+#[derive(PartialEq, Clone, Debug)]
+pub enum SystemF {
+    Lit(Literal),
+    Var(String),
+    Abs(Vec<(String, Spanned<Ty>)>, Box<Spanned<SystemF>>),
+    App(Box<Spanned<SystemF>>, Vec<Spanned<SystemF>>),
+    TyAbs(Vec<String>, Box<Spanned<SystemF>>),
+    TyApp(Box<Spanned<SystemF>>, Vec<Spanned<Ty>>),
+    Let(String, Box<Spanned<SystemF>>, Box<Spanned<SystemF>>),
+    LetRec(
+        Vec<(String, Spanned<Ty>, Spanned<SystemF>)>,
+        Box<Spanned<SystemF>>,
+    ),
+    If(
+        Box<Spanned<SystemF>>,
+        Box<Spanned<SystemF>>,
+        Box<Spanned<SystemF>>,
+    ),
+    Tuple(Vec<Spanned<SystemF>>),
+    Record(
+        Vec<(String, Spanned<SystemF>)>,
+        Option<Box<Spanned<SystemF>>>,
+    ),
+    Select(Box<Spanned<SystemF>>, String),
+    Restrict(Box<Spanned<SystemF>>, String),
+    // the injection carries the whole variant type, whose other labels the payload does not tell
+    Variant(String, Option<Box<Spanned<SystemF>>>, Spanned<Ty>),
+    Match(
+        Box<Spanned<SystemF>>,
+        Vec<(Spanned<Pattern>, Spanned<SystemF>)>,
+    ),
+}
+
+impl SystemF {
+    pub fn parse(s: &str) -> Result<Spanned<SystemF>, ParseError> {
+        parse(system_f() << eof(), s)
+    }
+}
+
+impl std::str::FromStr for SystemF {
+    type Err = ParseError;
+    fn from_str(s: &str) -> std::result::Result<Self, <Self as std::str::FromStr>::Err> {
+        SystemF::parse(s).map(|term| term.node)
+    }
+}
+
+impl std::fmt::Display for SystemF {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        // a head that extends to the right is parenthesized so the application or selection
+        // stays on it
+        fn head(fmt: &mut std::fmt::Formatter<'_>, f: &SystemF) -> Result<(), std::fmt::Error> {
+            match f {
+                SystemF::Let(_, _, _)
+                | SystemF::LetRec(_, _)
+                | SystemF::Abs(_, _)
+                | SystemF::TyAbs(_, _)
+                | SystemF::If(_, _, _)
+                | SystemF::Match(_, _) => write!(fmt, "({})", f),
+                _ => write!(fmt, "{}", f),
+            }
+        }
+
+        match self {
+            SystemF::Lit(literal) => {
+                write!(fmt, "{}", literal)
+            }
+            SystemF::Var(name) if is_operator(name) => {
+                write!(fmt, "({})", name)
+            }
+            SystemF::Var(name) => {
+                write!(fmt, "{}", name)
+            }
+            SystemF::Abs(args, body) => {
+                write!(fmt, "\\(")?;
+                for (i, (arg, ty)) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, ", ")?;
+                    }
+                    write!(fmt, "{} : {}", arg, ty)?;
+                }
+                write!(fmt, ") -> {}", body)
+            }
+            SystemF::App(f, args) => {
+                match (&f.node, &args[..]) {
+                    (SystemF::Var(op), [arg]) if is_operator(op) => {
+                        return write!(fmt, "({} {})", op, arg);
+                    }
+                    (SystemF::Var(op), [lhs, rhs]) if is_operator(op) => {
+                        return write!(fmt, "({} {} {})", lhs, op, rhs);
+                    }
+                    _ => {}
+                }
+                head(fmt, &f.node)?;
+                write!(fmt, "(")?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, ", ")?;
+                    }
+                    write!(fmt, "{}", arg)?;
+                }
+                write!(fmt, ")")
+            }
+            SystemF::TyAbs(params, body) => {
+                write!(fmt, "Λ{}. {}", params.join(" "), body)
+            }
+            SystemF::TyApp(f, args) => {
+                head(fmt, &f.node)?;
+                write!(fmt, "[")?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, ", ")?;
+                    }
+                    write!(fmt, "{}", arg)?;
+                }
+                write!(fmt, "]")
+            }
+            SystemF::Let(name, assign, body) => {
+                write!(fmt, "let {} = {} in {}", name, assign, body)
+            }
+            SystemF::LetRec(bindings, body) => {
+                write!(fmt, "let rec ")?;
+                for (i, (name, ty, assign)) in bindings.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, " and ")?;
+                    }
+                    write!(fmt, "{} : {} = {}", name, ty, assign)?;
+                }
+                write!(fmt, " in {}", body)
+            }
+            SystemF::If(cond, then, otherwise) => {
+                write!(fmt, "if {} then {} else {}", cond, then, otherwise)
+            }
+            SystemF::Tuple(items) => {
+                write!(fmt, "({}", items[0])?;
+                for item in items.iter().skip(1) {
+                    write!(fmt, ", {}", item)?;
+                }
+                write!(fmt, ")")
+            }
+            SystemF::Record(fields, rest) => {
+                write!(fmt, "{{")?;
+                for (i, (label, field)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, ", ")?;
+                    }
+                    write!(fmt, "{} = {}", label, field)?;
+                }
+                match rest {
+                    Some(rest) if fields.is_empty() => write!(fmt, "| {}}}", rest),
+                    Some(rest) => write!(fmt, " | {}}}", rest),
+                    None => write!(fmt, "}}"),
+                }
+            }
+            SystemF::Select(record, label) => {
+                head(fmt, &record.node)?;
+                write!(fmt, ".{}", label)
+            }
+            SystemF::Restrict(record, label) => {
+                write!(fmt, "{{{} - {}}}", record, label)
+            }
+            SystemF::Variant(label, None, ty) => {
+                write!(fmt, "(`{} : {})", label, ty)
+            }
+            SystemF::Variant(label, Some(payload), ty) => match payload.node {
+                SystemF::Tuple(_) => write!(fmt, "(`{}{} : {})", label, payload, ty),
+                _ => write!(fmt, "(`{}({}) : {})", label, payload, ty),
+            },
+            SystemF::Match(scrutinee, arms) => {
+                write!(fmt, "match {} {{ ", scrutinee)?;
+                for (i, (pattern, body)) in arms.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, ", ")?;
+                    }
+                    write!(fmt, "{} -> {}", pattern, body)?;
+                }
+                write!(fmt, " }}")
+            }
+        }
+    }
+}
+
+fn app(f: Spanned<SystemF>, args: Spanned<Vec<Spanned<SystemF>>>) -> Spanned<SystemF> {
+    let span = f.span.merge(args.span);
+    Spanned::new(SystemF::App(Box::new(f), args.node), span)
+}
+
+// the operators are always parenthesized, so they need no precedence
+fn parenthesized() -> Parser<'static, Spanned<Token>, Spanned<SystemF>> {
+    let p_prefix = bind(operator(is_operator) << many_space(), |op| {
+        fmap(
+            move |arg: Spanned<SystemF>| {
+                let args = Spanned::new(vec![arg.clone()], arg.span);
+                app(Spanned::new(SystemF::Var(op.node.clone()), op.span), args)
+            },
+            system_f(),
+        )
+    });
+    let p_infix = bind(system_f(), |lhs| {
+        let _lhs = lhs.clone();
+        bind(spaced(operator(is_operator)), move |op| {
+            let lhs = _lhs.clone();
+            fmap(
+                move |rhs: Spanned<SystemF>| {
+                    let args =
+                        Spanned::new(vec![lhs.clone(), rhs.clone()], lhs.span.merge(rhs.span));
+                    app(Spanned::new(SystemF::Var(op.node.clone()), op.span), args)
+                },
+                system_f(),
+            )
+        }) | Parser::pure(lhs)
+    });
+    // a parenthesized list of two or more terms is a tuple
+    let p_items = fmap(
+        |mut items: Spanned<Vec<Spanned<SystemF>>>| match items.node.len() {
+            1 => items.node.remove(0),
+            _ => items.map(SystemF::Tuple),
+        },
+        spanned(comma_list1(spaced(p_infix))),
+    );
+    // the injection is annotated with the whole variant type
+    let p_payload = fmap(
+        |mut items: Spanned<Vec<Spanned<SystemF>>>| match items.node.len() {
+            1 => items.node.remove(0),
+            _ => items.map(SystemF::Tuple),
+        },
+        spanned(parens(comma_list1(spaced(system_f())))),
+    );
+    let p_variant = spanned(bind(token(Token::Backtick) >> name(), move |label| {
+        let _label = label.clone();
+        bind(
+            fmap(|payload| Some(Box::new(payload)), p_payload.clone()) | Parser::pure(None),
+            move |payload| {
+                let label = _label.clone();
+                fmap(
+                    move |ty| SystemF::Variant(label.clone(), payload.clone(), ty),
+                    spaced(token(Token::Colon)) >> forall(),
+                )
+            },
+        )
+    }));
+
+    parens(spaced(p_variant | p_prefix | p_items))
+}
+
+fn simple() -> Parser<'static, Spanned<Token>, Spanned<SystemF>> {
+    let p_lit = spanned(fmap(SystemF::Lit, literal()));
+    // an operator in parentheses is the function it stands for
+    let p_section = fmap(|op| op.node, parens(spaced(operator(is_operator))));
+    let p_var = spanned(fmap(SystemF::Var, name() | p_section));
+
+    fn args() -> Parser<'static, Spanned<Token>, Spanned<Vec<Spanned<SystemF>>>> {
+        spanned(parens(spaced(comma_list(spaced(system_f()))))).label("argument list")
+    }
+
+    fn ty_args() -> Parser<'static, Spanned<Token>, Spanned<Vec<Spanned<Ty>>>> {
+        spanned(brackets(spaced(comma_list1(spaced(forall()))))).label("type argument list")
+    }
+
+    // calls, type applications and field selections chain to the right of the head
+    let p_head = p_var | lazy!(parenthesized()) | spanned(lazy!(record()));
+    let p_postfix = bind(p_head, |head| {
+        Parser::new(move |s| {
+            let mut term = head.clone();
+            loop {
+                if let Some(args) = args().run(s) {
+                    term = app(term, args);
+                } else if let Some(types) = ty_args().run(s) {
+                    let span = term.span.merge(types.span);
+                    term = Spanned::new(SystemF::TyApp(Box::new(term), types.node), span);
+                } else if let Some(label) = (token(Token::Dot).hidden() >> spanned(name())).run(s) {
+                    let span = term.span.merge(label.span);
+                    term = Spanned::new(SystemF::Select(Box::new(term), label.node), span);
+                } else {
+                    return Some(term);
+                }
+            }
+        })
+    });
+
+    p_lit | p_postfix
+}
+
+// `{label = term, ... | record}` extends the record, `{record - label}` removes the label
+fn record() -> Parser<'static, Spanned<Token>, SystemF> {
+    let p_field = bind(spaced(name()) << token(Token::Equals), |label| {
+        fmap(move |field| (label.clone(), field), spaced(system_f()))
+    });
+    let p_extend = bind(many_space() >> comma_list(p_field), |fields| {
+        let _fields = fields.clone();
+        fmap(
+            move |rest| SystemF::Record(fields.clone(), Some(Box::new(rest))),
+            token(Token::Pipe) >> spaced(system_f()),
+        ) | Parser::pure(SystemF::Record(_fields, None))
+    });
+    let p_restrict = bind(spaced(system_f()) << token(Token::Minus), |record| {
+        fmap(
+            move |label| SystemF::Restrict(Box::new(record.clone()), label),
+            spaced(name()),
+        )
+    });
+
+    braces(p_extend) | braces(p_restrict)
+}
+
+// every binder is annotated, a single one without parentheses only with a simple type
+fn binders() -> Parser<'static, Spanned<Token>, Vec<(String, Spanned<Ty>)>> {
+    let p_binder = bind(spaced(name()) << token(Token::Colon), |arg| {
+        fmap(move |ty| (arg.clone(), ty), spaced(forall()))
+    });
+    let p_single = bind(name() << spaced(token(Token::Colon)), |arg| {
+        fmap(move |ty| vec![(arg.clone(), ty)], simple_ty())
+    });
+
+    parens(comma_list1(p_binder)) | p_single
+}
+
+pub(crate) fn system_f() -> Parser<'static, Spanned<Token>, Spanned<SystemF>> {
+    let p_let = bind(
+        token(Token::Let) >> spaced(name()) << token(Token::Equals),
+        |name| {
+            bind(spaced(system_f()) << token(Token::In), move |assign| {
+                let name = name.clone();
+                fmap(
+                    move |body| {
+                        SystemF::Let(name.clone(), Box::new(assign.clone()), Box::new(body))
+                    },
+                    many_space() >> system_f(),
+                )
+            })
+        },
+    );
+    let p_binding = bind(spaced(name()) << token(Token::Colon), |name| {
+        bind(spaced(forall()) << token(Token::Equals), move |ty| {
+            let name = name.clone();
+            fmap(
+                move |assign| (name.clone(), ty.clone(), assign),
+                spaced(system_f()),
+            )
+        })
+    });
+    let p_let_rec = bind(
+        token(Token::Let) >> spaced(token(Token::Rec)) >> p_binding.sep_by1(token(Token::And))
+            << token(Token::In),
+        |bindings| {
+            fmap(
+                move |body| SystemF::LetRec(bindings.clone(), Box::new(body)),
+                many_space() >> system_f(),
+            )
+        },
+    );
+    let p_abs = bind(
+        token(Token::Backslash) >> spaced(binders()) << token(Token::Arrow),
+        |args| {
+            fmap(
+                move |body| SystemF::Abs(args.clone(), Box::new(body)),
+                many_space() >> system_f(),
+            )
+        },
+    );
+    let p_ty_abs = bind(
+        token(Token::BigLambda) >> spaced(ident().sep_by1(many_space())) << token(Token::Dot),
+        |params| {
+            fmap(
+                move |body| SystemF::TyAbs(params.clone(), Box::new(body)),
+                many_space() >> system_f(),
+            )
+        },
+    );
+    let p_if = bind(
+        token(Token::If) >> spaced(lazy!(system_f())) << token(Token::Then),
+        |cond| {
+            bind(spaced(system_f()) << token(Token::Else), move |then| {
+                let cond = cond.clone();
+                fmap(
+                    move |otherwise| {
+                        SystemF::If(
+                            Box::new(cond.clone()),
+                            Box::new(then.clone()),
+                            Box::new(otherwise),
+                        )
+                    },
+                    many_space() >> system_f(),
+                )
+            })
+        },
+    );
+    let p_match = bind(
+        token(Token::Match) >> spaced(lazy!(system_f())),
+        |scrutinee| {
+            let p_arm = bind(spaced(pattern()) << token(Token::Arrow), |pattern| {
+                fmap(move |body| (pattern.clone(), body), spaced(system_f()))
+            });
+            fmap(
+                move |arms| SystemF::Match(Box::new(scrutinee.clone()), arms),
+                braces(comma_list1(p_arm)),
+            )
+        },
+    );
+
+    (spanned(p_let_rec | p_let | p_abs | p_ty_abs | p_if | p_match) | lazy!(simple())).label("term")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SystemF, SystemF::*};
+    use crate::{Literal, Ty};
+
+    use std::str::FromStr;
+
+    fn collect(s: &str) -> Option<SystemF> {
+        SystemF::from_str(s).ok()
+    }
+
+    fn ty(s: &str) -> Ty {
+        Ty::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(collect(""), None);
+        assert_eq!(collect(r"\x -> x"), None);
+        assert_eq!(collect(r"\x : int -> int -> x"), None);
+        assert_eq!(collect("Λ. x"), None);
+        assert_eq!(collect("Λa x"), None);
+        assert_eq!(collect("f[]"), None);
+        assert_eq!(collect("a + b"), None);
+        assert_eq!(collect("`A(1)"), None);
+        assert_eq!(collect("let rec f = x in f"), None);
+    }
+
+    #[test]
+    fn errors() {
+        let error = |s: &str| SystemF::from_str(s).unwrap_err().to_string();
+        assert_eq!(error(r"\(x) -> x"), "expected `:` at 1:4, found `)`");
+        assert_eq!(
+            error("Λa b -> x"),
+            "expected identifier or `.` at 1:6, found `->`"
+        );
+    }
+
+    #[test]
+    fn abstractions() {
+        assert_eq!(
+            collect(r"Λa. \x : a -> x"),
+            Some(TyAbs(
+                vec![String::from("a")],
+                Box::new(
+                    Abs(
+                        vec![(String::from("x"), ty("a").into())],
+                        Box::new(Var(String::from("x")).into())
+                    )
+                    .into()
+                )
+            ))
+        );
+        assert_eq!(
+            collect(r"\f : (int -> int) -> f(1)"),
+            Some(Abs(
+                vec![(String::from("f"), ty("int -> int").into())],
+                Box::new(
+                    App(
+                        Box::new(Var(String::from("f")).into()),
+                        vec![Lit(Literal::Int(1)).into()]
+                    )
+                    .into()
+                )
+            ))
+        );
+        assert_eq!(
+            collect(r"\( f : forall[a] a -> a , x : int ) -> f[int](x)")
+                .unwrap()
+                .to_string(),
+            r"\(f : forall[a] a -> a, x : int) -> f[int](x)"
+        );
+        assert_eq!(
+            collect(r"(Λa b. \(x : a, y : b) -> x)[int, forall[c] c]")
+                .unwrap()
+                .to_string(),
+            r"(Λa b. \(x : a, y : b) -> x)[int, forall[c] c]"
+        );
+    }
+
+    #[test]
+    fn roundtrip() {
+        for term in [
+            r"let id = Λa. \(x : a) -> x in id[forall[a] a -> a](id)",
+            r"let rec f : forall[a] a -> int = Λa. \(x : a) -> (1 + g(x)) and g : int -> int = \(x : int) -> f[int](x) in f",
+            r"if (not b) then -1 else ((1 + 2) * 3)",
+            r"(`Some(x, 1) : [Some : (int, int) | r])",
+            r"(`None : [None : {}])",
+            r"(`A(\(x : int) -> (x + 1)) : [A : int -> int])",
+            r"(`A(let x = 1 in x, (`B : [B : {}])) : [A : (int, [B : {}])])",
+            r"{x = 1, y = r.x | {r - x}}",
+            r"match xs { nil -> 0, cons(_, t) -> (1 + len[a](t)), `A(x) -> x }",
+            r"(! r)",
+            r"((a := 1), (==)[int](d)(1, 2))",
+            r"(let r = {x = 1} in r).x",
+            r"f[int](x).y",
+        ] {
+            assert_eq!(
+                collect(term).map(|term| term.to_string()),
+                Some(String::from(term))
+            );
+        }
+    }
+}
@@ -30,13 +30,15 @@ fn number(s: &mut Stream<char>) -> String {
         match c {
             '0'..='9' => {
                 s.undo(1);
-                num = s.take(|x| matches!(x, '0'..='9')).into_iter().collect();
+                num = s.take(|x| x.is_ascii_digit()).into_iter().collect();
 
+                // the fraction and the exponent are only taken with their digits
                 match s.next() {
                     Some('.') => {
-                        let fraction: String =
-                            s.take(|x| matches!(x, '0'..='9')).into_iter().collect();
-                        if !fraction.is_empty() {
+                        let fraction: String = s.take(|x| x.is_ascii_digit()).into_iter().collect();
+                        if fraction.is_empty() {
+                            s.undo(1);
+                        } else {
                             num.push('.');
                             num.push_str(fraction.as_str());
                         }
@@ -47,10 +49,20 @@ fn number(s: &mut Stream<char>) -> String {
 
                 match s.next() {
                     Some('e') => {
-                        let exponent: String =
-                            s.take(|x| matches!(x, '0'..='9')).into_iter().collect();
-                        if !exponent.is_empty() {
+                        let sign = match s.next() {
+                            Some(sign @ ('+' | '-')) => Some(sign),
+                            None => None,
+                            Some(_) => {
+                                s.undo(1);
+                                None
+                            }
+                        };
+                        let exponent: String = s.take(|x| x.is_ascii_digit()).into_iter().collect();
+                        if exponent.is_empty() {
+                            s.undo(1 + sign.map_or(0, |_| 1));
+                        } else {
                             num.push('e');
+                            num.extend(sign);
                             num.push_str(exponent.as_str());
                         }
                     }
@@ -64,6 +76,26 @@ fn number(s: &mut Stream<char>) -> String {
     num
 }
 
+// the opening quote is already consumed, None on a missing closing quote,
+// a bad escape ends the string and is returned instead
+fn string(s: &mut Stream<char>) -> Option<Result<String, char>> {
+    let mut string = String::new();
+    loop {
+        match s.next()? {
+            '"' => return Some(Ok(string)),
+            '\\' => string.push(match s.next()? {
+                'n' => '\n',
+                't' => '\t',
+                'r' => '\r',
+                '0' => '\0',
+                c @ ('\\' | '"') => c,
+                c => return Some(Err(c)),
+            }),
+            c => string.push(c),
+        }
+    }
+}
+
 #[derive(PartialEq, Clone, Debug)]
 pub enum Token {
     Comma,
@@ -77,9 +109,12 @@ pub enum Token {
     Lbrace,
     Rbrace,
     Plus,
+    Minus,
     Star,
     Slash,
     Percent,
+    Pipe,
+    Backtick,
     Equals,
     GT,
     LT,
@@ -87,13 +122,22 @@ pub enum Token {
     LE,
     EQ,
     NE,
+    Bang,
+    Assign,
     Spacing,
     Newline,
     Arrow,
+    FatArrow,
     Backslash,
+    BigLambda,
     Forall,
     In,
     Let,
+    Rec,
+    Type,
+    Class,
+    Instance,
+    Match,
     If,
     Then,
     Else,
@@ -104,14 +148,97 @@ pub enum Token {
     False,
     Ident(String),
     Number(String),
+    Str(String),
     Comment(String),
+    Error(char),
+    // a string ended by an unknown escape
+    Escape(char),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            Token::Comma => write!(fmt, ","),
+            Token::Colon => write!(fmt, ":"),
+            Token::Semicolon => write!(fmt, ";"),
+            Token::Dot => write!(fmt, "."),
+            Token::Lparen => write!(fmt, "("),
+            Token::Rparen => write!(fmt, ")"),
+            Token::Lbracket => write!(fmt, "["),
+            Token::Rbracket => write!(fmt, "]"),
+            Token::Lbrace => write!(fmt, "{{"),
+            Token::Rbrace => write!(fmt, "}}"),
+            Token::Plus => write!(fmt, "+"),
+            Token::Minus => write!(fmt, "-"),
+            Token::Star => write!(fmt, "*"),
+            Token::Slash => write!(fmt, "/"),
+            Token::Percent => write!(fmt, "%"),
+            Token::Pipe => write!(fmt, "|"),
+            Token::Backtick => write!(fmt, "`"),
+            Token::Equals => write!(fmt, "="),
+            Token::GT => write!(fmt, ">"),
+            Token::LT => write!(fmt, "<"),
+            Token::GE => write!(fmt, ">="),
+            Token::LE => write!(fmt, "<="),
+            Token::EQ => write!(fmt, "=="),
+            Token::NE => write!(fmt, "!="),
+            Token::Bang => write!(fmt, "!"),
+            Token::Assign => write!(fmt, ":="),
+            Token::Spacing => write!(fmt, " "),
+            Token::Newline => writeln!(fmt),
+            Token::Arrow => write!(fmt, "->"),
+            Token::FatArrow => write!(fmt, "=>"),
+            Token::Backslash => write!(fmt, "\\"),
+            Token::BigLambda => write!(fmt, "Λ"),
+            Token::Forall => write!(fmt, "forall"),
+            Token::In => write!(fmt, "in"),
+            Token::Let => write!(fmt, "let"),
+            Token::Rec => write!(fmt, "rec"),
+            Token::Type => write!(fmt, "type"),
+            Token::Class => write!(fmt, "class"),
+            Token::Instance => write!(fmt, "instance"),
+            Token::Match => write!(fmt, "match"),
+            Token::If => write!(fmt, "if"),
+            Token::Then => write!(fmt, "then"),
+            Token::Else => write!(fmt, "else"),
+            Token::Not => write!(fmt, "not"),
+            Token::And => write!(fmt, "and"),
+            Token::Or => write!(fmt, "or"),
+            Token::True => write!(fmt, "true"),
+            Token::False => write!(fmt, "false"),
+            Token::Ident(ident) => write!(fmt, "{}", ident),
+            Token::Number(number) => write!(fmt, "{}", number),
+            Token::Str(string) => {
+                write!(fmt, "\"")?;
+                for c in string.chars() {
+                    match c {
+                        '\n' => write!(fmt, "\\n")?,
+                        '\t' => write!(fmt, "\\t")?,
+                        '\r' => write!(fmt, "\\r")?,
+                        '\0' => write!(fmt, "\\0")?,
+                        '\\' | '"' => write!(fmt, "\\{}", c)?,
+                        c => write!(fmt, "{}", c)?,
+                    }
+                }
+                write!(fmt, "\"")
+            }
+            Token::Comment(comment) => write!(fmt, "--{}", comment),
+            Token::Error(c) => write!(fmt, "{}", c),
+            Token::Escape(c) => write!(fmt, "\\{}", c),
+        }
+    }
 }
 
-fn keyword(s: &str) -> Option<Token> {
+pub(crate) fn keyword(s: &str) -> Option<Token> {
     match s {
         "forall" => Some(Token::Forall),
         "in" => Some(Token::In),
         "let" => Some(Token::Let),
+        "rec" => Some(Token::Rec),
+        "type" => Some(Token::Type),
+        "class" => Some(Token::Class),
+        "instance" => Some(Token::Instance),
+        "match" => Some(Token::Match),
         "if" => Some(Token::If),
         "then" => Some(Token::Then),
         "else" => Some(Token::Else),
@@ -129,7 +256,14 @@ impl Streamable<char> for Token {
     fn from(s: &mut Stream<char>) -> Option<Token> {
         s.next().and_then(|c| match c {
             ',' => Some(Token::Comma),
-            ':' => Some(Token::Colon),
+            ':' => match s.next() {
+                Some('=') => Some(Token::Assign),
+                Some(_) => {
+                    s.undo(1);
+                    Some(Token::Colon)
+                }
+                None => Some(Token::Colon),
+            },
             ';' => Some(Token::Semicolon),
             '.' => Some(Token::Dot),
             '(' => Some(Token::Lparen),
@@ -142,13 +276,17 @@ impl Streamable<char> for Token {
             '*' => Some(Token::Star),
             '/' => Some(Token::Slash),
             '%' => Some(Token::Percent),
+            '|' => Some(Token::Pipe),
+            '`' => Some(Token::Backtick),
             '\\' => Some(Token::Backslash),
+            'Λ' => Some(Token::BigLambda),
+            '"' => string(s).map(|string| string.map_or_else(Token::Escape, Token::Str)),
             c1 @ ('<' | '>' | '=' | '!') => {
                 let c1_token = match c1 {
                     '<' => Some(Token::LT),
                     '>' => Some(Token::GT),
                     '=' => Some(Token::Equals),
-                    _ => None,
+                    _ => Some(Token::Bang),
                 };
                 match s.next() {
                     None => c1_token,
@@ -159,6 +297,7 @@ impl Streamable<char> for Token {
                         '!' => Some(Token::NE),
                         _ => unreachable!("All the cases were handled"),
                     },
+                    Some('>') if c1 == '=' => Some(Token::FatArrow),
                     Some(_) => {
                         s.undo(1);
                         c1_token
@@ -174,27 +313,20 @@ impl Streamable<char> for Token {
                 Some(Token::Newline)
             }
             '-' => match s.next() {
-                None => {
-                    s.undo(1);
-                    None
-                }
+                None => Some(Token::Minus),
                 Some('>') => Some(Token::Arrow),
                 Some('-') => Some(Token::Comment(
                     s.take(|x| !matches!(x, '\n')).into_iter().collect(),
                 )),
-                Some('0'..='9') => {
-                    s.undo(1);
-                    Some(Token::Number("-".to_owned() + &number(s)))
-                }
                 Some(_) => {
-                    s.undo(2);
-                    None
+                    s.undo(1);
+                    Some(Token::Minus)
                 }
             },
             'a'..='z' | 'A'..='Z' | '_' => {
                 s.undo(1);
                 let ident = identifier(s);
-                keyword(ident.as_str()).or_else(|| Some(Token::Ident(ident)))
+                keyword(ident.as_str()).or(Some(Token::Ident(ident)))
             }
             '0'..='9' => {
                 s.undo(1);
@@ -206,15 +338,21 @@ impl Streamable<char> for Token {
             }
         })
     }
+
+    fn unexpected(c: char) -> Token {
+        Token::Error(c)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::{Token, Token::*};
-    use tokenstream::Tokenizer;
+    use tokenstream::{Position, Span, Spanned, Tokenizer};
 
     fn collect(s: &str) -> Vec<Token> {
-        Tokenizer::new(s.chars()).collect()
+        Tokenizer::new(s.chars())
+            .map(|t: Spanned<Token>| t.node)
+            .collect()
     }
 
     #[test]
@@ -225,6 +363,13 @@ mod tests {
         );
     }
 
+    #[test]
+    fn compound_operators() {
+        assert_eq!(collect("|<|>="), vec![Pipe, LT, Pipe, GE]);
+        assert_eq!(collect("=>>==>"), vec![FatArrow, GE, FatArrow]);
+        assert_eq!(collect(":=!:!!="), vec![Assign, Bang, Colon, Bang, NE]);
+    }
+
     #[test]
     fn symbols() {
         assert_eq!(
@@ -233,6 +378,20 @@ mod tests {
         );
     }
 
+    #[test]
+    fn backticks() {
+        assert_eq!(
+            collect("a`div`b"),
+            vec![
+                Ident("a".to_owned()),
+                Backtick,
+                Ident("div".to_owned()),
+                Backtick,
+                Ident("b".to_owned())
+            ]
+        );
+    }
+
     #[test]
     fn spacing() {
         assert_eq!(collect(" \t\r \r\t \n\n\n"), vec![Spacing, Newline]);
@@ -270,6 +429,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn declaration_keywords() {
+        assert_eq!(
+            collect("rec type match class instance"),
+            vec![Rec, Spacing, Type, Spacing, Match, Spacing, Class, Spacing, Instance]
+        );
+        assert_eq!(
+            collect("types matching"),
+            vec![
+                Ident("types".to_owned()),
+                Spacing,
+                Ident("matching".to_owned())
+            ]
+        );
+        assert_eq!(
+            collect(r"Λa.\"),
+            vec![BigLambda, Ident("a".to_owned()), Dot, Backslash]
+        );
+    }
+
     #[test]
     fn identifiers() {
         assert_eq!(
@@ -293,7 +472,8 @@ mod tests {
             vec![
                 Number("42".to_owned()),
                 Spacing,
-                Number("-0.42".to_owned()),
+                Minus,
+                Number("0.42".to_owned()),
                 Dot,
                 Number("42.0".to_owned())
             ]
@@ -301,12 +481,113 @@ mod tests {
         assert_eq!(
             collect("-42.0e12.-42.0.e2"),
             vec![
-                Number("-42.0e12".to_owned()),
+                Minus,
+                Number("42.0e12".to_owned()),
                 Dot,
-                Number("-42.0".to_owned()),
+                Minus,
+                Number("42.0".to_owned()),
                 Dot,
                 Ident("e2".to_owned())
             ]
+        );
+        // the fraction and the exponent are left alone without their digits
+        assert_eq!(collect("1."), vec![Number("1".to_owned()), Dot]);
+        assert_eq!(
+            collect("1e"),
+            vec![Number("1".to_owned()), Ident("e".to_owned())]
+        );
+        assert_eq!(
+            collect("1e+"),
+            vec![Number("1".to_owned()), Ident("e".to_owned()), Plus]
+        );
+        assert_eq!(
+            collect("1e+2 1e-2"),
+            vec![
+                Number("1e+2".to_owned()),
+                Spacing,
+                Number("1e-2".to_owned())
+            ]
+        );
+        // the sign is left to the parser, which tells it apart from a subtraction
+        assert_eq!(
+            collect("n-1"),
+            vec![Ident("n".to_owned()), Minus, Number("1".to_owned())]
         )
     }
+
+    #[test]
+    fn strings() {
+        assert_eq!(
+            collect(r#""" "a b" "\"\\\n\t""#),
+            vec![
+                Str("".to_owned()),
+                Spacing,
+                Str("a b".to_owned()),
+                Spacing,
+                Str("\"\\\n\t".to_owned())
+            ]
+        );
+        assert_eq!(collect(r#""ab"#), vec![Error('"'), Ident("ab".to_owned())]);
+        // the string ends at the bad escape
+        assert_eq!(collect(r#""a\q"#), vec![Escape('q')]);
+        assert_eq!(
+            Str("\"\\\n".to_owned()).to_string(),
+            r#""\"\\\n""#.to_owned()
+        );
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!(
+            collect(r#"true 4.2"\r\0"false"#),
+            vec![
+                True,
+                Spacing,
+                Number("4.2".to_owned()),
+                Str("\r\0".to_owned()),
+                False
+            ]
+        );
+        assert_eq!(Str("\r\0\t".to_owned()).to_string(), r#""\r\0\t""#);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            collect("a @-?-x$"),
+            vec![
+                Ident("a".to_owned()),
+                Spacing,
+                Error('@'),
+                Minus,
+                Error('?'),
+                Minus,
+                Ident("x".to_owned()),
+                Error('$')
+            ]
+        );
+        assert_eq!(collect("!-"), vec![Bang, Minus]);
+    }
+
+    #[test]
+    fn spans() {
+        let at = |offset, line, column| Position {
+            offset,
+            line,
+            column,
+        };
+        let spans: Vec<Span> = Tokenizer::new("let\n  x->".chars())
+            .map(|t: Spanned<Token>| t.span)
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                Span::new(at(0, 1, 1), at(3, 1, 4)),
+                Span::new(at(3, 1, 4), at(4, 2, 1)),
+                Span::new(at(4, 2, 1), at(6, 2, 3)),
+                Span::new(at(6, 2, 3), at(7, 2, 4)),
+                Span::new(at(7, 2, 4), at(9, 2, 6)),
+            ]
+        );
+    }
 }
@@ -0,0 +1,258 @@
+/*
+ * Copyright (c) 2021, Andrei Yaskovets
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+use crate::{Bindable, Id, Level};
+use std::cell::{Cell, RefCell};
+
+// the variable a class resolves to, at the lowest level of its members,
+// and the type the class is bound to
+#[derive(Clone)]
+struct Root<T> {
+    rank: usize,
+    var: T,
+    value: Option<T>,
+}
+
+// what a change overwrote, undone in reverse order
+#[derive(Clone)]
+enum Undo<T> {
+    Parent(Id, Id),
+    Root(Id, Option<Root<T>>),
+}
+
+// arena of the variables indexed by their ids, only the roots keep the data of their class,
+// the parents are compressed on every lookup
+#[derive(Clone)]
+pub struct UnionFind<T>
+where
+    T: From<(Id, Option<Level>)> + Bindable,
+{
+    parents: Vec<Cell<Id>>,
+    roots: Vec<Option<Root<T>>>,
+    // the changes since the oldest open snapshot, the compressions included
+    log: RefCell<Vec<Undo<T>>>,
+    snapshots: usize,
+}
+
+impl<T> UnionFind<T>
+where
+    T: From<(Id, Option<Level>)> + Bindable + Clone,
+{
+    pub fn new() -> Self {
+        UnionFind {
+            parents: Vec::new(),
+            roots: Vec::new(),
+            log: RefCell::new(Vec::new()),
+            snapshots: 0,
+        }
+    }
+
+    fn record(&self, undo: Undo<T>) {
+        if self.snapshots > 0 {
+            self.log.borrow_mut().push(undo);
+        }
+    }
+
+    fn replace_root(&mut self, id: Id, root: Option<Root<T>>) -> Option<Root<T>> {
+        let old = std::mem::replace(&mut self.roots[id], root);
+        if self.snapshots > 0 {
+            self.record(Undo::Root(id, old.clone()));
+        }
+        old
+    }
+
+    fn root_mut(&mut self, id: Id) -> &mut Root<T> {
+        if self.snapshots > 0 {
+            self.record(Undo::Root(id, self.roots[id].clone()));
+        }
+        self.roots[id].as_mut().unwrap()
+    }
+
+    // the size of the arena and the length of the log to go back to
+    pub fn snapshot(&mut self) -> (usize, usize) {
+        self.snapshots += 1;
+        (self.parents.len(), self.log.borrow().len())
+    }
+
+    // the changes since the snapshot are kept, they are forgotten with the last open one
+    pub fn commit(&mut self, _: (usize, usize)) {
+        self.snapshots -= 1;
+        if self.snapshots == 0 {
+            self.log.get_mut().clear();
+        }
+    }
+
+    pub fn rollback(&mut self, (len, mark): (usize, usize)) {
+        let undone = self.log.get_mut().split_off(mark);
+        for undo in undone.into_iter().rev() {
+            match undo {
+                Undo::Parent(id, parent) => self.parents[id].set(parent),
+                Undo::Root(id, root) => self.roots[id] = root,
+            }
+        }
+        self.parents.truncate(len);
+        self.roots.truncate(len);
+        self.snapshots -= 1;
+    }
+
+    // the variables are added as singleton classes the first time they are bound
+    fn insert(&mut self, id: Id, level: Level) -> Id {
+        if id >= self.parents.len() {
+            self.parents
+                .extend((self.parents.len()..=id).map(Cell::new));
+            self.roots.resize_with(id + 1, || None);
+        }
+        if let Some(root) = self.find(id) {
+            return root;
+        }
+        self.replace_root(
+            id,
+            Some(Root {
+                rank: 0,
+                var: T::from((id, Some(level))),
+                value: None,
+            }),
+        );
+        id
+    }
+
+    // none for a variable that was never bound
+    fn find(&self, id: Id) -> Option<Id> {
+        let mut root = id;
+        loop {
+            let parent = self.parents.get(root)?.get();
+            if parent == root {
+                break;
+            }
+            root = parent;
+        }
+        self.roots[root].as_ref()?;
+
+        let mut id = id;
+        while id != root {
+            let parent = self.parents[id].replace(root);
+            if parent != root {
+                self.record(Undo::Parent(id, parent));
+            }
+            id = parent;
+        }
+        Some(root)
+    }
+
+    fn root(&self, id: Id) -> Option<&Root<T>> {
+        self.find(id).and_then(|root| self.roots[root].as_ref())
+    }
+
+    // the class of the first variable joins the one of the second, which it resolves to,
+    // at the lower of their levels
+    pub fn union(&mut self, (id1, level1): (Id, Level), (id2, level2): (Id, Level)) {
+        let root1 = self.insert(id1, level1);
+        let root2 = self.insert(id2, level2);
+        if root1 == root2 {
+            let root = self.roots[root1].as_ref().unwrap();
+            if let Some((id, level)) = Bindable::get_unbound_id_level(&root.var) {
+                if level2 < level {
+                    self.root_mut(root1).var = T::from((id, Some(level2)));
+                }
+            }
+            return;
+        }
+
+        let class1 = self.replace_root(root1, None).unwrap();
+        let class2 = self.replace_root(root2, None).unwrap();
+        let (id, level) = Bindable::get_unbound_id_level(&class2.var).unwrap();
+        let (_, level1) = Bindable::get_unbound_id_level(&class1.var).unwrap();
+        let (root, child) = if class1.rank > class2.rank {
+            (root1, root2)
+        } else {
+            (root2, root1)
+        };
+        self.record(Undo::Parent(child, child));
+        self.parents[child].set(root);
+        self.replace_root(
+            root,
+            Some(Root {
+                rank: class1.rank.max(class2.rank) + usize::from(class1.rank == class2.rank),
+                var: T::from((id, Some(level.min(level1)))),
+                value: class2.value.or(class1.value),
+            }),
+        );
+    }
+
+    // a variable binds its whole class, binding to another variable joins the classes
+    pub fn bind(&mut self, id: Id, level: Level, v: T) -> Option<T> {
+        if let Some(other) = Bindable::get_unbound_id_level(&v) {
+            self.union((id, level), other);
+            return None;
+        }
+        let root = self.insert(id, level);
+        self.root_mut(root).value.replace(v)
+    }
+
+    // the type of the class or its variable, if it is not the given one
+    pub fn lookup(&self, id: Id, level: Level) -> Option<&T> {
+        let root = self.root(id)?;
+        match &root.value {
+            Some(value) => Some(value),
+            None if Bindable::get_unbound_id_level(&root.var) == Some((id, level)) => None,
+            None => Some(&root.var),
+        }
+    }
+}
+
+impl<T> Default for UnionFind<T>
+where
+    T: From<(Id, Option<Level>)> + Bindable + Clone,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Type;
+
+    #[test]
+    fn union() {
+        let mut classes = UnionFind::new();
+        assert_eq!(classes.lookup(1, 2), None);
+
+        classes.bind(1, 2, Type::TypeVar(2, 3));
+        assert_eq!(classes.lookup(1, 2), Some(&Type::TypeVar(2, 2)));
+        assert_eq!(classes.lookup(2, 3), Some(&Type::TypeVar(2, 2)));
+        assert_eq!(classes.lookup(2, 2), None);
+
+        classes.bind(3, 1, Type::TypeVar(1, 2));
+        assert_eq!(classes.lookup(1, 2), Some(&Type::TypeVar(2, 1)));
+        assert_eq!(classes.lookup(3, 1), Some(&Type::TypeVar(2, 1)));
+
+        classes.bind(2, 2, Type::TypeVar(2, 0));
+        assert_eq!(classes.lookup(3, 1), Some(&Type::TypeVar(2, 0)));
+
+        classes.bind(2, 0, Type::Const(String::from("int")));
+        for id in 1..=3 {
+            assert_eq!(
+                classes.lookup(id, 0),
+                Some(&Type::Const(String::from("int")))
+            );
+        }
+        assert_eq!(classes.lookup(4, 0), None);
+    }
+
+    #[test]
+    fn balanced() {
+        let mut classes = UnionFind::new();
+        for id in 1..100 {
+            classes.bind(id, 0, Type::TypeVar(id + 1, 0));
+        }
+        assert_eq!(classes.lookup(1, 0), Some(&Type::TypeVar(100, 0)));
+        let root = classes.find(1).unwrap();
+        assert!((1..=100).all(|id| classes.parents[id].get() == root));
+    }
+}
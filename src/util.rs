@@ -5,16 +5,22 @@
  * LICENSE file in the root directory of this source tree.
  */
 
-use crate::Token;
-use tokenstream::{fmap, many, satisfy, some, Parser};
+use crate::{ParseError, Token};
+use tokenstream::{fmap, many, satisfy, some, Locatable, Parser, Spanned, Stream, Tokenizer};
 
 macro_rules! extract {
     ($pattern:pat => $extracted_value:expr) => {
-        Parser::new(|s| {
-            s.next().and_then(|t| match t {
-                $pattern => Some($extracted_value),
-                _ => None,
-            })
+        Parser::new(|s| match s.next() {
+            Some(Spanned { node: $pattern, .. }) => Some($extracted_value),
+            Some(t) => {
+                s.undo(1);
+                s.fail(Vec::new(), Some(t));
+                None
+            }
+            None => {
+                s.fail(Vec::new(), None);
+                None
+            }
         })
     };
 }
@@ -26,70 +32,112 @@ macro_rules! lazy {
     };
 }
 
-pub fn token(t: Token) -> Parser<'static, Token, Token> {
-    satisfy(move |x| x == t)
+pub fn parse<To>(p: Parser<'static, Spanned<Token>, To>, s: &str) -> Result<To, ParseError>
+where
+    To: Clone,
+{
+    let tokens: Vec<Spanned<Token>> = Tokenizer::new(s.chars()).collect();
+    for t in &tokens {
+        match t.node {
+            Token::Error(found) => {
+                return Err(ParseError::Lexical {
+                    position: t.span.start,
+                    found,
+                })
+            }
+            // the escape is the last two characters of the string
+            Token::Escape(found) => {
+                let string = &s[t.span.start.offset..t.span.end.offset];
+                let mut position = t.span.start;
+                for c in string.chars().take(string.chars().count() - 2) {
+                    c.advance(&mut position);
+                }
+                return Err(ParseError::Escape { position, found });
+            }
+            _ => (),
+        }
+    }
+
+    let end = tokens.last().map(|t| t.span.end).unwrap_or_default();
+    p.parse(&mut Stream::new(tokens.clone().into_iter()))
+        .map_err(|error| {
+            // the failure is reported at the next token that is not whitespace
+            let found = error.found.and_then(|found| {
+                tokens
+                    .into_iter()
+                    .skip_while(|t| t.span.start < found.span.start)
+                    .find(|t| !matches!(t.node, Token::Spacing | Token::Newline))
+            });
+            ParseError::Syntax {
+                position: found.as_ref().map(|t| t.span.start).unwrap_or(end),
+                expected: error.expected,
+                found: found.map(|t| t.node),
+            }
+        })
+}
+
+pub fn token(t: Token) -> Parser<'static, Spanned<Token>, Token> {
+    let label = format!("`{}`", t);
+    fmap(|x| x.node, satisfy(move |x: Spanned<Token>| x.node == t)).label(&label)
 }
-pub fn ident() -> Parser<'static, Token, String> {
-    extract!(Token::Ident(ident) => ident)
+pub fn ident() -> Parser<'static, Spanned<Token>, String> {
+    extract!(Token::Ident(ident) => ident).label("identifier")
 }
-pub fn number() -> Parser<'static, Token, String> {
-    extract!(Token::Number(number) => number)
+pub fn number() -> Parser<'static, Spanned<Token>, String> {
+    extract!(Token::Number(number) => number).label("number")
 }
-pub fn comment() -> Parser<'static, Token, String> {
+pub fn string() -> Parser<'static, Spanned<Token>, String> {
+    extract!(Token::Str(string) => string).label("string")
+}
+pub fn comment() -> Parser<'static, Spanned<Token>, String> {
     fmap(
         |c| c[..2].to_owned(),
         extract!(Token::Comment(comment) => comment),
     )
 }
-pub fn many_space() -> Parser<'static, Token, ()> {
-    fmap(|_| {}, many(token(Token::Spacing) | token(Token::Newline)))
+pub fn many_space() -> Parser<'static, Spanned<Token>, ()> {
+    fmap(|_| {}, many(token(Token::Spacing) | token(Token::Newline))).hidden()
 }
-pub fn some_space() -> Parser<'static, Token, ()> {
-    fmap(|_| {}, some(token(Token::Spacing) | token(Token::Newline)))
+pub fn some_space() -> Parser<'static, Spanned<Token>, ()> {
+    fmap(|_| {}, some(token(Token::Spacing) | token(Token::Newline))).label("whitespace")
 }
-pub fn eof() -> Parser<'static, Token, ()> {
-    Parser::new(|s| {
-        if let Some(_) = s.next() {
-            None
-        } else {
-            Some(())
-        }
-    })
+pub fn eof() -> Parser<'static, Spanned<Token>, ()> {
+    tokenstream::eof()
 }
 
-pub fn spaced<'a, To: 'a>(p: Parser<'a, Token, To>) -> Parser<'a, Token, To>
+pub fn spaced<'a, To>(p: Parser<'a, Spanned<Token>, To>) -> Parser<'a, Spanned<Token>, To>
 where
-    To: Clone,
+    To: Clone + 'a,
 {
     many_space() >> p << many_space()
 }
-pub fn parens<'a, To: 'a>(p: Parser<'a, Token, To>) -> Parser<'a, Token, To>
+pub fn parens<'a, To>(p: Parser<'a, Spanned<Token>, To>) -> Parser<'a, Spanned<Token>, To>
 where
-    To: Clone,
+    To: Clone + 'a,
 {
     p.between(token(Token::Lparen), token(Token::Rparen))
 }
-pub fn braces<'a, To: 'a>(p: Parser<'a, Token, To>) -> Parser<'a, Token, To>
+pub fn braces<'a, To>(p: Parser<'a, Spanned<Token>, To>) -> Parser<'a, Spanned<Token>, To>
 where
-    To: Clone,
+    To: Clone + 'a,
 {
     p.between(token(Token::Lbrace), token(Token::Rbrace))
 }
-pub fn brackets<'a, To: 'a>(p: Parser<'a, Token, To>) -> Parser<'a, Token, To>
+pub fn brackets<'a, To>(p: Parser<'a, Spanned<Token>, To>) -> Parser<'a, Spanned<Token>, To>
 where
-    To: Clone,
+    To: Clone + 'a,
 {
     p.between(token(Token::Lbracket), token(Token::Rbracket))
 }
-pub fn comma_list<'a, To: 'a>(p: Parser<'a, Token, To>) -> Parser<'a, Token, Vec<To>>
+pub fn comma_list<'a, To>(p: Parser<'a, Spanned<Token>, To>) -> Parser<'a, Spanned<Token>, Vec<To>>
 where
-    To: Clone,
+    To: Clone + 'a,
 {
     p.sep_by(token(Token::Comma))
 }
-pub fn comma_list1<'a, To: 'a>(p: Parser<'a, Token, To>) -> Parser<'a, Token, Vec<To>>
+pub fn comma_list1<'a, To>(p: Parser<'a, Spanned<Token>, To>) -> Parser<'a, Spanned<Token>, Vec<To>>
 where
-    To: Clone,
+    To: Clone + 'a,
 {
     p.sep_by1(token(Token::Comma))
 }
@@ -8,6 +8,9 @@
 mod parser;
 pub use parser::*;
 
+mod span;
+pub use span::*;
+
 mod stream;
 pub use stream::*;
 
@@ -7,6 +7,41 @@
 
 use crate::Stream;
 
+#[derive(PartialEq, Clone, Debug)]
+pub struct ParseError<T> {
+    pub position: usize,
+    pub expected: Vec<String>,
+    pub found: Option<T>,
+}
+
+impl<T> ParseError<T> {
+    pub fn new(position: usize, expected: Vec<String>, found: Option<T>) -> Self {
+        ParseError {
+            position,
+            expected,
+            found,
+        }
+    }
+
+    // the furthest failure wins, failures at the same position are combined
+    pub fn merge(mut self, other: Self) -> Self {
+        if other.position > self.position {
+            return other;
+        }
+        if other.position == self.position {
+            for expected in other.expected {
+                if !self.expected.contains(&expected) {
+                    self.expected.push(expected);
+                }
+            }
+            if self.found.is_none() {
+                self.found = other.found;
+            }
+        }
+        self
+    }
+}
+
 #[derive(Clone)]
 pub struct Parser<'a, From, To>
 where
@@ -39,6 +74,23 @@ where
             None
         })
     }
+
+    pub fn parse(&self, s: &mut Stream<From>) -> Result<To, ParseError<From>> {
+        s.replace_failure(None);
+        match self.run(s) {
+            Some(to) => {
+                s.replace_failure(None);
+                Ok(to)
+            }
+            None => {
+                let position = s.len();
+                let found = s.next();
+                Err(s
+                    .replace_failure(None)
+                    .unwrap_or_else(|| ParseError::new(position, Vec::new(), found)))
+            }
+        }
+    }
 }
 
 impl<'a, From, To: 'a> Parser<'a, From, To>
@@ -167,3 +219,78 @@ where
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ParseError, Parser};
+    use crate::{eof, many, satisfy, Stream};
+
+    fn char(c: char) -> Parser<'static, char, char> {
+        satisfy(move |x| x == c).label(&format!("'{}'", c))
+    }
+
+    fn parse<To: Clone>(p: &Parser<char, To>, s: &str) -> Result<To, ParseError<char>> {
+        p.parse(&mut Stream::new(s.chars()))
+    }
+
+    #[test]
+    fn furthest_failure() {
+        let p = ((char('a') >> char('b')) | char('c')) << eof();
+        assert_eq!(parse(&p, "ab"), Ok('b'));
+        assert_eq!(
+            parse(&p, "x"),
+            Err(ParseError::new(
+                0,
+                vec![String::from("'a'"), String::from("'c'")],
+                Some('x')
+            ))
+        );
+        assert_eq!(
+            parse(&p, "ax"),
+            Err(ParseError::new(1, vec![String::from("'b'")], Some('x')))
+        );
+        assert_eq!(
+            parse(&p, "a"),
+            Err(ParseError::new(1, vec![String::from("'b'")], None))
+        );
+        assert_eq!(
+            parse(&p, "ab!"),
+            Err(ParseError::new(
+                2,
+                vec![String::from("end of input")],
+                Some('!')
+            ))
+        );
+    }
+
+    #[test]
+    fn label() {
+        let p = (char('a') >> char('b')).label("ab");
+        assert_eq!(
+            parse(&p, "x"),
+            Err(ParseError::new(0, vec![String::from("ab")], Some('x')))
+        );
+        assert_eq!(
+            parse(&p, "ax"),
+            Err(ParseError::new(1, vec![String::from("'b'")], Some('x')))
+        );
+    }
+
+    #[test]
+    fn hidden() {
+        let p = many(char(' ')) >> char('a');
+        assert_eq!(
+            parse(&p, " b"),
+            Err(ParseError::new(
+                1,
+                vec![String::from("' '"), String::from("'a'")],
+                Some('b')
+            ))
+        );
+        let p = many(char(' ')).hidden() >> char('a');
+        assert_eq!(
+            parse(&p, " b"),
+            Err(ParseError::new(1, vec![String::from("'a'")], Some('b')))
+        );
+    }
+}
@@ -0,0 +1,241 @@
+/*
+ * Copyright (c) 2021, Andrei Yaskovets
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+use crate::{Parser, Stream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug)]
+pub struct Position {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    pub fn new() -> Self {
+        Position {
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Display for Position {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(fmt, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Default)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    pub fn new(start: Position, end: Position) -> Self {
+        Span { start, end }
+    }
+
+    pub fn merge(self, other: Span) -> Self {
+        Span {
+            start: self.start,
+            end: other.end,
+        }
+    }
+}
+
+impl std::fmt::Display for Span {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(fmt, "{}-{}", self.start, self.end)
+    }
+}
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+// spans are deliberately ignored by equality so that trees parsed from
+// different sources compare equal when their structure is the same
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span,
+    // tells apart the nodes with the same span, a clone is a different node
+    id: usize,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(node: T, span: Span) -> Self {
+        Spanned {
+            node,
+            span,
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Spanned<U> {
+        Spanned {
+            node: f(self.node),
+            span: self.span,
+            id: self.id,
+        }
+    }
+}
+
+impl<T: Clone> Clone for Spanned<T> {
+    fn clone(&self) -> Self {
+        Spanned::new(self.node.clone(), self.span)
+    }
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for Spanned<T> {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        fmt.debug_struct("Spanned")
+            .field("node", &self.node)
+            .field("span", &self.span)
+            .finish()
+    }
+}
+
+impl<T> From<T> for Spanned<T> {
+    fn from(node: T) -> Self {
+        Spanned::new(node, Span::default())
+    }
+}
+
+impl<T: PartialEq> PartialEq for Spanned<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.node == other.node
+    }
+}
+
+impl<T: std::fmt::Display> std::fmt::Display for Spanned<T> {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(fmt, "{}", self.node)
+    }
+}
+
+pub trait Locatable {
+    fn advance(&self, position: &mut Position);
+}
+
+impl Locatable for char {
+    fn advance(&self, position: &mut Position) {
+        position.offset += self.len_utf8();
+        if *self == '\n' {
+            position.line += 1;
+            position.column = 1;
+        } else {
+            position.column += 1;
+        }
+    }
+}
+
+// Span of everything consumed by the parser, from the start of the first
+// token to the end of the last one
+pub fn spanned<'a, From, To>(
+    p: Parser<'a, Spanned<From>, To>,
+) -> Parser<'a, Spanned<From>, Spanned<To>>
+where
+    From: Clone + 'a,
+    To: Clone + 'a,
+{
+    fn start<From: Clone>(s: &mut Stream<Spanned<From>>) -> Position {
+        match s.next() {
+            Some(t) => {
+                s.undo(1);
+                t.span.start
+            }
+            None => s.last_read().map(|t| t.span.end).unwrap_or_default(),
+        }
+    }
+
+    Parser::new(move |s| {
+        let start = start(s);
+        let consumed = s.len();
+        p.run(s).map(|node| {
+            let end = if s.len() > consumed {
+                s.last_read().map(|t| t.span.end).unwrap_or(start)
+            } else {
+                start
+            };
+            Spanned::new(node, Span::new(start, end))
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Position, Span, Spanned};
+    use crate::{Streamable, Tokenizer};
+
+    #[derive(PartialEq, Clone, Debug)]
+    struct Word(String);
+
+    impl Streamable<char> for Word {
+        fn from(s: &mut crate::Stream<char>) -> Option<Word> {
+            let word: String = s.take(|c| !c.is_whitespace()).into_iter().collect();
+            s.skip(|c| c.is_whitespace());
+            Some(Word(word))
+        }
+
+        fn unexpected(c: char) -> Word {
+            Word(c.to_string())
+        }
+    }
+
+    #[test]
+    fn tokenizer() {
+        let spans: Vec<Span> = Tokenizer::<char, Word>::new("ab\n cd".chars())
+            .map(|t: Spanned<Word>| t.span)
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                Span::new(
+                    Position::new(),
+                    Position {
+                        offset: 4,
+                        line: 2,
+                        column: 2
+                    }
+                ),
+                Span::new(
+                    Position {
+                        offset: 4,
+                        line: 2,
+                        column: 2
+                    },
+                    Position {
+                        offset: 6,
+                        line: 2,
+                        column: 4
+                    }
+                )
+            ]
+        );
+    }
+
+    #[test]
+    fn ids() {
+        let word = Spanned::from(Word("ab".to_owned()));
+        let clone = word.clone();
+        assert_eq!(word, clone);
+        assert_ne!(word.id(), clone.id());
+        let id = word.id();
+        assert_eq!(word.map(|Word(w)| w).id(), id);
+    }
+}
@@ -8,6 +8,8 @@
 use std::collections::VecDeque;
 use std::vec::Vec;
 
+use crate::ParseError;
+
 pub struct Stream<'a, T>
 where
     T: Clone,
@@ -15,6 +17,7 @@ where
     iter: Box<dyn Iterator<Item = T> + 'a>,
     undo: VecDeque<T>,
     cursor: usize,
+    failure: Option<ParseError<T>>,
 }
 
 impl<'a, T> Stream<'a, T>
@@ -26,6 +29,7 @@ where
             iter: Box::new(iter),
             undo: VecDeque::new(),
             cursor: 0,
+            failure: None,
         }
     }
 
@@ -33,6 +37,10 @@ where
         self.undo.len() - self.cursor
     }
 
+    pub fn last_read(&self) -> Option<&T> {
+        self.undo.get(self.cursor)
+    }
+
     pub fn undo(&mut self, times: usize) {
         self.cursor = std::cmp::min(self.cursor + times, self.undo.len());
     }
@@ -42,6 +50,22 @@ where
         self.cursor = self.undo.len();
     }
 
+    // remembers the furthest position at which a parser has failed
+    pub fn fail(&mut self, expected: Vec<String>, found: Option<T>) {
+        self.record(ParseError::new(self.len(), expected, found));
+    }
+
+    pub fn record(&mut self, failure: ParseError<T>) {
+        self.failure = Some(match self.failure.take() {
+            Some(previous) => previous.merge(failure),
+            None => failure,
+        });
+    }
+
+    pub fn replace_failure(&mut self, failure: Option<ParseError<T>>) -> Option<ParseError<T>> {
+        std::mem::replace(&mut self.failure, failure)
+    }
+
     pub fn wrap(&mut self) -> Vec<T> {
         let read = self.undo.drain(self.cursor..).rev().collect();
         self.commit();
@@ -86,6 +110,19 @@ mod tests {
         assert_eq!(s.wrap(), vec!['a', 'b', 'c']);
     }
 
+    #[test]
+    fn last_read() {
+        let mut s = Stream::new("abc".chars());
+        assert_eq!(s.last_read(), None);
+        assert_eq!(s.next(), Some('a'));
+        assert_eq!(s.next(), Some('b'));
+        assert_eq!(s.last_read().copied(), Some('b'));
+        s.undo(1);
+        assert_eq!(s.last_read().copied(), Some('a'));
+        s.undo(1);
+        assert_eq!(s.last_read(), None);
+    }
+
     #[test]
     fn commit() {
         let mut s = Stream::new("abcd".chars());
@@ -5,27 +5,31 @@
  * LICENSE file in the root directory of this source tree.
  */
 
-use crate::Stream;
+use crate::{Locatable, Position, Span, Spanned, Stream};
 
 pub trait Streamable<T>: Sized + std::fmt::Debug
 where
     T: Clone,
 {
     fn from(s: &mut Stream<T>) -> Option<Self>;
+
+    // token for input that does not start any valid token
+    fn unexpected(t: T) -> Self;
 }
 
 pub struct Tokenizer<'a, From, To>
 where
-    From: Clone + std::fmt::Debug,
+    From: Clone + std::fmt::Debug + Locatable,
     To: Streamable<From>,
 {
     from_stream: Stream<'a, From>,
+    position: Position,
     to_t: std::marker::PhantomData<To>,
 }
 
 impl<'a, From, To> Tokenizer<'a, From, To>
 where
-    From: Clone + std::fmt::Debug,
+    From: Clone + std::fmt::Debug + Locatable,
     To: Streamable<From>,
 {
     pub fn new<I>(tokens: I) -> Self
@@ -34,6 +38,7 @@ where
     {
         Self {
             from_stream: Stream::new(tokens),
+            position: Position::new(),
             to_t: std::marker::PhantomData::<To>,
         }
     }
@@ -44,6 +49,7 @@ where
     {
         Tokenizer {
             from_stream: self.from_stream,
+            position: self.position,
             to_t: std::marker::PhantomData::<OtherTo>,
         }
     }
@@ -51,20 +57,60 @@ where
 
 impl<'a, From, To> Iterator for Tokenizer<'a, From, To>
 where
-    From: Clone + std::fmt::Debug,
+    From: Clone + std::fmt::Debug + Locatable,
     To: Streamable<From>,
 {
-    type Item = To;
+    type Item = Spanned<To>;
     fn next(&mut self) -> Option<Self::Item> {
-        self.from_stream.next().and_then(|t| {
+        self.from_stream.next().map(|t| {
             self.from_stream.undo(1);
-            match To::from(&mut self.from_stream) {
-                None => panic!("Unexpected token '{:?}'", t),
-                token => {
-                    self.from_stream.commit();
-                    token
-                }
+            let token = To::from(&mut self.from_stream).unwrap_or_else(|| {
+                self.from_stream.undo(self.from_stream.len());
+                self.from_stream.next();
+                To::unexpected(t)
+            });
+
+            let start = self.position;
+            for t in self.from_stream.wrap() {
+                t.advance(&mut self.position);
             }
+            Spanned::new(token, Span::new(start, self.position))
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Streamable, Tokenizer};
+    use crate::{Spanned, Stream};
+
+    #[derive(PartialEq, Clone, Debug)]
+    enum Digit {
+        Digit(u32),
+        Unexpected(char),
+    }
+
+    impl Streamable<char> for Digit {
+        fn from(s: &mut Stream<char>) -> Option<Digit> {
+            s.next().and_then(|c| c.to_digit(10)).map(Digit::Digit)
+        }
+
+        fn unexpected(c: char) -> Digit {
+            Digit::Unexpected(c)
+        }
+    }
+
+    #[test]
+    fn unexpected() {
+        let tokens: Vec<Spanned<Digit>> = Tokenizer::new("1x2".chars()).collect();
+        assert_eq!(
+            tokens
+                .iter()
+                .map(|t| t.node.clone())
+                .collect::<Vec<Digit>>(),
+            vec![Digit::Digit(1), Digit::Unexpected('x'), Digit::Digit(2)]
+        );
+        assert_eq!(tokens[1].span.start.column, 2);
+        assert_eq!(tokens[1].span.end.column, 3);
+    }
+}
@@ -53,16 +53,36 @@ pub fn satisfy<'a, From: 'a>(f: impl Fn(From) -> bool + 'a) -> Parser<'a, From,
 where
     From: Clone,
 {
-    Parser::new(move |s: &mut Stream<From>| {
-        s.next().and_then(|x| {
+    Parser::new(move |s: &mut Stream<From>| match s.next() {
+        Some(x) => {
             if f(x.clone()) {
                 Some(x)
             } else {
                 s.undo(1);
+                s.fail(Vec::new(), Some(x));
                 None
             }
-        })
+        }
+        None => {
+            s.fail(Vec::new(), None);
+            None
+        }
+    })
+}
+
+pub fn eof<'a, From: 'a>() -> Parser<'a, From, ()>
+where
+    From: Clone,
+{
+    Parser::new(|s: &mut Stream<From>| match s.next() {
+        Some(x) => {
+            s.undo(1);
+            s.fail(Vec::new(), Some(x));
+            None
+        }
+        None => Some(()),
     })
+    .label("end of input")
 }
 
 pub fn some<'a, From: 'a, To: 'a>(p: Parser<'a, From, To>) -> Parser<'a, From, Vec<To>>
@@ -118,6 +138,33 @@ where
         many(self << p)
     }
 
+    // names the parser in error messages if it fails without consuming input
+    pub fn label(self, name: &str) -> Parser<'a, From, To> {
+        let name = name.to_owned();
+        Parser::new(move |s| {
+            let saved = s.replace_failure(None);
+            let start = s.len();
+            let result = self.run(s);
+            if let Some(mut failure) = s.replace_failure(saved) {
+                if failure.position == start {
+                    failure.expected = vec![name.clone()];
+                }
+                s.record(failure);
+            }
+            result
+        })
+    }
+
+    // keeps the parser out of error messages entirely
+    pub fn hidden(self) -> Parser<'a, From, To> {
+        Parser::new(move |s| {
+            let saved = s.replace_failure(None);
+            let result = self.run(s);
+            s.replace_failure(saved);
+            result
+        })
+    }
+
     pub fn between<ToL: 'a, ToR: 'a>(
         self,
         l: Parser<'a, From, ToL>,